@@ -0,0 +1,83 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use tracing::warn;
+
+/// The catalog and schema that records beyond the cardinality limit are
+/// accounted to.
+pub const OVERFLOW_KEY: &str = "__overflow__";
+
+/// A cap on the number of distinct group keys a collector tracks.
+///
+/// Once the cap is reached, records of unseen keys are redirected to the
+/// [OVERFLOW_KEY] bucket, so memory stays bounded while the total usage is
+/// still preserved.
+#[derive(Debug, Default)]
+pub struct CardinalityLimit {
+    max_keys: Option<usize>,
+    overflowed: AtomicU64,
+}
+
+impl CardinalityLimit {
+    /// No limit, every key is tracked.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn new(max_keys: usize) -> Self {
+        Self {
+            max_keys: Some(max_keys),
+            overflowed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn max_keys(&self) -> Option<usize> {
+        self.max_keys
+    }
+
+    /// Decides whether a record may be accounted to its own key, given
+    /// whether the key is already tracked and how many keys are tracked.
+    ///
+    /// Returns false if the record must go to the overflow bucket.
+    pub fn admit(&self, known: bool, len: usize) -> bool {
+        let Some(max_keys) = self.max_keys else {
+            return true;
+        };
+        if known || len < max_keys {
+            return true;
+        }
+
+        if self.overflowed.fetch_add(1, Ordering::Relaxed) == 0 {
+            warn!(
+                "[meter]the number of group keys reaches the limit: {}, further keys go to {}",
+                max_keys, OVERFLOW_KEY
+            );
+        }
+        false
+    }
+
+    /// Whether `len` tracked keys leave no room for an unseen key. Unlike
+    /// [CardinalityLimit::admit], it does not count an overflowed record.
+    pub fn is_full(&self, len: usize) -> bool {
+        self.max_keys.is_some_and(|max_keys| len >= max_keys)
+    }
+
+    /// The number of records redirected to the overflow bucket.
+    pub fn overflowed_records(&self) -> u64 {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Little-endian binary encoding helpers shared by the write-ahead log and
+//! aggregate snapshots. Readers take from the front of a slice, advancing
+//! it, and return `None` if it is too short.
+
+pub(crate) fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+pub(crate) fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (head, rest) = buf.split_at(n);
+    *buf = rest;
+    Some(head)
+}
+
+pub(crate) fn get_u8(buf: &mut &[u8]) -> Option<u8> {
+    take(buf, 1).map(|b| b[0])
+}
+
+pub(crate) fn get_u16(buf: &mut &[u8]) -> Option<u16> {
+    take(buf, 2).map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub(crate) fn get_u32(buf: &mut &[u8]) -> Option<u32> {
+    take(buf, 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub(crate) fn get_u64(buf: &mut &[u8]) -> Option<u64> {
+    take(buf, 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub(crate) fn get_i32(buf: &mut &[u8]) -> Option<i32> {
+    take(buf, 4).map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub(crate) fn get_str(buf: &mut &[u8]) -> Option<String> {
+    let len = get_u32(buf)? as usize;
+    take(buf, len).and_then(|b| String::from_utf8(b.to_vec()).ok())
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::data::MeterKind;
 use crate::data::MeterRecord;
 
 /// Trait representing the methods required to collect read/write record.
@@ -23,3 +24,11 @@ pub trait Collect: Send + Sync {
     /// Notifies the method that an event about data query occurs.
     fn on_read(&self, record: MeterRecord);
 }
+
+/// Calls [Collect::on_read] or [Collect::on_write] depending on `kind`.
+pub(crate) fn dispatch(collector: &dyn Collect, kind: MeterKind, record: MeterRecord) {
+    match kind {
+        MeterKind::Read => collector.on_read(record),
+        MeterKind::Write => collector.on_write(record),
+    }
+}
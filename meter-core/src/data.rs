@@ -12,6 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::cardinality::OVERFLOW_KEY;
+use crate::id::RecordId;
+
 #[derive(Debug)]
 pub struct ReadItem {
     /// The CPU consumed by query SQL processes.
@@ -34,13 +41,22 @@ impl ReadItem {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct MeterRecord {
     pub catalog: String,
     pub schema: String,
     pub value: u64,
     pub source: u8,
+    /// The time the record was produced.
+    ///
+    /// Unit is millisecond since the Unix epoch.
+    pub timestamp_millis: u64,
+    /// Extra dimensions of the record, such as table, user or client address.
+    pub labels: BTreeMap<String, String>,
+    /// The unique ID of the record, assigned by the [Registry](crate::registry::Registry)
+    /// if not set, so retried deliveries can be deduplicated downstream.
+    pub id: Option<RecordId>,
 }
 
 impl MeterRecord {
@@ -50,6 +66,65 @@ impl MeterRecord {
             schema,
             value,
             source,
+            timestamp_millis: current_time_millis(),
+            labels: BTreeMap::new(),
+            id: None,
         }
     }
+
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_id(mut self, id: RecordId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Overrides the timestamp of the record, e.g. when replaying historical data.
+    pub fn with_timestamp_millis(mut self, timestamp_millis: u64) -> Self {
+        self.timestamp_millis = timestamp_millis;
+        self
+    }
+}
+
+/// Whether a record is about data query or data insertion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeterKind {
+    Read,
+    Write,
+}
+
+/// The SchemaId identifies a database.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Debug)]
+pub struct SchemaId {
+    pub catalog: String,
+    pub schema: String,
+}
+
+impl SchemaId {
+    pub fn new(catalog: impl Into<String>, schema: impl Into<String>) -> Self {
+        Self {
+            catalog: catalog.into(),
+            schema: schema.into(),
+        }
+    }
+
+    pub fn of(record: &MeterRecord) -> Self {
+        Self::new(record.catalog.clone(), record.schema.clone())
+    }
+
+    /// The bucket of records beyond the cardinality limit.
+    pub fn overflow() -> Self {
+        Self::new(OVERFLOW_KEY, OVERFLOW_KEY)
+    }
+}
+
+/// Returns the current wall-clock time in milliseconds since the Unix epoch.
+pub fn current_time_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
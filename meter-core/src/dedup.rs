@@ -0,0 +1,151 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tracing::debug;
+
+use crate::collect::Collect;
+use crate::data::MeterRecord;
+use crate::id::RecordId;
+
+/// Remembers the [RecordId]s seen within a bounded window to drop replayed or
+/// retried records.
+///
+/// IDs are kept while they are within `window` of the newest ID seen, and at
+/// most `capacity` of them. An ID older than what is remembered cannot be
+/// verified and is accepted.
+pub struct Deduplicator {
+    window_millis: u64,
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    seen: BTreeSet<RecordId>,
+    duplicates: u64,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration, capacity: usize) -> Self {
+        Self {
+            window_millis: window.as_millis() as u64,
+            capacity: capacity.max(1),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Returns true if `id` is seen for the first time.
+    pub fn check(&self, id: RecordId) -> bool {
+        let mut state = self.state.lock();
+
+        if !state.seen.insert(id) {
+            state.duplicates += 1;
+            debug!("[meter]drop duplicated record: {}", id);
+            return false;
+        }
+
+        // IDs sort by time, so the oldest are always first.
+        let newest = state.seen.last().map_or(0, |id| id.timestamp_millis());
+        let horizon = newest.saturating_sub(self.window_millis);
+        while let Some(oldest) = state.seen.first() {
+            if oldest.timestamp_millis() >= horizon && state.seen.len() <= self.capacity {
+                break;
+            }
+            state.seen.pop_first();
+        }
+
+        true
+    }
+
+    /// Removes the records seen before from `records`. Records without an ID
+    /// are kept.
+    pub fn retain_unique(&self, records: &mut Vec<MeterRecord>) {
+        records.retain(|record| record.id.is_none_or(|id| self.check(id)));
+    }
+
+    /// The number of duplicates dropped.
+    pub fn duplicates(&self) -> u64 {
+        self.state.lock().duplicates
+    }
+}
+
+/// A [Collect] stage that forwards each record to an inner [Collect] exactly
+/// once, judged by its [RecordId] within the window of a [Deduplicator].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::dedup::DedupCollector;
+/// use meter_core::dedup::Deduplicator;
+/// use meter_core::id::RecordId;
+/// use meter_core::window::WindowConfig;
+/// use meter_core::window::WindowedCollector;
+///
+/// let inner = Arc::new(WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(60))));
+/// let dedup = DedupCollector::new(Deduplicator::new(Duration::from_secs(600), 100_000), inner.clone());
+///
+/// let id = RecordId::new(1_000, 1, 1, 42);
+/// for _ in 0..3 {
+///     let record = MeterRecord::new("greptime".into(), "public".into(), 10, 0);
+///     dedup.on_write(record.with_timestamp_millis(1_000).with_id(id));
+/// }
+///
+/// assert_eq!(inner.flush()[0].usage.write_units, 10);
+/// assert_eq!(dedup.deduplicator().duplicates(), 2);
+/// ```
+pub struct DedupCollector {
+    deduplicator: Deduplicator,
+    inner: Arc<dyn Collect>,
+}
+
+impl DedupCollector {
+    pub fn new(deduplicator: Deduplicator, inner: Arc<dyn Collect>) -> Self {
+        Self {
+            deduplicator,
+            inner,
+        }
+    }
+
+    pub fn deduplicator(&self) -> &Deduplicator {
+        &self.deduplicator
+    }
+
+    fn is_unique(&self, record: &MeterRecord) -> bool {
+        record.id.is_none_or(|id| self.deduplicator.check(id))
+    }
+}
+
+impl Collect for DedupCollector {
+    fn on_write(&self, record: MeterRecord) {
+        if self.is_unique(&record) {
+            self.inner.on_write(record);
+        }
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        if self.is_unique(&record) {
+            self.inner.on_read(record);
+        }
+    }
+}
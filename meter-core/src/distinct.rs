@@ -0,0 +1,161 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+
+use crate::cardinality::CardinalityLimit;
+use crate::collect::Collect;
+use crate::data::MeterRecord;
+use crate::data::SchemaId;
+use crate::sketch::hll::merge_by_label;
+use crate::sketch::hll::HyperLogLog;
+use crate::sketch::hll::DEFAULT_PRECISION;
+use crate::sketch::MergeError;
+use crate::window::Usage;
+
+/// The usage sums and distinct-count sketches of one schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistinctSummary {
+    pub usage: Usage,
+    /// A sketch of the distinct values of each chosen label.
+    pub sketches: BTreeMap<String, HyperLogLog>,
+}
+
+impl DistinctSummary {
+    /// The approximate number of distinct values of each chosen label.
+    pub fn distinct_counts(&self) -> BTreeMap<String, u64> {
+        self.sketches
+            .iter()
+            .map(|(label, hll)| (label.clone(), hll.estimate()))
+            .collect()
+    }
+
+    /// Merges a partial summary, e.g. from another node, into this one.
+    /// Fails, leaving this one unchanged, if the sketches of a label have
+    /// different precisions.
+    pub fn merge(&mut self, other: &DistinctSummary) -> Result<(), MergeError> {
+        merge_by_label(&mut self.sketches, &other.sketches)?;
+        self.usage.merge(&other.usage);
+        Ok(())
+    }
+}
+
+/// A [Collect] that counts the distinct values of chosen record labels per
+/// schema with a [HyperLogLog], alongside the usage sums.
+///
+/// Summaries are accumulated until [DistinctCollector::drain], so draining
+/// every hour yields hourly distinct counts.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::data::SchemaId;
+/// use meter_core::distinct::DistinctCollector;
+///
+/// let collector = DistinctCollector::new(["table"]);
+/// for table in ["t1", "t2", "t1"] {
+///     let record = MeterRecord::new("greptime".into(), "public".into(), 10, 0);
+///     collector.on_read(record.with_label("table", table));
+/// }
+///
+/// let summaries = collector.drain();
+/// let summary = &summaries[&SchemaId::new("greptime", "public")];
+/// assert_eq!(summary.usage.read_units, 30);
+/// assert_eq!(summary.distinct_counts()["table"], 2);
+/// ```
+pub struct DistinctCollector {
+    labels: Vec<String>,
+    precision: u8,
+    limit: CardinalityLimit,
+    data: Mutex<HashMap<SchemaId, DistinctSummary>>,
+}
+
+impl DistinctCollector {
+    /// Counts the distinct values of `labels`.
+    pub fn new<I, S>(labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            labels: labels.into_iter().map(Into::into).collect(),
+            precision: DEFAULT_PRECISION,
+            limit: CardinalityLimit::unlimited(),
+            data: Mutex::default(),
+        }
+    }
+
+    /// Sets the precision of the sketches, see [HyperLogLog::new].
+    pub fn with_precision(mut self, precision: u8) -> Self {
+        HyperLogLog::validate_precision(precision);
+        self.precision = precision;
+        self
+    }
+
+    /// Caps the number of distinct schemas tracked.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.limit = CardinalityLimit::new(max_keys);
+        self
+    }
+
+    /// The number of records accounted to [SchemaId::overflow].
+    pub fn overflowed_records(&self) -> u64 {
+        self.limit.overflowed_records()
+    }
+
+    /// Takes the summaries accumulated so far.
+    pub fn drain(&self) -> HashMap<SchemaId, DistinctSummary> {
+        std::mem::take(&mut *self.data.lock())
+    }
+
+    fn record(&self, record: MeterRecord, add: impl Fn(&mut Usage, u64)) {
+        let mut data = self.data.lock();
+
+        let mut schema_id = SchemaId::of(&record);
+        if !self.limit.admit(data.contains_key(&schema_id), data.len()) {
+            schema_id = SchemaId::overflow();
+        }
+
+        let summary = data.entry(schema_id).or_insert_with(|| DistinctSummary {
+            usage: Usage::default(),
+            sketches: self
+                .labels
+                .iter()
+                .map(|label| (label.clone(), HyperLogLog::new(self.precision)))
+                .collect(),
+        });
+
+        add(&mut summary.usage, record.value);
+        for (label, hll) in summary.sketches.iter_mut() {
+            if let Some(value) = record.labels.get(label) {
+                hll.insert(value.as_bytes());
+            }
+        }
+    }
+}
+
+impl Collect for DistinctCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.record(record, Usage::add_write);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.record(record, Usage::add_read);
+    }
+}
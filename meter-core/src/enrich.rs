@@ -0,0 +1,160 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::data::MeterRecord;
+
+/// A hook run by the [Registry](crate::registry::Registry) on every record
+/// before it is dispatched to the collector.
+pub trait Enrich: Send + Sync {
+    fn enrich(&self, record: &mut MeterRecord);
+}
+
+impl<F> Enrich for F
+where
+    F: Fn(&mut MeterRecord) + Send + Sync,
+{
+    fn enrich(&self, record: &mut MeterRecord) {
+        self(record)
+    }
+}
+
+/// Adds fixed labels, such as node ID, cluster, region and build version from
+/// the config. Labels already set on the record are kept.
+#[derive(Debug, Clone, Default)]
+pub struct StaticLabels {
+    labels: BTreeMap<String, String>,
+}
+
+impl StaticLabels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl Enrich for StaticLabels {
+    fn enrich(&self, record: &mut MeterRecord) {
+        for (key, value) in &self.labels {
+            if !record.labels.contains_key(key) {
+                record.labels.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+thread_local! {
+    static CONTEXT_LABELS: RefCell<Vec<BTreeMap<String, String>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with `labels` added to every record metered on this thread
+/// meanwhile, when [ContextLabels] is registered. Scopes can be nested, the
+/// innermost label winning.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+/// use std::sync::Arc;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::enrich::scope;
+/// use meter_core::enrich::ContextLabels;
+/// use meter_core::enrich::StaticLabels;
+/// use meter_core::registry::Registry;
+///
+/// struct AssertRegion;
+///
+/// impl Collect for AssertRegion {
+///     fn on_write(&self, record: MeterRecord) {
+///         assert_eq!(record.labels["region"], "us-west-2");
+///         assert_eq!(record.labels["user"], "alice");
+///     }
+///
+///     fn on_read(&self, _: MeterRecord) {}
+/// }
+///
+/// let registry = Registry::default();
+/// registry.set_collector(Arc::new(AssertRegion));
+/// registry.add_enricher(Arc::new(StaticLabels::new().with_label("region", "us-west-2")));
+/// registry.add_enricher(Arc::new(ContextLabels));
+///
+/// let labels = BTreeMap::from([("user".to_string(), "alice".to_string())]);
+/// scope(labels, || {
+///     registry.record_write(MeterRecord::new("greptime".into(), "public".into(), 1, 0));
+/// });
+/// ```
+pub fn scope<R>(labels: BTreeMap<String, String>, f: impl FnOnce() -> R) -> R {
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CONTEXT_LABELS.with(|stack| stack.borrow_mut().pop());
+        }
+    }
+
+    CONTEXT_LABELS.with(|stack| stack.borrow_mut().push(labels));
+    let _guard = Guard;
+    f()
+}
+
+#[cfg(feature = "tokio")]
+tokio::task_local! {
+    static TASK_LABELS: BTreeMap<String, String>;
+}
+
+/// Runs `fut` with `labels` added to every record metered by the task
+/// meanwhile, when [ContextLabels] is registered. Unlike [scope], the labels
+/// follow the future across threads. Scopes can be nested, the innermost
+/// label winning.
+#[cfg(feature = "tokio")]
+pub async fn scope_async<F: std::future::Future>(
+    mut labels: BTreeMap<String, String>,
+    fut: F,
+) -> F::Output {
+    let _ = TASK_LABELS.try_with(|outer| {
+        for (key, value) in outer {
+            labels.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+    });
+    TASK_LABELS.scope(labels, fut).await
+}
+
+/// Adds the labels of the enclosing [scope]s (and task scopes with the
+/// `tokio` feature). Labels already set on the record are kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContextLabels;
+
+impl Enrich for ContextLabels {
+    fn enrich(&self, record: &mut MeterRecord) {
+        let mut add = |labels: &BTreeMap<String, String>| {
+            for (key, value) in labels {
+                if !record.labels.contains_key(key) {
+                    record.labels.insert(key.clone(), value.clone());
+                }
+            }
+        };
+
+        CONTEXT_LABELS.with(|stack| stack.borrow().iter().rev().for_each(&mut add));
+        #[cfg(feature = "tokio")]
+        let _ = TASK_LABELS.try_with(add);
+    }
+}
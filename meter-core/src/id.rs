@@ -0,0 +1,161 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use crate::data::current_time_millis;
+
+const TIMESTAMP_BITS: u32 = 48;
+const NODE_BITS: u32 = 16;
+const EPOCH_BITS: u32 = 16;
+const SEQUENCE_BITS: u32 = 48;
+
+/// A globally unique, time-ordered record ID.
+///
+/// Laid out from the most significant bits as
+/// `timestamp_millis (48) | node_id (16) | epoch (16) | sequence (48)`, so IDs
+/// sort by time first. The epoch tells apart restarts of the same node, whose
+/// sequence starts over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecordId(u128);
+
+impl RecordId {
+    pub fn new(timestamp_millis: u64, node_id: u16, epoch: u16, sequence: u64) -> Self {
+        let timestamp = (timestamp_millis & mask(TIMESTAMP_BITS)) as u128;
+        let sequence = (sequence & mask(SEQUENCE_BITS)) as u128;
+
+        Self(
+            timestamp << (NODE_BITS + EPOCH_BITS + SEQUENCE_BITS)
+                | (node_id as u128) << (EPOCH_BITS + SEQUENCE_BITS)
+                | (epoch as u128) << SEQUENCE_BITS
+                | sequence,
+        )
+    }
+
+    pub fn from_u128(id: u128) -> Self {
+        Self(id)
+    }
+
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    pub fn timestamp_millis(&self) -> u64 {
+        (self.0 >> (NODE_BITS + EPOCH_BITS + SEQUENCE_BITS)) as u64
+    }
+
+    pub fn node_id(&self) -> u16 {
+        (self.0 >> (EPOCH_BITS + SEQUENCE_BITS)) as u16
+    }
+
+    pub fn epoch(&self) -> u16 {
+        (self.0 >> SEQUENCE_BITS) as u16
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.0 as u64 & mask(SEQUENCE_BITS)
+    }
+}
+
+impl fmt::Display for RecordId {
+    /// Formats as 32 lowercase hex digits, which sort like the ID.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for RecordId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16).map(Self)
+    }
+}
+
+fn mask(bits: u32) -> u64 {
+    (1 << bits) - 1
+}
+
+/// Generates the [RecordId]s of one registry.
+#[derive(Debug)]
+pub struct RecordIdGenerator {
+    /// `node_id << 16 | epoch`.
+    origin: AtomicU32,
+    sequence: AtomicU64,
+}
+
+impl Default for RecordIdGenerator {
+    /// Node 0, with the epoch derived from the start time in minutes so that a
+    /// restarted process does not reuse the IDs of the previous one. The epoch
+    /// wraps about every 45 days; processes restarting within the same minute
+    /// should set an epoch of their own, e.g. a persisted restart counter.
+    fn default() -> Self {
+        Self::new(0, (current_time_millis() / 60_000) as u16)
+    }
+}
+
+impl RecordIdGenerator {
+    pub fn new(node_id: u16, epoch: u16) -> Self {
+        Self {
+            origin: AtomicU32::new(origin(node_id, epoch)),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    pub fn node_id(&self) -> u16 {
+        (self.origin.load(Ordering::Relaxed) >> EPOCH_BITS) as u16
+    }
+
+    pub fn epoch(&self) -> u16 {
+        self.origin.load(Ordering::Relaxed) as u16
+    }
+
+    pub fn set_node_id(&self, node_id: u16) {
+        self.update(|_, epoch| origin(node_id, epoch));
+    }
+
+    pub fn set_epoch(&self, epoch: u16) {
+        self.update(|node_id, _| origin(node_id, epoch));
+    }
+
+    /// The next ID, ordered by `timestamp_millis`.
+    pub fn next(&self, timestamp_millis: u64) -> RecordId {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        let origin = self.origin.load(Ordering::Relaxed);
+        RecordId::new(
+            timestamp_millis,
+            (origin >> EPOCH_BITS) as u16,
+            origin as u16,
+            sequence,
+        )
+    }
+
+    /// Replaces the origin atomically, so concurrent updates of the node ID
+    /// and the epoch do not overwrite each other.
+    fn update(&self, f: impl Fn(u16, u16) -> u32) {
+        let _ = self
+            .origin
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |origin| {
+                Some(f((origin >> EPOCH_BITS) as u16, origin as u16))
+            });
+    }
+}
+
+fn origin(node_id: u16, epoch: u16) -> u32 {
+    (node_id as u32) << EPOCH_BITS | epoch as u32
+}
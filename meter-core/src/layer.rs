@@ -0,0 +1,195 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::collect::dispatch;
+use crate::collect::Collect;
+use crate::data::MeterKind;
+use crate::data::MeterRecord;
+
+/// Wraps a [Collect] into another one, e.g. to filter, enrich or transform
+/// records before they reach the inner collector.
+pub trait Layer: Send + Sync {
+    fn layer(&self, inner: Arc<dyn Collect>) -> Arc<dyn Collect>;
+}
+
+/// A record-by-record pipeline stage, which can be unit-tested without any
+/// collector.
+pub trait Stage: Send + Sync + 'static {
+    /// Returns the record to pass on, or `None` to drop it.
+    fn process(&self, kind: MeterKind, record: MeterRecord) -> Option<MeterRecord>;
+}
+
+/// The [Layer] running a [Stage] in front of the inner collector.
+pub struct StageLayer<S> {
+    stage: Arc<S>,
+}
+
+impl<S: Stage> StageLayer<S> {
+    pub fn new(stage: S) -> Self {
+        Self {
+            stage: Arc::new(stage),
+        }
+    }
+}
+
+impl<S: Stage> Layer for StageLayer<S> {
+    fn layer(&self, inner: Arc<dyn Collect>) -> Arc<dyn Collect> {
+        Arc::new(StageCollector {
+            stage: self.stage.clone(),
+            inner,
+        })
+    }
+}
+
+struct StageCollector<S> {
+    stage: Arc<S>,
+    inner: Arc<dyn Collect>,
+}
+
+impl<S: Stage> StageCollector<S> {
+    fn process(&self, kind: MeterKind, record: MeterRecord) {
+        if let Some(record) = self.stage.process(kind, record) {
+            dispatch(self.inner.as_ref(), kind, record);
+        }
+    }
+}
+
+impl<S: Stage> Collect for StageCollector<S> {
+    fn on_write(&self, record: MeterRecord) {
+        self.process(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.process(MeterKind::Read, record);
+    }
+}
+
+/// A [Stage] that keeps only the records matching a predicate.
+pub struct Filter<F>(pub F);
+
+impl<F> Stage for Filter<F>
+where
+    F: Fn(MeterKind, &MeterRecord) -> bool + Send + Sync + 'static,
+{
+    fn process(&self, kind: MeterKind, record: MeterRecord) -> Option<MeterRecord> {
+        (self.0)(kind, &record).then_some(record)
+    }
+}
+
+/// A [Stage] that enriches or transforms every record.
+pub struct Map<F>(pub F);
+
+impl<F> Stage for Map<F>
+where
+    F: Fn(MeterKind, MeterRecord) -> MeterRecord + Send + Sync + 'static,
+{
+    fn process(&self, kind: MeterKind, record: MeterRecord) -> Option<MeterRecord> {
+        Some((self.0)(kind, record))
+    }
+}
+
+/// A [Stage] that keeps one record out of every `n` and scales its value by
+/// `n`, so sums are preserved in expectation.
+pub struct Sample {
+    n: u64,
+    seen: AtomicU64,
+}
+
+impl Sample {
+    pub fn one_in(n: u64) -> Self {
+        Self {
+            n: n.max(1),
+            seen: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Stage for Sample {
+    fn process(&self, _kind: MeterKind, mut record: MeterRecord) -> Option<MeterRecord> {
+        if self.seen.fetch_add(1, Ordering::Relaxed) % self.n != 0 {
+            return None;
+        }
+        record.value = record.value.saturating_mul(self.n);
+        Some(record)
+    }
+}
+
+/// Builds a collector pipeline declaratively, from the outermost layer to the
+/// innermost collector.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::layer::Filter;
+/// use meter_core::layer::Map;
+/// use meter_core::layer::PipelineBuilder;
+/// use meter_core::layer::Sample;
+/// use meter_core::window::WindowConfig;
+/// use meter_core::window::WindowedCollector;
+///
+/// let aggregate = Arc::new(WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(60))));
+///
+/// // filter -> enrich -> sample -> aggregate
+/// let pipeline = PipelineBuilder::new()
+///     .stage(Filter(|_, record: &MeterRecord| record.catalog != "greptime_private"))
+///     .stage(Map(|_, record: MeterRecord| record.with_label("region", "us-west-2")))
+///     .stage(Sample::one_in(2))
+///     .build(aggregate.clone());
+///
+/// for catalog in ["greptime_private", "greptime", "greptime"] {
+///     pipeline.on_write(MeterRecord::new(catalog.into(), "public".into(), 10, 0));
+/// }
+///
+/// let aggregates = aggregate.flush();
+/// assert_eq!(aggregates.len(), 1);
+/// assert_eq!(aggregates[0].usage.write_units, 20);
+/// ```
+#[derive(Default)]
+pub struct PipelineBuilder {
+    layers: Vec<Arc<dyn Layer>>,
+}
+
+impl PipelineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer inside the ones added before.
+    pub fn layer(mut self, layer: impl Layer + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    /// Adds a [Stage] inside the layers added before.
+    pub fn stage(self, stage: impl Stage) -> Self {
+        self.layer(StageLayer::new(stage))
+    }
+
+    /// Wraps `inner` in the layers, the first added being the outermost.
+    pub fn build(self, inner: Arc<dyn Collect>) -> Arc<dyn Collect> {
+        self.layers
+            .iter()
+            .rev()
+            .fold(inner, |inner, layer| layer.layer(inner))
+    }
+}
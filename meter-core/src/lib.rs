@@ -12,10 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod cardinality;
+mod codec;
 pub mod collect;
 pub mod data;
+pub mod dedup;
+pub mod distinct;
+pub mod enrich;
 pub mod global;
+pub mod id;
+pub mod layer;
+pub mod quantile;
+pub mod rate;
 pub mod registry;
+pub mod router;
+pub mod sketch;
+pub mod snapshot;
+pub mod topk;
+pub mod wal;
+pub mod window;
 
 pub trait ItemCalculator<T>: Send + Sync {
     fn calc(&self, value: &T) -> u64;
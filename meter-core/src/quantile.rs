@@ -0,0 +1,144 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+
+use crate::cardinality::CardinalityLimit;
+use crate::collect::Collect;
+use crate::data::MeterKind;
+use crate::data::MeterRecord;
+use crate::data::SchemaId;
+use crate::sketch::ddsketch::DDSketch;
+use crate::sketch::ddsketch::DEFAULT_MAX_BINS;
+use crate::sketch::ddsketch::DEFAULT_RELATIVE_ACCURACY;
+
+/// A [Collect] that keeps a [DDSketch] of per-record values for each schema,
+/// separately for reads and writes.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterKind;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::data::SchemaId;
+/// use meter_core::quantile::QuantileCollector;
+///
+/// let collector = QuantileCollector::default();
+/// for value in 1..=100 {
+///     collector.on_read(MeterRecord::new("greptime".into(), "public".into(), value, 0));
+/// }
+///
+/// let quantiles = collector.quantiles(MeterKind::Read, &[0.5, 0.99]);
+/// let public = &quantiles[&SchemaId::new("greptime", "public")];
+/// assert!((public[0] - 50.0).abs() <= 50.0 * 0.01);
+/// assert!((public[1] - 99.0).abs() <= 99.0 * 0.01);
+/// ```
+pub struct QuantileCollector {
+    relative_accuracy: f64,
+    max_bins: usize,
+    limit: CardinalityLimit,
+    reads: Mutex<HashMap<SchemaId, DDSketch>>,
+    writes: Mutex<HashMap<SchemaId, DDSketch>>,
+}
+
+impl Default for QuantileCollector {
+    fn default() -> Self {
+        Self::new(DEFAULT_RELATIVE_ACCURACY, DEFAULT_MAX_BINS)
+    }
+}
+
+impl QuantileCollector {
+    /// See [DDSketch::new], panics on the same settings.
+    pub fn new(relative_accuracy: f64, max_bins: usize) -> Self {
+        DDSketch::validate_settings(relative_accuracy, max_bins);
+        Self {
+            relative_accuracy,
+            max_bins,
+            limit: CardinalityLimit::unlimited(),
+            reads: Mutex::default(),
+            writes: Mutex::default(),
+        }
+    }
+
+    /// Caps the number of distinct schemas tracked for reads and for writes.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.limit = CardinalityLimit::new(max_keys);
+        self
+    }
+
+    /// The number of records accounted to [SchemaId::overflow].
+    pub fn overflowed_records(&self) -> u64 {
+        self.limit.overflowed_records()
+    }
+
+    /// A copy of the sketches of `kind`, which can be merged with the
+    /// sketches of other nodes by [DDSketch::merge].
+    pub fn sketches(&self, kind: MeterKind) -> HashMap<SchemaId, DDSketch> {
+        self.data(kind).lock().clone()
+    }
+
+    /// Estimates the quantiles `qs` of `kind` for each schema, in the order
+    /// of `qs`.
+    pub fn quantiles(&self, kind: MeterKind, qs: &[f64]) -> HashMap<SchemaId, Vec<f64>> {
+        self.data(kind)
+            .lock()
+            .iter()
+            .map(|(schema_id, sketch)| {
+                let values = qs
+                    .iter()
+                    .map(|q| sketch.quantile(*q).unwrap_or_default())
+                    .collect();
+                (schema_id.clone(), values)
+            })
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.reads.lock().clear();
+        self.writes.lock().clear();
+    }
+
+    fn data(&self, kind: MeterKind) -> &Mutex<HashMap<SchemaId, DDSketch>> {
+        match kind {
+            MeterKind::Read => &self.reads,
+            MeterKind::Write => &self.writes,
+        }
+    }
+
+    fn record(&self, kind: MeterKind, record: MeterRecord) {
+        let mut data = self.data(kind).lock();
+
+        let mut schema_id = SchemaId::of(&record);
+        if !self.limit.admit(data.contains_key(&schema_id), data.len()) {
+            schema_id = SchemaId::overflow();
+        }
+
+        data.entry(schema_id)
+            .or_insert_with(|| DDSketch::new(self.relative_accuracy, self.max_bins))
+            .add(record.value);
+    }
+}
+
+impl Collect for QuantileCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.record(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.record(MeterKind::Read, record);
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use parking_lot::RwLock;
+
+use crate::cardinality::CardinalityLimit;
+use crate::cardinality::OVERFLOW_KEY;
+use crate::collect::Collect;
+use crate::data::current_time_millis;
+use crate::data::MeterRecord;
+
+/// The span and resolution of a [RateCollector].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateConfig {
+    /// The length of the sliding window the rate is computed over.
+    pub window: Duration,
+
+    /// The number of sub-buckets the window is split into.
+    ///
+    /// More buckets make the window slide more smoothly at the cost of memory.
+    pub buckets: usize,
+}
+
+impl RateConfig {
+    pub fn new(window: Duration, buckets: usize) -> Self {
+        assert!(buckets > 0, "buckets must be positive");
+        assert!(
+            window.as_millis() as usize >= buckets,
+            "each bucket must span at least one millisecond"
+        );
+
+        Self { window, buckets }
+    }
+
+    fn bucket_millis(&self) -> u64 {
+        self.window.as_millis() as u64 / self.buckets as u64
+    }
+}
+
+impl Default for RateConfig {
+    /// A 10 second window with 1 second buckets.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(10), 10)
+    }
+}
+
+/// Units per second over the sliding window.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub read_units_per_sec: f64,
+    pub write_units_per_sec: f64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Bucket {
+    /// `timestamp / bucket_millis` of the data held in this bucket.
+    index: u64,
+    read_units: u64,
+    write_units: u64,
+}
+
+/// A ring of sub-buckets covering the sliding window of one schema.
+struct Ring {
+    buckets: Mutex<Vec<Bucket>>,
+}
+
+impl Ring {
+    fn new(len: usize) -> Self {
+        Self {
+            buckets: Mutex::new(vec![Bucket::default(); len]),
+        }
+    }
+
+    fn add(&self, index: u64, read_units: u64, write_units: u64) {
+        let mut buckets = self.buckets.lock();
+        let len = buckets.len() as u64;
+        let bucket = &mut buckets[(index % len) as usize];
+
+        if bucket.index < index {
+            *bucket = Bucket {
+                index,
+                ..Default::default()
+            };
+        } else if bucket.index > index {
+            // Older than the whole window, it would never be counted.
+            return;
+        }
+
+        bucket.read_units = bucket.read_units.saturating_add(read_units);
+        bucket.write_units = bucket.write_units.saturating_add(write_units);
+    }
+
+    /// Sums the buckets within `(now_index - len, now_index]`, saturating at
+    /// `u64::MAX`.
+    fn sum(&self, now_index: u64) -> (u64, u64) {
+        let buckets = self.buckets.lock();
+        let oldest = (now_index + 1).saturating_sub(buckets.len() as u64);
+
+        buckets
+            .iter()
+            .filter(|b| b.index >= oldest && b.index <= now_index)
+            .fold((0u64, 0u64), |(r, w), b| {
+                (
+                    r.saturating_add(b.read_units),
+                    w.saturating_add(b.write_units),
+                )
+            })
+    }
+}
+
+/// A [Collect] that keeps a per-schema sliding-window rate, intended for
+/// admission control.
+///
+/// Records are added to the sub-bucket of their timestamp, and
+/// [RateCollector::current_rate] sums the buckets of the last window without
+/// allocating.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::rate::RateCollector;
+/// use meter_core::rate::RateConfig;
+///
+/// let collector = RateCollector::new(RateConfig::default());
+///
+/// for ts in [0, 1_000, 2_000] {
+///     let record = MeterRecord::new("greptime".into(), "public".into(), 100, 0);
+///     collector.on_read(record.with_timestamp_millis(ts));
+/// }
+///
+/// let rate = collector.current_rate_at("greptime", "public", 9_999);
+/// assert_eq!(rate.read_units_per_sec, 30.0);
+///
+/// // The first record has left the window.
+/// let rate = collector.current_rate_at("greptime", "public", 10_000);
+/// assert_eq!(rate.read_units_per_sec, 20.0);
+/// ```
+pub struct RateCollector {
+    config: RateConfig,
+    limit: CardinalityLimit,
+    rings: RwLock<HashMap<String, HashMap<String, Arc<Ring>>>>,
+}
+
+impl RateCollector {
+    pub fn new(config: RateConfig) -> Self {
+        Self {
+            config,
+            limit: CardinalityLimit::unlimited(),
+            rings: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Caps the number of distinct schemas tracked. Schemas beyond the cap
+    /// share the rate of the [OVERFLOW_KEY] bucket.
+    ///
+    /// The shared rate is the sum of every overflowed schema, so admission
+    /// control based on it throttles all of them together once a single heavy
+    /// one goes over the limit. Set the cap above the number of schemas
+    /// expected, and treat [RateCollector::overflowed_records] growing as a
+    /// sign to raise it.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.limit = CardinalityLimit::new(max_keys);
+        self
+    }
+
+    /// The number of records accounted to the [OVERFLOW_KEY] bucket.
+    pub fn overflowed_records(&self) -> u64 {
+        self.limit.overflowed_records()
+    }
+
+    /// The rate of the schema over the window ending now.
+    pub fn current_rate(&self, catalog: &str, schema: &str) -> Rate {
+        self.current_rate_at(catalog, schema, current_time_millis())
+    }
+
+    /// The rate of the schema over the window ending at `now_millis`.
+    ///
+    /// A schema that is not tracked while the cap is reached has its records
+    /// in the [OVERFLOW_KEY] bucket, so it gets the rate of that bucket.
+    pub fn current_rate_at(&self, catalog: &str, schema: &str, now_millis: u64) -> Rate {
+        let ring = {
+            let rings = self.rings.read();
+            let get = |catalog: &str, schema: &str| {
+                rings
+                    .get(catalog)
+                    .and_then(|schemas| schemas.get(schema))
+                    .cloned()
+            };
+            let ring = get(catalog, schema).or_else(|| {
+                let len = rings.values().map(HashMap::len).sum();
+                self.limit
+                    .is_full(len)
+                    .then(|| get(OVERFLOW_KEY, OVERFLOW_KEY))
+                    .flatten()
+            });
+            match ring {
+                Some(ring) => ring,
+                None => return Rate::default(),
+            }
+        };
+
+        let (read_units, write_units) = ring.sum(now_millis / self.config.bucket_millis());
+        let secs = self.config.window.as_secs_f64();
+
+        Rate {
+            read_units_per_sec: read_units as f64 / secs,
+            write_units_per_sec: write_units as f64 / secs,
+        }
+    }
+
+    /// Removes the schemas that have no units in the window ending at
+    /// `now_millis`, so idle tenants do not hold memory forever.
+    pub fn evict_idle(&self, now_millis: u64) {
+        let now_index = now_millis / self.config.bucket_millis();
+        let mut rings = self.rings.write();
+
+        rings.retain(|_, schemas| {
+            schemas.retain(|_, ring| ring.sum(now_index) != (0, 0));
+            !schemas.is_empty()
+        });
+    }
+
+    fn ring(&self, catalog: &str, schema: &str) -> Arc<Ring> {
+        if let Some(ring) = self
+            .rings
+            .read()
+            .get(catalog)
+            .and_then(|schemas| schemas.get(schema))
+        {
+            return ring.clone();
+        }
+
+        let mut rings = self.rings.write();
+        let known = rings
+            .get(catalog)
+            .is_some_and(|schemas| schemas.contains_key(schema));
+        let len = rings.values().map(HashMap::len).sum();
+        let (catalog, schema) = if self.limit.admit(known, len) {
+            (catalog, schema)
+        } else {
+            (OVERFLOW_KEY, OVERFLOW_KEY)
+        };
+
+        rings
+            .entry(catalog.to_string())
+            .or_default()
+            .entry(schema.to_string())
+            .or_insert_with(|| Arc::new(Ring::new(self.config.buckets)))
+            .clone()
+    }
+
+    fn record(&self, record: &MeterRecord, read_units: u64, write_units: u64) {
+        let index = record.timestamp_millis / self.config.bucket_millis();
+        self.ring(&record.catalog, &record.schema)
+            .add(index, read_units, write_units);
+    }
+}
+
+impl Collect for RateCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.record(&record, 0, record.value);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.record(&record, record.value, 0);
+    }
+}
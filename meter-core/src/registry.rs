@@ -19,6 +19,8 @@ use tracing::warn;
 
 use crate::collect::Collect;
 use crate::data::MeterRecord;
+use crate::enrich::Enrich;
+use crate::id::RecordIdGenerator;
 use crate::ItemCalculator;
 
 type CalculatorMap = anymap2::SendSyncAnyMap;
@@ -31,6 +33,8 @@ pub struct Registry {
 struct Inner {
     collector: RwLock<Option<Arc<dyn Collect>>>,
     calculator: RwLock<CalculatorMap>,
+    ids: RecordIdGenerator,
+    enrichers: RwLock<Vec<Arc<dyn Enrich>>>,
 }
 
 impl Default for Inner {
@@ -38,6 +42,8 @@ impl Default for Inner {
         Self {
             collector: Default::default(),
             calculator: RwLock::new(CalculatorMap::new()),
+            ids: RecordIdGenerator::default(),
+            enrichers: Default::default(),
         }
     }
 }
@@ -49,6 +55,24 @@ impl Registry {
         *guard = Some(collector);
     }
 
+    /// Add an [Enrich] hook, run on every record in the order added before
+    /// the record is dispatched to the collector.
+    pub fn add_enricher(&self, enricher: Arc<dyn Enrich>) {
+        let mut guard = self.inner.enrichers.write();
+        guard.push(enricher);
+    }
+
+    /// Set the node ID embedded in the [RecordId](crate::id::RecordId) of every record.
+    pub fn set_node_id(&self, node_id: u16) {
+        self.inner.ids.set_node_id(node_id);
+    }
+
+    /// Set the epoch embedded in the [RecordId](crate::id::RecordId) of every
+    /// record, which should change on every restart of the node.
+    pub fn set_epoch(&self, epoch: u16) {
+        self.inner.ids.set_epoch(epoch);
+    }
+
     /// Register the calculation formula of 'insert request' -> 'byte count'
     pub fn register_calculator<T: Send + Sync + 'static>(
         &self,
@@ -83,7 +107,7 @@ impl Registry {
             None => return,
         };
 
-        collector.on_write(record);
+        collector.on_write(self.prepare(record));
     }
 
     /// A base API for recording information about data query.
@@ -95,6 +119,17 @@ impl Registry {
             None => return,
         };
 
-        collector.on_read(record);
+        collector.on_read(self.prepare(record));
+    }
+
+    /// Assigns the ID and runs the enrichers.
+    fn prepare(&self, mut record: MeterRecord) -> MeterRecord {
+        if record.id.is_none() {
+            record.id = Some(self.inner.ids.next(record.timestamp_millis));
+        }
+        for enricher in self.inner.enrichers.read().iter() {
+            enricher.enrich(&mut record);
+        }
+        record
     }
 }
@@ -0,0 +1,203 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::collect::dispatch;
+use crate::collect::Collect;
+use crate::data::MeterKind;
+use crate::data::MeterRecord;
+
+/// A glob pattern where `*` matches any sequence of characters and `?` matches
+/// exactly one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: Vec<char>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<_>>();
+        let (mut p, mut t) = (0, 0);
+        // The position of the last `*` and the text position it matched up to.
+        let mut star = None;
+
+        while t < text.len() {
+            match self.pattern.get(p) {
+                Some('*') => {
+                    star = Some((p, t));
+                    p += 1;
+                }
+                Some(c) if *c == '?' || *c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match star {
+                    // Let the last `*` swallow one more character.
+                    Some((star_p, star_t)) => {
+                        star = Some((star_p, star_t + 1));
+                        p = star_p + 1;
+                        t = star_t + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        self.pattern[p..].iter().all(|c| *c == '*')
+    }
+}
+
+/// A rule of a [RouterCollector]: the records it matches and the collectors
+/// they are sent to. Conditions left unset match every record.
+pub struct RouteRule {
+    catalog: Option<Glob>,
+    schema: Option<Glob>,
+    source: Option<u8>,
+    kind: Option<MeterKind>,
+    targets: Vec<Arc<dyn Collect>>,
+}
+
+impl RouteRule {
+    pub fn new(targets: Vec<Arc<dyn Collect>>) -> Self {
+        Self {
+            catalog: None,
+            schema: None,
+            source: None,
+            kind: None,
+            targets,
+        }
+    }
+
+    /// Matches the catalogs matched by the glob `pattern`.
+    pub fn catalog(mut self, pattern: &str) -> Self {
+        self.catalog = Some(Glob::new(pattern));
+        self
+    }
+
+    /// Matches the schemas matched by the glob `pattern`.
+    pub fn schema(mut self, pattern: &str) -> Self {
+        self.schema = Some(Glob::new(pattern));
+        self
+    }
+
+    pub fn source(mut self, source: u8) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Matches only reads or only writes.
+    pub fn kind(mut self, kind: MeterKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    fn matches(&self, kind: MeterKind, record: &MeterRecord) -> bool {
+        self.kind.is_none_or(|k| k == kind)
+            && self.source.is_none_or(|s| s == record.source)
+            && self
+                .catalog
+                .as_ref()
+                .is_none_or(|glob| glob.matches(&record.catalog))
+            && self
+                .schema
+                .as_ref()
+                .is_none_or(|glob| glob.matches(&record.schema))
+    }
+}
+
+/// A [Collect] that dispatches each record to child collectors by an ordered
+/// list of [RouteRule]s.
+///
+/// The first matching rule wins and the record is sent to all of its
+/// targets. Records matching no rule go to the default route.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::router::RouteRule;
+/// use meter_core::router::RouterCollector;
+/// use meter_core::window::WindowConfig;
+/// use meter_core::window::WindowedCollector;
+///
+/// let window = WindowConfig::tumbling(Duration::from_secs(60));
+/// let analytics = Arc::new(WindowedCollector::new(window));
+/// let billing = Arc::new(WindowedCollector::new(window));
+///
+/// let router = RouterCollector::new(vec![billing.clone()])
+///     .with_rule(RouteRule::new(vec![analytics.clone()]).catalog("greptime_private*"));
+///
+/// router.on_write(MeterRecord::new("greptime_private".into(), "public".into(), 1, 0));
+/// router.on_write(MeterRecord::new("customer".into(), "public".into(), 2, 0));
+///
+/// assert_eq!(analytics.flush()[0].key.catalog, "greptime_private");
+/// assert_eq!(billing.flush()[0].key.catalog, "customer");
+/// ```
+pub struct RouterCollector {
+    rules: Vec<RouteRule>,
+    default_targets: Vec<Arc<dyn Collect>>,
+}
+
+impl RouterCollector {
+    /// A router sending every record to `default_targets` until rules are added.
+    pub fn new(default_targets: Vec<Arc<dyn Collect>>) -> Self {
+        Self {
+            rules: Vec::new(),
+            default_targets,
+        }
+    }
+
+    /// Appends a rule, evaluated after the ones added before.
+    pub fn with_rule(mut self, rule: RouteRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    fn route(&self, kind: MeterKind, record: MeterRecord) {
+        let targets = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(kind, &record))
+            .map_or(&self.default_targets, |rule| &rule.targets);
+
+        let Some((last, others)) = targets.split_last() else {
+            return;
+        };
+        for target in others {
+            dispatch(target.as_ref(), kind, record.clone());
+        }
+        dispatch(last.as_ref(), kind, record);
+    }
+}
+
+impl Collect for RouterCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.route(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.route(MeterKind::Read, record);
+    }
+}
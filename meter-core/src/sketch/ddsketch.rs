@@ -0,0 +1,250 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use crate::codec::get_i32;
+use crate::codec::get_u32;
+use crate::codec::get_u64;
+use crate::sketch::MergeError;
+
+/// The default relative accuracy of [DDSketch].
+pub const DEFAULT_RELATIVE_ACCURACY: f64 = 0.01;
+
+/// The default maximum number of bins of [DDSketch].
+pub const DEFAULT_MAX_BINS: usize = 2048;
+
+/// A quantile sketch with a relative error guarantee, after DDSketch.
+///
+/// Values are put into logarithmically sized bins, so any quantile is
+/// estimated within `relative_accuracy` of the true value. Once there are more
+/// than `max_bins` bins the lowest ones are collapsed, which only affects the
+/// accuracy of the lowest quantiles.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::sketch::ddsketch::DDSketch;
+///
+/// let mut sketch = DDSketch::default();
+/// for v in 1..=1000 {
+///     sketch.add(v);
+/// }
+///
+/// let p99 = sketch.quantile(0.99).unwrap();
+/// assert!((p99 - 990.0).abs() <= 990.0 * 0.01);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DDSketch {
+    relative_accuracy: f64,
+    gamma: f64,
+    ln_gamma: f64,
+    max_bins: usize,
+    bins: BTreeMap<i32, u64>,
+    zero_count: u64,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Default for DDSketch {
+    fn default() -> Self {
+        Self::new(DEFAULT_RELATIVE_ACCURACY, DEFAULT_MAX_BINS)
+    }
+}
+
+impl DDSketch {
+    pub fn new(relative_accuracy: f64, max_bins: usize) -> Self {
+        Self::validate_settings(relative_accuracy, max_bins);
+
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            relative_accuracy,
+            gamma,
+            ln_gamma: gamma.ln(),
+            max_bins,
+            bins: BTreeMap::new(),
+            zero_count: 0,
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Panics unless `relative_accuracy` is in `(0, 1)` and `max_bins` is
+    /// positive, e.g. to reject a bad setting before any sketch is built.
+    pub fn validate_settings(relative_accuracy: f64, max_bins: usize) {
+        assert!(
+            relative_accuracy > 0.0 && relative_accuracy < 1.0,
+            "relative accuracy must be in (0, 1)"
+        );
+        assert!(max_bins > 0, "max bins must be positive");
+    }
+
+    pub fn relative_accuracy(&self) -> f64 {
+        self.relative_accuracy
+    }
+
+    pub fn max_bins(&self) -> usize {
+        self.max_bins
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Adds a value. The count and the sum saturate at `u64::MAX`.
+    pub fn add(&mut self, value: u64) {
+        self.count = self.count.saturating_add(1);
+        self.sum = self.sum.saturating_add(value);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+
+        if value == 0 {
+            self.zero_count = self.zero_count.saturating_add(1);
+        } else {
+            let bin = self.bins.entry(self.key(value)).or_default();
+            *bin = bin.saturating_add(1);
+            self.collapse();
+        }
+    }
+
+    /// Estimates the `q`-quantile, `q` in `[0, 1]`. Returns `None` if empty.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+
+        let rank = (q * (self.count - 1) as f64) as u64;
+        if rank < self.zero_count {
+            return Some(0.0);
+        }
+
+        let mut seen = self.zero_count;
+        for (key, count) in &self.bins {
+            seen = seen.saturating_add(*count);
+            if seen > rank {
+                let estimate = self.value(*key);
+                // The extremes are known exactly, never estimate beyond them.
+                return Some(estimate.clamp(self.min as f64, self.max as f64));
+            }
+        }
+        Some(self.max as f64)
+    }
+
+    /// Merges another sketch with the same relative accuracy into this one,
+    /// keeping the `max_bins` of this one. The counts saturate at `u64::MAX`.
+    ///
+    /// Fails, leaving this sketch unchanged, if the relative accuracies
+    /// differ.
+    pub fn merge(&mut self, other: &DDSketch) -> Result<(), MergeError> {
+        if self.gamma != other.gamma {
+            return Err(MergeError::new(format!(
+                "different relative accuracy: {} and {}",
+                self.relative_accuracy, other.relative_accuracy
+            )));
+        }
+
+        for (key, count) in &other.bins {
+            let bin = self.bins.entry(*key).or_default();
+            *bin = bin.saturating_add(*count);
+        }
+        self.zero_count = self.zero_count.saturating_add(other.zero_count);
+        self.count = self.count.saturating_add(other.count);
+        self.sum = self.sum.saturating_add(other.sum);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.collapse();
+        Ok(())
+    }
+
+    /// Appends the sketch to `buf`, see [DDSketch::decode].
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.relative_accuracy.to_bits().to_le_bytes());
+        buf.extend_from_slice(&(self.max_bins as u64).to_le_bytes());
+        for n in [self.zero_count, self.count, self.sum, self.min, self.max] {
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.bins.len() as u32).to_le_bytes());
+        for (key, count) in &self.bins {
+            buf.extend_from_slice(&key.to_le_bytes());
+            buf.extend_from_slice(&count.to_le_bytes());
+        }
+    }
+
+    /// Reads a sketch written by [DDSketch::encode] from the front of `buf`.
+    /// Returns `None` if it is truncated or inconsistent.
+    pub(crate) fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let relative_accuracy = f64::from_bits(get_u64(buf)?);
+        let max_bins = get_u64(buf)? as usize;
+        if !(relative_accuracy > 0.0 && relative_accuracy < 1.0) || max_bins == 0 {
+            return None;
+        }
+
+        let mut sketch = Self::new(relative_accuracy, max_bins);
+        sketch.zero_count = get_u64(buf)?;
+        sketch.count = get_u64(buf)?;
+        sketch.sum = get_u64(buf)?;
+        sketch.min = get_u64(buf)?;
+        sketch.max = get_u64(buf)?;
+        for _ in 0..get_u32(buf)? {
+            let key = get_i32(buf)?;
+            sketch.bins.insert(key, get_u64(buf)?);
+        }
+
+        let binned = sketch
+            .bins
+            .values()
+            .fold(sketch.zero_count, |total, count| {
+                total.saturating_add(*count)
+            });
+        (sketch.bins.len() <= max_bins && binned == sketch.count).then_some(sketch)
+    }
+
+    fn key(&self, value: u64) -> i32 {
+        ((value as f64).ln() / self.ln_gamma).ceil() as i32
+    }
+
+    /// The representative value of bin `key`, within the relative accuracy of
+    /// every value in `(gamma^(key-1), gamma^key]`.
+    fn value(&self, key: i32) -> f64 {
+        2.0 * self.gamma.powi(key) / (self.gamma + 1.0)
+    }
+
+    fn collapse(&mut self) {
+        while self.bins.len() > self.max_bins {
+            let (_, lowest) = self.bins.pop_first().unwrap();
+            let bin = self.bins.first_entry().unwrap().into_mut();
+            *bin = bin.saturating_add(lowest);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use crate::codec::get_u8;
+use crate::codec::take;
+use crate::sketch::MergeError;
+
+/// The default precision of [HyperLogLog], about 0.8% standard error with
+/// 16KiB of registers.
+pub const DEFAULT_PRECISION: u8 = 14;
+
+const PRECISIONS: RangeInclusive<u8> = 4..=16;
+
+/// A HyperLogLog distinct-count sketch.
+///
+/// Uses `2^precision` registers and has a standard error of about
+/// `1.04 / sqrt(2^precision)`. Values are hashed with a fixed hash function,
+/// so sketches built on different nodes can be merged.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::sketch::hll::HyperLogLog;
+///
+/// let mut a = HyperLogLog::default();
+/// let mut b = HyperLogLog::default();
+/// for i in 0..1000 {
+///     a.insert(format!("table_{i}").as_bytes());
+///     b.insert(format!("table_{}", i + 500).as_bytes());
+/// }
+///
+/// a.merge(&b).unwrap();
+/// let estimate = a.estimate() as f64;
+/// assert!((estimate - 1500.0).abs() < 1500.0 * 0.05);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRECISION)
+    }
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Self {
+        Self::validate_precision(precision);
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Panics unless `precision` is in `[4, 16]`, e.g. to reject a bad setting
+    /// before any sketch is built.
+    pub fn validate_precision(precision: u8) {
+        assert!(
+            PRECISIONS.contains(&precision),
+            "precision must be in [4, 16]"
+        );
+    }
+
+    /// Rebuilds a sketch from the registers of [HyperLogLog::registers].
+    pub fn from_registers(precision: u8, registers: Vec<u8>) -> Self {
+        let hll = Self::new(precision);
+        assert_eq!(
+            hll.registers.len(),
+            registers.len(),
+            "the number of registers does not match the precision"
+        );
+        Self { registers, ..hll }
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    pub fn insert(&mut self, value: &[u8]) {
+        let hash = hash64(value);
+        let index = (hash >> (64 - self.precision)) as usize;
+        // The remaining bits, with a sentinel so the rank is bounded.
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank);
+    }
+
+    /// Merges another sketch with the same precision into this one. Fails,
+    /// leaving this sketch unchanged, if the precisions differ.
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), MergeError> {
+        self.check_precision(other)?;
+        for (a, b) in self.registers.iter_mut().zip(&other.registers) {
+            *a = (*a).max(*b);
+        }
+        Ok(())
+    }
+
+    fn check_precision(&self, other: &HyperLogLog) -> Result<(), MergeError> {
+        if self.precision != other.precision {
+            return Err(MergeError::new(format!(
+                "different precision: {} and {}",
+                self.precision, other.precision
+            )));
+        }
+        Ok(())
+    }
+
+    /// Appends the sketch to `buf`, see [HyperLogLog::decode].
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.precision);
+        buf.extend_from_slice(&self.registers);
+    }
+
+    /// Reads a sketch written by [HyperLogLog::encode] from the front of
+    /// `buf`. Returns `None` if it is truncated or invalid.
+    pub(crate) fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let precision = get_u8(buf)?;
+        if !PRECISIONS.contains(&precision) {
+            return None;
+        }
+        let registers = take(buf, 1 << precision)?.to_vec();
+        Some(Self::from_registers(precision, registers))
+    }
+
+    /// The approximate number of distinct values inserted.
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let raw = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities.
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+}
+
+/// Merges sketches keyed by label into `into`, copying those it lacks.
+/// Fails, leaving `into` unchanged, if the sketches of a label have different
+/// precisions.
+pub fn merge_by_label(
+    into: &mut BTreeMap<String, HyperLogLog>,
+    from: &BTreeMap<String, HyperLogLog>,
+) -> Result<(), MergeError> {
+    check_by_label(into, from)?;
+    for (label, hll) in from {
+        match into.get_mut(label) {
+            Some(sketch) => sketch.merge(hll)?,
+            None => {
+                into.insert(label.clone(), hll.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that [merge_by_label] would succeed.
+fn check_by_label(
+    into: &BTreeMap<String, HyperLogLog>,
+    from: &BTreeMap<String, HyperLogLog>,
+) -> Result<(), MergeError> {
+    for (label, hll) in from {
+        if let Some(sketch) = into.get(label) {
+            sketch.check_precision(hll)?;
+        }
+    }
+    Ok(())
+}
+
+/// FNV-1a followed by the MurmurHash3 finalizer, stable across platforms and
+/// releases.
+fn hash64(value: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in value {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+    hash
+}
@@ -0,0 +1,180 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A key with its estimated weight.
+///
+/// The true weight is within `[count - error, count]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeavyHitter<K> {
+    pub key: K,
+    pub count: u64,
+    pub error: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Counter {
+    count: u64,
+    error: u64,
+}
+
+/// The space-saving heavy-hitter sketch.
+///
+/// Keeps at most `capacity` counters. The weight of any key is overestimated
+/// by at most `total / capacity`, and every key heavier than that is
+/// guaranteed to be tracked. Weights saturate at `u64::MAX`.
+#[derive(Debug, Clone)]
+pub struct SpaceSaving<K> {
+    capacity: usize,
+    total: u64,
+    counters: HashMap<K, Counter>,
+    /// The counters ordered by count, so the one to evict is found in
+    /// `O(log capacity)`. Among equal counts the largest key goes first.
+    by_count: BTreeSet<(u64, Reverse<K>)>,
+}
+
+impl<K: Hash + Eq + Clone + Ord> SpaceSaving<K> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+
+        Self {
+            capacity,
+            total: 0,
+            counters: HashMap::with_capacity(capacity),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The sum of all weights added.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The upper bound of the overestimation of any key.
+    pub fn max_error(&self) -> u64 {
+        self.total / self.capacity as u64
+    }
+
+    pub fn add(&mut self, key: K, weight: u64) {
+        self.total = self.total.saturating_add(weight);
+
+        if let Some(counter) = self.counters.get_mut(&key) {
+            let count = counter.count.saturating_add(weight);
+            self.by_count.remove(&(counter.count, Reverse(key.clone())));
+            self.by_count.insert((count, Reverse(key)));
+            counter.count = count;
+            return;
+        }
+
+        if self.counters.len() < self.capacity {
+            self.insert(
+                key,
+                Counter {
+                    count: weight,
+                    error: 0,
+                },
+            );
+            return;
+        }
+
+        // Replace the smallest counter, inheriting its count as error.
+        let (min_count, Reverse(min_key)) = self.by_count.pop_first().unwrap();
+        self.counters.remove(&min_key);
+        self.insert(
+            key,
+            Counter {
+                count: min_count.saturating_add(weight),
+                error: min_count,
+            },
+        );
+    }
+
+    /// The `n` heaviest keys, heaviest first.
+    pub fn top(&self, n: usize) -> Vec<HeavyHitter<K>> {
+        let mut hitters = self
+            .counters
+            .iter()
+            .map(|(key, counter)| HeavyHitter {
+                key: key.clone(),
+                count: counter.count,
+                error: counter.error,
+            })
+            .collect::<Vec<_>>();
+        hitters.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        hitters.truncate(n);
+        hitters
+    }
+
+    /// Merges another sketch of the same capacity into this one.
+    ///
+    /// A key missing from one side may still have been seen there with up to
+    /// that side's minimum count, which is added to both its count and error.
+    pub fn merge(&mut self, other: &SpaceSaving<K>) {
+        let self_min = self.min_count();
+        let other_min = other.min_count();
+
+        let mut merged = HashMap::with_capacity(self.counters.len() + other.counters.len());
+        for (key, counter) in &self.counters {
+            let (count, error) = match other.counters.get(key) {
+                Some(o) => (o.count, o.error),
+                None => (other_min, other_min),
+            };
+            merged.insert(
+                key.clone(),
+                Counter {
+                    count: counter.count.saturating_add(count),
+                    error: counter.error.saturating_add(error),
+                },
+            );
+        }
+        for (key, counter) in &other.counters {
+            merged.entry(key.clone()).or_insert(Counter {
+                count: counter.count.saturating_add(self_min),
+                error: counter.error.saturating_add(self_min),
+            });
+        }
+
+        let mut merged = merged.into_iter().collect::<Vec<_>>();
+        merged.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        merged.truncate(self.capacity);
+
+        self.total = self.total.saturating_add(other.total);
+        self.counters.clear();
+        self.by_count.clear();
+        for (key, counter) in merged {
+            self.insert(key, counter);
+        }
+    }
+
+    fn insert(&mut self, key: K, counter: Counter) {
+        self.by_count.insert((counter.count, Reverse(key.clone())));
+        self.counters.insert(key, counter);
+    }
+
+    /// The smallest count tracked once the sketch is full, zero otherwise.
+    fn min_count(&self) -> u64 {
+        if self.counters.len() < self.capacity {
+            return 0;
+        }
+        self.by_count.first().map_or(0, |(count, _)| *count)
+    }
+}
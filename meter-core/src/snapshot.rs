@@ -0,0 +1,454 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Partial aggregates of a node that merge into cluster-wide ones without
+//! shipping every record.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+
+use parking_lot::Mutex;
+
+use crate::cardinality::CardinalityLimit;
+use crate::codec::get_str;
+use crate::codec::get_u16;
+use crate::codec::get_u32;
+use crate::codec::get_u64;
+use crate::codec::get_u8;
+use crate::codec::put_str;
+use crate::collect::Collect;
+use crate::data::MeterKind;
+use crate::data::MeterRecord;
+use crate::sketch::ddsketch::DDSketch;
+use crate::sketch::ddsketch::DEFAULT_MAX_BINS;
+use crate::sketch::ddsketch::DEFAULT_RELATIVE_ACCURACY;
+use crate::sketch::hll::merge_by_label;
+use crate::sketch::hll::HyperLogLog;
+use crate::sketch::hll::DEFAULT_PRECISION;
+use crate::sketch::MergeError;
+use crate::window::GroupKey;
+use crate::window::Usage;
+use crate::window::Window;
+
+/// The version of the encoding written by [AggregateSnapshot::encode].
+const VERSION: u8 = 1;
+
+/// The partial aggregates of one group key in an [AggregateSnapshot].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    pub usage: Usage,
+    /// The distribution of the values of read records.
+    pub reads: DDSketch,
+    /// The distribution of the values of write records.
+    pub writes: DDSketch,
+    /// A sketch of the distinct values of each chosen label.
+    pub distinct: BTreeMap<String, HyperLogLog>,
+}
+
+impl SnapshotEntry {
+    fn merge(&mut self, other: &SnapshotEntry) -> Result<(), MergeError> {
+        self.reads.merge(&other.reads)?;
+        self.writes.merge(&other.writes)?;
+        merge_by_label(&mut self.distinct, &other.distinct)?;
+        self.usage.merge(&other.usage);
+        Ok(())
+    }
+}
+
+/// The settings shared by every sketch of a snapshot, `None` if it has no
+/// sketch of the kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Settings {
+    /// The relative accuracy and maximum number of bins of the value
+    /// distributions.
+    quantile: Option<(f64, usize)>,
+    /// The precision of the distinct-count sketches.
+    precision: Option<u8>,
+}
+
+impl Settings {
+    fn of(snapshot: &AggregateSnapshot) -> Result<Self, MergeError> {
+        let mut settings = Settings::default();
+        for entry in snapshot.entries.values() {
+            for sketch in [&entry.reads, &entry.writes] {
+                let quantile = Some((sketch.relative_accuracy(), sketch.max_bins()));
+                settings.quantile = same(settings.quantile, quantile, "quantile sketches")?;
+            }
+            for hll in entry.distinct.values() {
+                let precision = Some(hll.precision());
+                settings.precision = same(settings.precision, precision, "precisions")?;
+            }
+        }
+        Ok(settings)
+    }
+
+    fn merge(self, other: Settings) -> Result<Self, MergeError> {
+        Ok(Settings {
+            quantile: same(self.quantile, other.quantile, "quantile sketches")?,
+            precision: same(self.precision, other.precision, "precisions")?,
+        })
+    }
+}
+
+/// The setting of `a` or `b`, an error if both have one and they differ.
+fn same<T>(a: Option<T>, b: Option<T>, what: &str) -> Result<Option<T>, MergeError>
+where
+    T: PartialEq + fmt::Debug,
+{
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => Err(MergeError::new(format!(
+            "different {}: {:?} and {:?}",
+            what, a, b
+        ))),
+        (a, b) => Ok(a.or(b)),
+    }
+}
+
+/// The partial aggregates of one or more nodes: usage sums and counts, value
+/// distributions and distinct-count sketches per group key.
+///
+/// [AggregateSnapshot::merge] is associative and commutative, with the empty
+/// snapshot as identity, so snapshots of every node may be merged in any
+/// order and grouping to the same cluster-wide snapshot. Snapshots taken with
+/// different sketch settings are not merged. Sums and counts saturate at
+/// `u64::MAX`.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::snapshot::AggregateSnapshot;
+/// use meter_core::snapshot::SnapshotCollector;
+/// use meter_core::window::GroupKey;
+///
+/// let node = |node_id, value| {
+///     let collector = SnapshotCollector::new(node_id).with_distinct_labels(["table"]);
+///     let record = MeterRecord::new("greptime".into(), "public".into(), value, 0);
+///     collector.on_write(record.with_label("table", format!("t{}", node_id)));
+///     collector.take()
+/// };
+///
+/// let mut cluster = AggregateSnapshot::default();
+/// for snapshot in [node(1, 10), node(2, 20)] {
+///     // Snapshots are shipped encoded.
+///     let snapshot = AggregateSnapshot::decode(&snapshot.encode()).unwrap();
+///     cluster.merge(&snapshot).unwrap();
+/// }
+///
+/// assert_eq!(cluster.nodes.len(), 2);
+/// let entry = &cluster.entries[&GroupKey::new("greptime", "public", 0)];
+/// assert_eq!(entry.usage.write_units, 30);
+/// assert_eq!(entry.writes.max(), Some(20));
+/// assert_eq!(entry.distinct["table"].estimate(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AggregateSnapshot {
+    /// The IDs of the nodes whose records are included.
+    pub nodes: BTreeSet<u16>,
+    /// The smallest window holding the timestamps of the records included,
+    /// `None` if there are none.
+    pub window: Option<Window>,
+    pub entries: BTreeMap<GroupKey, SnapshotEntry>,
+}
+
+impl AggregateSnapshot {
+    /// Creates an empty snapshot of node `node_id`.
+    pub fn new(node_id: u16) -> Self {
+        Self {
+            nodes: BTreeSet::from([node_id]),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Merges the snapshot of other nodes into this one. Fails, leaving this
+    /// one unchanged, if their value distributions or distinct-count
+    /// sketches were taken with different settings.
+    pub fn merge(&mut self, other: &AggregateSnapshot) -> Result<(), MergeError> {
+        Settings::of(self)?.merge(Settings::of(other)?)?;
+
+        self.nodes.extend(&other.nodes);
+        self.window = match (self.window, other.window) {
+            (Some(a), Some(b)) => Some(Window::new(
+                a.start_millis.min(b.start_millis),
+                a.end_millis.max(b.end_millis),
+            )),
+            (a, b) => a.or(b),
+        };
+        for (key, entry) in &other.entries {
+            match self.entries.get_mut(key) {
+                Some(mine) => mine.merge(entry)?,
+                None => {
+                    self.entries.insert(key.clone(), entry.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the snapshot as `[version: u8][crc32: u32][payload]`, little
+    /// endian, to be shipped to other nodes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(256);
+        payload.extend_from_slice(&(self.nodes.len() as u32).to_le_bytes());
+        for node_id in &self.nodes {
+            payload.extend_from_slice(&node_id.to_le_bytes());
+        }
+        match self.window {
+            Some(window) => {
+                payload.push(1);
+                payload.extend_from_slice(&window.start_millis.to_le_bytes());
+                payload.extend_from_slice(&window.end_millis.to_le_bytes());
+            }
+            None => payload.push(0),
+        }
+
+        payload.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, entry) in &self.entries {
+            put_str(&mut payload, &key.catalog);
+            put_str(&mut payload, &key.schema);
+            payload.push(key.source);
+            let usage = &entry.usage;
+            for n in [
+                usage.read_units,
+                usage.write_units,
+                usage.read_count,
+                usage.write_count,
+            ] {
+                payload.extend_from_slice(&n.to_le_bytes());
+            }
+            entry.reads.encode(&mut payload);
+            entry.writes.encode(&mut payload);
+            payload.extend_from_slice(&(entry.distinct.len() as u32).to_le_bytes());
+            for (label, hll) in &entry.distinct {
+                put_str(&mut payload, label);
+                hll.encode(&mut payload);
+            }
+        }
+
+        let mut buf = Vec::with_capacity(payload.len() + 5);
+        buf.push(VERSION);
+        buf.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        buf.extend_from_slice(&payload);
+        buf
+    }
+
+    /// Decodes a snapshot written by [AggregateSnapshot::encode]. Fails if
+    /// its sketches do not share the same settings.
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let corrupted =
+            || io::Error::new(io::ErrorKind::InvalidData, "corrupted aggregate snapshot");
+
+        let mut buf = buf;
+        let version = get_u8(&mut buf).ok_or_else(corrupted)?;
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported aggregate snapshot version {}", version),
+            ));
+        }
+        let crc = get_u32(&mut buf).ok_or_else(corrupted)?;
+        if crc32fast::hash(buf) != crc {
+            return Err(corrupted());
+        }
+
+        decode_payload(&mut buf)
+            .filter(|_| buf.is_empty())
+            .ok_or_else(corrupted)
+    }
+}
+
+fn decode_payload(p: &mut &[u8]) -> Option<AggregateSnapshot> {
+    let mut snapshot = AggregateSnapshot::default();
+    for _ in 0..get_u32(p)? {
+        snapshot.nodes.insert(get_u16(p)?);
+    }
+    snapshot.window = match get_u8(p)? {
+        0 => None,
+        1 => Some(Window::new(get_u64(p)?, get_u64(p)?)),
+        _ => return None,
+    };
+
+    for _ in 0..get_u32(p)? {
+        let catalog = get_str(p)?;
+        let schema = get_str(p)?;
+        let source = get_u8(p)?;
+        let usage = Usage {
+            read_units: get_u64(p)?,
+            write_units: get_u64(p)?,
+            read_count: get_u64(p)?,
+            write_count: get_u64(p)?,
+        };
+        let reads = DDSketch::decode(p)?;
+        let writes = DDSketch::decode(p)?;
+        let mut distinct = BTreeMap::new();
+        for _ in 0..get_u32(p)? {
+            let label = get_str(p)?;
+            distinct.insert(label, HyperLogLog::decode(p)?);
+        }
+
+        let entry = SnapshotEntry {
+            usage,
+            reads,
+            writes,
+            distinct,
+        };
+        snapshot
+            .entries
+            .insert(GroupKey::new(catalog, schema, source), entry);
+    }
+    Settings::of(&snapshot).ok()?;
+    Some(snapshot)
+}
+
+/// A [Collect] that accumulates the records of a node into an
+/// [AggregateSnapshot], taken with [SnapshotCollector::take] to be shipped
+/// and merged elsewhere.
+pub struct SnapshotCollector {
+    node_id: u16,
+    labels: Vec<String>,
+    precision: u8,
+    relative_accuracy: f64,
+    max_bins: usize,
+    limit: CardinalityLimit,
+    snapshot: Mutex<AggregateSnapshot>,
+}
+
+impl SnapshotCollector {
+    pub fn new(node_id: u16) -> Self {
+        Self {
+            node_id,
+            labels: vec![],
+            precision: DEFAULT_PRECISION,
+            relative_accuracy: DEFAULT_RELATIVE_ACCURACY,
+            max_bins: DEFAULT_MAX_BINS,
+            limit: CardinalityLimit::unlimited(),
+            snapshot: Mutex::new(AggregateSnapshot::new(node_id)),
+        }
+    }
+
+    /// Counts the distinct values of `labels`.
+    pub fn with_distinct_labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.labels = labels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the precision of the distinct-count sketches, see
+    /// [HyperLogLog::new].
+    pub fn with_precision(mut self, precision: u8) -> Self {
+        HyperLogLog::validate_precision(precision);
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the accuracy and size of the value distributions, see
+    /// [DDSketch::new].
+    pub fn with_quantile_sketch(mut self, relative_accuracy: f64, max_bins: usize) -> Self {
+        DDSketch::validate_settings(relative_accuracy, max_bins);
+        self.relative_accuracy = relative_accuracy;
+        self.max_bins = max_bins;
+        self
+    }
+
+    /// Caps the number of distinct group keys tracked.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.limit = CardinalityLimit::new(max_keys);
+        self
+    }
+
+    /// The number of records accounted to [GroupKey::overflow].
+    pub fn overflowed_records(&self) -> u64 {
+        self.limit.overflowed_records()
+    }
+
+    /// Takes the snapshot accumulated so far.
+    pub fn take(&self) -> AggregateSnapshot {
+        std::mem::replace(
+            &mut *self.snapshot.lock(),
+            AggregateSnapshot::new(self.node_id),
+        )
+    }
+
+    fn record(&self, kind: MeterKind, record: MeterRecord) {
+        let mut snapshot = self.snapshot.lock();
+        let snapshot = &mut *snapshot;
+
+        let ts = record.timestamp_millis;
+        snapshot.window = Some(match snapshot.window {
+            Some(window) => Window::new(
+                window.start_millis.min(ts),
+                window.end_millis.max(ts.saturating_add(1)),
+            ),
+            None => Window::new(ts, ts.saturating_add(1)),
+        });
+
+        let mut key = GroupKey::of(&record);
+        if !self
+            .limit
+            .admit(snapshot.entries.contains_key(&key), snapshot.entries.len())
+        {
+            key = GroupKey::overflow(record.source);
+        }
+
+        let entry = snapshot
+            .entries
+            .entry(key)
+            .or_insert_with(|| SnapshotEntry {
+                usage: Usage::default(),
+                reads: DDSketch::new(self.relative_accuracy, self.max_bins),
+                writes: DDSketch::new(self.relative_accuracy, self.max_bins),
+                distinct: self
+                    .labels
+                    .iter()
+                    .map(|label| (label.clone(), HyperLogLog::new(self.precision)))
+                    .collect(),
+            });
+
+        match kind {
+            MeterKind::Read => {
+                entry.usage.add_read(record.value);
+                entry.reads.add(record.value);
+            }
+            MeterKind::Write => {
+                entry.usage.add_write(record.value);
+                entry.writes.add(record.value);
+            }
+        }
+        for (label, hll) in entry.distinct.iter_mut() {
+            if let Some(value) = record.labels.get(label) {
+                hll.insert(value.as_bytes());
+            }
+        }
+    }
+}
+
+impl Collect for SnapshotCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.record(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.record(MeterKind::Read, record);
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::collect::Collect;
+use crate::data::current_time_millis;
+use crate::data::MeterKind;
+use crate::data::MeterRecord;
+use crate::data::SchemaId;
+use crate::sketch::space_saving::HeavyHitter;
+use crate::sketch::space_saving::SpaceSaving;
+
+/// The sketch size, window and meter of a [TopKCollector].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopKConfig {
+    /// The number of counters of each sketch, bounding both memory and the
+    /// error to `total / capacity`.
+    pub capacity: usize,
+
+    /// The length of the sliding window heavy hitters are tracked over.
+    pub window: Duration,
+
+    /// The number of sub-window sketches the window is split into.
+    pub buckets: usize,
+
+    /// Whether read or write units are ranked.
+    pub kind: MeterKind,
+}
+
+impl TopKConfig {
+    pub fn new(capacity: usize, window: Duration, buckets: usize, kind: MeterKind) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        assert!(buckets > 0, "buckets must be positive");
+        assert!(
+            window.as_millis() as usize >= buckets,
+            "each bucket must span at least one millisecond"
+        );
+
+        Self {
+            capacity,
+            window,
+            buckets,
+            kind,
+        }
+    }
+
+    fn bucket_millis(&self) -> u64 {
+        self.window.as_millis() as u64 / self.buckets as u64
+    }
+}
+
+struct Bucket {
+    /// `timestamp / bucket_millis` of the data held in this bucket.
+    index: u64,
+    sketch: SpaceSaving<SchemaId>,
+}
+
+/// A [Collect] that tracks the heaviest schemas of the last window with a
+/// space-saving sketch per sub-window, without keeping every tenant's totals.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterKind;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::topk::TopKCollector;
+/// use meter_core::topk::TopKConfig;
+///
+/// let config = TopKConfig::new(64, Duration::from_secs(60), 6, MeterKind::Read);
+/// let collector = TopKCollector::new(config);
+///
+/// for (schema, value) in [("a", 10), ("b", 300), ("c", 20), ("b", 5)] {
+///     let record = MeterRecord::new("greptime".into(), schema.into(), value, 0);
+///     collector.on_read(record.with_timestamp_millis(1_000));
+/// }
+///
+/// let top = collector.top_at(2, 1_000);
+/// assert_eq!(top[0].key.schema, "b");
+/// assert_eq!(top[0].count, 305);
+/// assert_eq!(top[1].key.schema, "c");
+/// ```
+pub struct TopKCollector {
+    config: TopKConfig,
+    buckets: Vec<Mutex<Bucket>>,
+}
+
+impl TopKCollector {
+    pub fn new(config: TopKConfig) -> Self {
+        let buckets = (0..config.buckets)
+            .map(|_| {
+                Mutex::new(Bucket {
+                    index: 0,
+                    sketch: SpaceSaving::new(config.capacity),
+                })
+            })
+            .collect();
+
+        Self { config, buckets }
+    }
+
+    /// The `n` heaviest schemas of the window ending now.
+    pub fn top(&self, n: usize) -> Vec<HeavyHitter<SchemaId>> {
+        self.top_at(n, current_time_millis())
+    }
+
+    /// The `n` heaviest schemas of the window ending at `now_millis`.
+    pub fn top_at(&self, n: usize, now_millis: u64) -> Vec<HeavyHitter<SchemaId>> {
+        self.sketch_at(now_millis).top(n)
+    }
+
+    /// The sketch of the window ending at `now_millis`, which can be merged
+    /// with the sketches of other nodes by [SpaceSaving::merge].
+    pub fn sketch_at(&self, now_millis: u64) -> SpaceSaving<SchemaId> {
+        let now_index = now_millis / self.config.bucket_millis();
+        let oldest = (now_index + 1).saturating_sub(self.buckets.len() as u64);
+
+        let mut sketch = SpaceSaving::new(self.config.capacity);
+        for bucket in &self.buckets {
+            let bucket = bucket.lock();
+            if bucket.index >= oldest && bucket.index <= now_index {
+                sketch.merge(&bucket.sketch);
+            }
+        }
+        sketch
+    }
+
+    fn record(&self, record: MeterRecord) {
+        let index = record.timestamp_millis / self.config.bucket_millis();
+        let mut bucket = self.buckets[(index % self.buckets.len() as u64) as usize].lock();
+
+        if bucket.index < index {
+            bucket.index = index;
+            bucket.sketch = SpaceSaving::new(self.config.capacity);
+        } else if bucket.index > index {
+            // Older than the whole window, it would never be counted.
+            return;
+        }
+
+        bucket.sketch.add(SchemaId::of(&record), record.value);
+    }
+}
+
+impl Collect for TopKCollector {
+    fn on_write(&self, record: MeterRecord) {
+        if self.config.kind == MeterKind::Write {
+            self.record(record);
+        }
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        if self.config.kind == MeterKind::Read {
+            self.record(record);
+        }
+    }
+}
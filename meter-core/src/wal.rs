@@ -0,0 +1,515 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Condvar;
+use parking_lot::Mutex;
+use tracing::error;
+use tracing::warn;
+
+use crate::codec::get_str;
+use crate::codec::get_u32;
+use crate::codec::get_u64;
+use crate::codec::get_u8;
+use crate::codec::put_str;
+use crate::codec::take;
+use crate::collect::dispatch;
+use crate::collect::Collect;
+use crate::data::MeterKind;
+use crate::data::MeterRecord;
+use crate::id::RecordId;
+
+const SEGMENT_SUFFIX: &str = ".wal";
+
+/// Where and how often a [DurableCollector] persists records.
+#[derive(Debug, Clone)]
+pub struct WalConfig {
+    /// The directory holding the segment files.
+    pub dir: PathBuf,
+
+    /// A segment is sealed and a new one started once it exceeds this size in bytes.
+    pub segment_size: u64,
+
+    /// The number of records appended before they are fsynced together.
+    pub sync_batch: usize,
+
+    /// The longest time appended records wait for an fsync, enforced by a
+    /// background thread.
+    pub sync_interval: Duration,
+}
+
+impl WalConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            segment_size: 64 * 1024 * 1024,
+            sync_batch: 128,
+            sync_interval: Duration::from_millis(100),
+        }
+    }
+
+    pub fn with_segment_size(mut self, segment_size: u64) -> Self {
+        self.segment_size = segment_size;
+        self
+    }
+
+    pub fn with_sync_batch(mut self, sync_batch: usize) -> Self {
+        self.sync_batch = sync_batch.max(1);
+        self
+    }
+
+    pub fn with_sync_interval(mut self, sync_interval: Duration) -> Self {
+        self.sync_interval = sync_interval;
+        self
+    }
+}
+
+/// The position up to which the records of a [DurableCollector] have been
+/// handed to a reporter, see [DurableCollector::checkpoint].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Checkpoint {
+    /// Segments before this one only hold records covered by the checkpoint.
+    segment_id: u64,
+}
+
+/// A [Collect] that appends records to a local segmented log before handing
+/// them to an inner [Collect], so records survive a crash.
+///
+/// Records are forwarded to the inner collector only once fsynced, and fsyncs
+/// are batched by [WalConfig::sync_batch] and [WalConfig::sync_interval]. On
+/// [DurableCollector::open], the segments not yet acknowledged are replayed
+/// into the inner collector.
+///
+/// If a write or fsync fails, the segment is cut back to its last fsynced
+/// frame and the records not yet fsynced are written again into a new
+/// segment, so a torn frame never hides the records after it. They are
+/// forwarded once an fsync succeeds.
+///
+/// A reporter drains the inner collector inside
+/// [DurableCollector::checkpoint] and, once the export succeeded, passes the
+/// checkpoint to [DurableCollector::ack] to delete the covered segments.
+/// The `ExportReporter` of `meter-reporter` does so when given the collector
+/// with `with_wal`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::wal::DurableCollector;
+/// use meter_core::wal::WalConfig;
+/// use meter_core::window::WindowConfig;
+/// use meter_core::window::WindowedCollector;
+///
+/// let dir = std::env::temp_dir().join(format!("meter-wal-doc-{}", std::process::id()));
+/// let config = WalConfig::new(&dir).with_sync_batch(1);
+///
+/// let window = WindowConfig::tumbling(std::time::Duration::from_secs(60));
+/// let inner = Arc::new(WindowedCollector::new(window));
+/// let durable = DurableCollector::open(config.clone(), inner).unwrap();
+/// durable.on_write(MeterRecord::new("greptime".into(), "public".into(), 10, 0));
+/// drop(durable);
+///
+/// // After a crash, the unacknowledged record is replayed.
+/// let inner = Arc::new(WindowedCollector::new(window));
+/// let durable = DurableCollector::open(config.clone(), inner.clone()).unwrap();
+/// let (checkpoint, aggregates) = durable.checkpoint(|| inner.flush()).unwrap();
+/// assert_eq!(aggregates[0].usage.write_units, 10);
+///
+/// // Once exported, the record is never replayed again.
+/// durable.ack(checkpoint).unwrap();
+/// drop(durable);
+/// let inner = Arc::new(WindowedCollector::new(window));
+/// let _durable = DurableCollector::open(config, inner.clone()).unwrap();
+/// assert!(inner.flush().is_empty());
+/// # std::fs::remove_dir_all(dir).unwrap();
+/// ```
+pub struct DurableCollector {
+    wal: Arc<Wal>,
+    flusher: Option<JoinHandle<()>>,
+}
+
+struct Wal {
+    config: WalConfig,
+    inner: Arc<dyn Collect>,
+    state: Mutex<State>,
+    closed: Mutex<bool>,
+    condvar: Condvar,
+}
+
+struct State {
+    segment_id: u64,
+    segment_len: u64,
+    /// The length of the segment at the last successful fsync.
+    synced_len: u64,
+    writer: BufWriter<File>,
+    pending: Vec<(MeterKind, MeterRecord)>,
+    last_sync: Instant,
+}
+
+impl DurableCollector {
+    /// Opens the log in [WalConfig::dir], replaying the unacknowledged
+    /// segments into `inner`.
+    pub fn open(config: WalConfig, inner: Arc<dyn Collect>) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+
+        let segments = list_segments(&config.dir)?;
+        for path in segments.values() {
+            replay_segment(path, inner.as_ref())?;
+        }
+
+        let segment_id = segments.keys().next_back().map_or(0, |id| id + 1);
+        let writer = create_segment(&config.dir, segment_id)?;
+
+        let wal = Arc::new(Wal {
+            config,
+            inner,
+            state: Mutex::new(State {
+                segment_id,
+                segment_len: 0,
+                synced_len: 0,
+                writer,
+                pending: Vec::new(),
+                last_sync: Instant::now(),
+            }),
+            closed: Mutex::new(false),
+            condvar: Condvar::new(),
+        });
+        let flusher = {
+            let wal = wal.clone();
+            std::thread::Builder::new()
+                .name("meter-wal".to_string())
+                .spawn(move || wal.run_flusher())?
+        };
+
+        Ok(Self {
+            wal,
+            flusher: Some(flusher),
+        })
+    }
+
+    /// Fsyncs the appended records and forwards them to the inner collector.
+    pub fn sync(&self) -> io::Result<()> {
+        let mut state = self.wal.state.lock();
+        self.wal.sync_locked(&mut state)
+    }
+
+    /// Syncs, seals the current segment and runs `drain` while no record can
+    /// be appended, so what `drain` takes from the inner collector is exactly
+    /// what the returned checkpoint covers.
+    pub fn checkpoint<T>(&self, drain: impl FnOnce() -> T) -> io::Result<(Checkpoint, T)> {
+        let mut state = self.wal.state.lock();
+        self.wal.roll(&mut state)?;
+
+        let checkpoint = Checkpoint {
+            segment_id: state.segment_id,
+        };
+        Ok((checkpoint, drain()))
+    }
+
+    /// Deletes the segments covered by `checkpoint`, after their records have
+    /// been exported successfully.
+    pub fn ack(&self, checkpoint: Checkpoint) -> io::Result<()> {
+        for (id, path) in list_segments(&self.wal.config.dir)? {
+            if id < checkpoint.segment_id {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DurableCollector {
+    fn drop(&mut self) {
+        *self.wal.closed.lock() = true;
+        self.wal.condvar.notify_all();
+        if let Some(flusher) = self.flusher.take() {
+            if flusher.join().is_err() {
+                error!("[meter]wal flusher thread panicked");
+            }
+        }
+        if let Err(e) = self.sync() {
+            error!("[meter]failed to sync wal on close: {}", e);
+        }
+    }
+}
+
+impl Wal {
+    /// Syncs the records that waited for [WalConfig::sync_interval] without
+    /// another append, until the collector is dropped.
+    fn run_flusher(&self) {
+        let interval = self.config.sync_interval.max(Duration::from_millis(1));
+        loop {
+            {
+                let mut closed = self.closed.lock();
+                if !*closed {
+                    self.condvar.wait_for(&mut closed, interval);
+                }
+                if *closed {
+                    return;
+                }
+            }
+
+            let mut state = self.state.lock();
+            if !state.pending.is_empty() && state.last_sync.elapsed() >= interval {
+                if let Err(e) = self.sync_locked(&mut state) {
+                    error!("[meter]failed to sync wal: {}", e);
+                }
+            }
+        }
+    }
+
+    fn append(&self, kind: MeterKind, record: MeterRecord) {
+        let mut state = self.state.lock();
+
+        let frame = encode_frame(kind, &record);
+        state.pending.push((kind, record));
+        let result = match state.writer.write_all(&frame) {
+            Ok(()) => {
+                state.segment_len += frame.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                error!("[meter]failed to append record to wal: {}", e);
+                self.recover(&mut state)
+            }
+        };
+
+        let result = result.and_then(|_| {
+            if state.pending.len() >= self.config.sync_batch
+                || state.last_sync.elapsed() >= self.config.sync_interval
+            {
+                self.sync_locked(&mut state)
+            } else {
+                Ok(())
+            }
+        });
+        let result = result.and_then(|_| {
+            if state.segment_len >= self.config.segment_size {
+                self.roll(&mut state)
+            } else {
+                Ok(())
+            }
+        });
+
+        if let Err(e) = result {
+            error!("[meter]failed to sync wal: {}", e);
+        }
+    }
+
+    /// Fsyncs the segment and forwards the pending records. If it fails, the
+    /// records stay pending until a later sync succeeds.
+    fn sync_locked(&self, state: &mut State) -> io::Result<()> {
+        state.last_sync = Instant::now();
+        let result = state
+            .writer
+            .flush()
+            .and_then(|_| state.writer.get_ref().sync_data());
+        if let Err(e) = result {
+            if let Err(e) = self.recover(state) {
+                error!("[meter]failed to recover wal: {}", e);
+            }
+            return Err(e);
+        }
+
+        state.synced_len = state.segment_len;
+        for (kind, record) in state.pending.drain(..) {
+            dispatch(self.inner.as_ref(), kind, record);
+        }
+        Ok(())
+    }
+
+    /// Recovers from a failed write or flush, which may have left a torn
+    /// frame that would hide every later frame of the segment on replay.
+    ///
+    /// The segment is cut back to its length at the last fsync and the
+    /// pending records are written again into a new segment.
+    fn recover(&self, state: &mut State) -> io::Result<()> {
+        let writer = create_segment(&self.config.dir, state.segment_id + 1)?;
+        // Whatever the old writer still buffers is written again below.
+        let (file, _) = std::mem::replace(&mut state.writer, writer).into_parts();
+        if let Err(e) = file.set_len(state.synced_len) {
+            warn!(
+                "[meter]failed to truncate wal segment {}, its records may be replayed twice: {}",
+                state.segment_id, e
+            );
+        }
+
+        state.segment_id += 1;
+        state.segment_len = 0;
+        state.synced_len = 0;
+        for (kind, record) in &state.pending {
+            let frame = encode_frame(*kind, record);
+            state.writer.write_all(&frame)?;
+            state.segment_len += frame.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn roll(&self, state: &mut State) -> io::Result<()> {
+        self.sync_locked(state)?;
+
+        state.segment_id += 1;
+        state.segment_len = 0;
+        state.synced_len = 0;
+        state.writer = create_segment(&self.config.dir, state.segment_id)?;
+        Ok(())
+    }
+}
+
+impl Collect for DurableCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.wal.append(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.wal.append(MeterKind::Read, record);
+    }
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}{}", id, SEGMENT_SUFFIX))
+}
+
+fn create_segment(dir: &Path, id: u64) -> io::Result<BufWriter<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, id))?;
+    // Persist the directory entry of the new segment as well.
+    File::open(dir)?.sync_all()?;
+    Ok(BufWriter::new(file))
+}
+
+fn list_segments(dir: &Path) -> io::Result<BTreeMap<u64, PathBuf>> {
+    let mut segments = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let id = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(SEGMENT_SUFFIX))
+            .and_then(|id| id.parse::<u64>().ok());
+        if let Some(id) = id {
+            segments.insert(id, path);
+        }
+    }
+    Ok(segments)
+}
+
+fn replay_segment(path: &Path, inner: &dyn Collect) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let mut buf = data.as_slice();
+
+    while !buf.is_empty() {
+        match decode_frame(&mut buf) {
+            Some((kind, record)) => dispatch(inner, kind, record),
+            None => {
+                // A torn write at the tail of a crashed segment.
+                warn!(
+                    "[meter]stop replaying corrupted wal segment: {}, {} bytes skipped",
+                    path.display(),
+                    buf.len()
+                );
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Encodes `[len: u32][crc32: u32][payload]`, little endian.
+fn encode_frame(kind: MeterKind, record: &MeterRecord) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(64);
+    payload.push(match kind {
+        MeterKind::Read => 0,
+        MeterKind::Write => 1,
+    });
+    payload.push(record.source);
+    payload.extend_from_slice(&record.value.to_le_bytes());
+    payload.extend_from_slice(&record.timestamp_millis.to_le_bytes());
+    put_str(&mut payload, &record.catalog);
+    put_str(&mut payload, &record.schema);
+    payload.extend_from_slice(&(record.labels.len() as u32).to_le_bytes());
+    for (key, value) in &record.labels {
+        put_str(&mut payload, key);
+        put_str(&mut payload, value);
+    }
+    if let Some(id) = record.id {
+        payload.extend_from_slice(&id.as_u128().to_le_bytes());
+    }
+
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Decodes a frame from the front of `buf`, advancing it. Returns `None` if
+/// the frame is truncated or corrupted.
+fn decode_frame(buf: &mut &[u8]) -> Option<(MeterKind, MeterRecord)> {
+    let len = get_u32(buf)? as usize;
+    let crc = get_u32(buf)?;
+    if buf.len() < len {
+        return None;
+    }
+    let (mut payload, rest) = buf.split_at(len);
+    if crc32fast::hash(payload) != crc {
+        return None;
+    }
+    *buf = rest;
+
+    let p = &mut payload;
+    let kind = match get_u8(p)? {
+        0 => MeterKind::Read,
+        1 => MeterKind::Write,
+        _ => return None,
+    };
+    let source = get_u8(p)?;
+    let value = get_u64(p)?;
+    let timestamp_millis = get_u64(p)?;
+    let catalog = get_str(p)?;
+    let schema = get_str(p)?;
+
+    let mut record =
+        MeterRecord::new(catalog, schema, value, source).with_timestamp_millis(timestamp_millis);
+    for _ in 0..get_u32(p)? {
+        let key = get_str(p)?;
+        let value = get_str(p)?;
+        record.labels.insert(key, value);
+    }
+    // The ID is optional and takes the rest of the payload.
+    if !p.is_empty() {
+        let id = take(p, 16).map(|b| u128::from_le_bytes(b.try_into().unwrap()))?;
+        record.id = Some(RecordId::from_u128(id));
+    }
+
+    Some((kind, record))
+}
@@ -0,0 +1,367 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::cardinality::CardinalityLimit;
+use crate::cardinality::OVERFLOW_KEY;
+use crate::collect::Collect;
+use crate::data::MeterRecord;
+
+/// The size, hop and lateness policy of a [WindowedCollector].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowConfig {
+    /// The length of each window.
+    pub size: Duration,
+
+    /// The distance between the starts of two adjacent windows.
+    ///
+    /// Equal to `size` for tumbling windows and smaller than `size` for
+    /// hopping windows, in which case a record belongs to several windows.
+    pub hop: Duration,
+
+    /// How long a window is kept open after the watermark has passed its end.
+    pub allowed_lateness: Duration,
+}
+
+impl WindowConfig {
+    /// Non-overlapping windows of `size`.
+    pub fn tumbling(size: Duration) -> Self {
+        Self::hopping(size, size)
+    }
+
+    /// Windows of `size` that start every `hop`, at most `size` so that
+    /// every timestamp falls into a window.
+    ///
+    /// Every record is counted in each of the `size / hop` windows it falls
+    /// into, so hopping aggregates must not be summed: exporters that add up
+    /// aggregates, e.g. into counters or billing totals, need tumbling
+    /// windows.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// use meter_core::window::WindowConfig;
+    ///
+    /// let config = WindowConfig::hopping(Duration::from_secs(10), Duration::from_secs(5));
+    /// assert_eq!(config.assign(12_000).count(), 2);
+    /// ```
+    pub fn hopping(size: Duration, hop: Duration) -> Self {
+        assert!(!size.is_zero(), "window size must be positive");
+        assert!(!hop.is_zero(), "window hop must be positive");
+        assert!(hop <= size, "window hop must not exceed the size");
+
+        Self {
+            size,
+            hop,
+            allowed_lateness: Duration::ZERO,
+        }
+    }
+
+    pub fn with_allowed_lateness(mut self, allowed_lateness: Duration) -> Self {
+        self.allowed_lateness = allowed_lateness;
+        self
+    }
+
+    fn size_millis(&self) -> u64 {
+        self.size.as_millis() as u64
+    }
+
+    fn hop_millis(&self) -> u64 {
+        self.hop.as_millis() as u64
+    }
+
+    fn lateness_millis(&self) -> u64 {
+        self.allowed_lateness.as_millis() as u64
+    }
+
+    /// Returns the windows that contain `timestamp_millis`, latest first.
+    pub fn assign(&self, timestamp_millis: u64) -> impl Iterator<Item = Window> {
+        let size = self.size_millis();
+        let hop = self.hop_millis();
+        let last_start = timestamp_millis - timestamp_millis % hop;
+
+        (0..)
+            .map_while(move |i: u64| last_start.checked_sub(i * hop))
+            .map(move |start| Window::new(start, start + size))
+            .take_while(move |window| window.contains(timestamp_millis))
+    }
+}
+
+/// A half-open time range `[start_millis, end_millis)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Window {
+    pub start_millis: u64,
+    pub end_millis: u64,
+}
+
+impl Window {
+    pub fn new(start_millis: u64, end_millis: u64) -> Self {
+        Self {
+            start_millis,
+            end_millis,
+        }
+    }
+
+    pub fn contains(&self, timestamp_millis: u64) -> bool {
+        self.start_millis <= timestamp_millis && timestamp_millis < self.end_millis
+    }
+}
+
+/// The key records are grouped by inside a window.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupKey {
+    pub catalog: String,
+    pub schema: String,
+    pub source: u8,
+}
+
+impl GroupKey {
+    pub fn new(catalog: impl Into<String>, schema: impl Into<String>, source: u8) -> Self {
+        Self {
+            catalog: catalog.into(),
+            schema: schema.into(),
+            source,
+        }
+    }
+
+    pub fn of(record: &MeterRecord) -> Self {
+        Self::new(record.catalog.clone(), record.schema.clone(), record.source)
+    }
+
+    /// The bucket of records beyond the cardinality limit, per source.
+    pub fn overflow(source: u8) -> Self {
+        Self::new(OVERFLOW_KEY, OVERFLOW_KEY, source)
+    }
+}
+
+/// Read and write units accumulated for one group key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub read_units: u64,
+    pub write_units: u64,
+    pub read_count: u64,
+    pub write_count: u64,
+}
+
+impl Usage {
+    pub fn add_read(&mut self, value: u64) {
+        self.read_units = self.read_units.saturating_add(value);
+        self.read_count = self.read_count.saturating_add(1);
+    }
+
+    pub fn add_write(&mut self, value: u64) {
+        self.write_units = self.write_units.saturating_add(value);
+        self.write_count = self.write_count.saturating_add(1);
+    }
+
+    pub fn merge(&mut self, other: &Usage) {
+        self.read_units = self.read_units.saturating_add(other.read_units);
+        self.write_units = self.write_units.saturating_add(other.write_units);
+        self.read_count = self.read_count.saturating_add(other.read_count);
+        self.write_count = self.write_count.saturating_add(other.write_count);
+    }
+}
+
+/// The usage of one group key in a closed window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowAggregate {
+    pub window: Window,
+    pub key: GroupKey,
+    pub usage: Usage,
+}
+
+/// A [Collect] that aggregates records into aligned tumbling or hopping
+/// windows by their timestamp.
+///
+/// The watermark is the largest timestamp observed so far (or set by
+/// [WindowedCollector::advance_watermark]). A window is closed once the
+/// watermark reaches its end plus the allowed lateness; records that only
+/// fall into closed windows are dropped and counted as late.
+///
+/// Aggregates of hopping windows overlap and must not be summed, see
+/// [WindowConfig::hopping].
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_core::window::WindowConfig;
+/// use meter_core::window::WindowedCollector;
+///
+/// let collector = WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(1)));
+///
+/// let record = |ts| MeterRecord::new("greptime".into(), "public".into(), 10, 0).with_timestamp_millis(ts);
+/// collector.on_write(record(100));
+/// collector.on_write(record(900));
+/// collector.on_write(record(1_000));
+///
+/// let closed = collector.drain_closed();
+/// assert_eq!(closed.len(), 1);
+/// assert_eq!(closed[0].usage.write_units, 20);
+/// ```
+pub struct WindowedCollector {
+    config: WindowConfig,
+    limit: CardinalityLimit,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    watermark: u64,
+    open: BTreeMap<Window, HashMap<GroupKey, Usage>>,
+    closed: Vec<WindowAggregate>,
+    late_records: u64,
+}
+
+impl WindowedCollector {
+    pub fn new(config: WindowConfig) -> Self {
+        Self {
+            config,
+            limit: CardinalityLimit::unlimited(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Caps the number of distinct group keys in each window.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.limit = CardinalityLimit::new(max_keys);
+        self
+    }
+
+    /// The number of records accounted to [GroupKey::overflow].
+    pub fn overflowed_records(&self) -> u64 {
+        self.limit.overflowed_records()
+    }
+
+    pub fn config(&self) -> &WindowConfig {
+        &self.config
+    }
+
+    /// The current watermark, in milliseconds since the Unix epoch.
+    pub fn watermark(&self) -> u64 {
+        self.state.lock().watermark
+    }
+
+    /// The number of records dropped because all their windows were closed.
+    pub fn late_records(&self) -> u64 {
+        self.state.lock().late_records
+    }
+
+    /// Moves the watermark forward, e.g. to the wall clock when no records
+    /// arrive, closing the windows it passes.
+    pub fn advance_watermark(&self, watermark_millis: u64) {
+        let mut state = self.state.lock();
+        if watermark_millis > state.watermark {
+            state.watermark = watermark_millis;
+            self.close_expired(&mut state);
+        }
+    }
+
+    /// Takes the windows closed so far, ordered by window start.
+    pub fn drain_closed(&self) -> Vec<WindowAggregate> {
+        std::mem::take(&mut self.state.lock().closed)
+    }
+
+    /// Closes every open window regardless of the watermark and takes all
+    /// closed windows. Intended for shutdown.
+    pub fn flush(&self) -> Vec<WindowAggregate> {
+        let mut state = self.state.lock();
+        let open = std::mem::take(&mut state.open);
+        for (window, groups) in open {
+            Self::emit(&mut state.closed, window, groups);
+        }
+        std::mem::take(&mut state.closed)
+    }
+
+    fn is_closed(&self, window: &Window, watermark: u64) -> bool {
+        window
+            .end_millis
+            .saturating_add(self.config.lateness_millis())
+            <= watermark
+    }
+
+    fn close_expired(&self, state: &mut State) {
+        while let Some(entry) = state.open.first_entry() {
+            if !self.is_closed(entry.key(), state.watermark) {
+                break;
+            }
+            let (window, groups) = entry.remove_entry();
+            Self::emit(&mut state.closed, window, groups);
+        }
+    }
+
+    fn emit(closed: &mut Vec<WindowAggregate>, window: Window, groups: HashMap<GroupKey, Usage>) {
+        let mut aggregates = groups
+            .into_iter()
+            .map(|(key, usage)| WindowAggregate { window, key, usage })
+            .collect::<Vec<_>>();
+        aggregates.sort_by(|a, b| a.key.cmp(&b.key));
+        closed.extend(aggregates);
+    }
+
+    fn record(&self, record: MeterRecord, add: impl Fn(&mut Usage, u64)) {
+        let mut state = self.state.lock();
+        let timestamp = record.timestamp_millis;
+        let key = GroupKey::of(&record);
+
+        let mut accepted = false;
+        for window in self.config.assign(timestamp) {
+            if self.is_closed(&window, state.watermark) {
+                continue;
+            }
+            let groups = state.open.entry(window).or_default();
+            let key = if self.limit.admit(groups.contains_key(&key), groups.len()) {
+                key.clone()
+            } else {
+                GroupKey::overflow(key.source)
+            };
+            add(groups.entry(key).or_default(), record.value);
+            accepted = true;
+        }
+
+        if !accepted {
+            state.late_records += 1;
+            warn!(
+                "[meter]drop late record, catalog: {}, schema: {}, timestamp: {}, watermark: {}",
+                record.catalog, record.schema, timestamp, state.watermark
+            );
+        }
+
+        if timestamp > state.watermark {
+            state.watermark = timestamp;
+            self.close_expired(&mut state);
+        }
+    }
+}
+
+impl Collect for WindowedCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.record(record, Usage::add_write);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.record(record, Usage::add_read);
+    }
+}
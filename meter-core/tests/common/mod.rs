@@ -0,0 +1,58 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers shared by the integration tests.
+
+// Every test crate uses its own subset of the helpers.
+#![allow(dead_code)]
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use parking_lot::Mutex;
+
+/// A [Collect] that keeps every record it receives, in order.
+#[derive(Default)]
+pub struct Recorder {
+    records: Mutex<Vec<(MeterKind, MeterRecord)>>,
+}
+
+impl Recorder {
+    pub fn records(&self) -> Vec<(MeterKind, MeterRecord)> {
+        self.records.lock().clone()
+    }
+
+    /// The `catalog.schema` of every record received.
+    pub fn schemas(&self) -> Vec<String> {
+        self.records
+            .lock()
+            .iter()
+            .map(|(_, r)| format!("{}.{}", r.catalog, r.schema))
+            .collect()
+    }
+}
+
+impl Collect for Recorder {
+    fn on_write(&self, record: MeterRecord) {
+        self.records.lock().push((MeterKind::Write, record));
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.records.lock().push((MeterKind::Read, record));
+    }
+}
+
+pub fn record(catalog: &str, schema: &str, value: u64) -> MeterRecord {
+    MeterRecord::new(catalog.to_string(), schema.to_string(), value, 0)
+}
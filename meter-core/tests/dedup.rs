@@ -0,0 +1,107 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::dedup::DedupCollector;
+use meter_core::dedup::Deduplicator;
+use meter_core::id::RecordId;
+use meter_core::window::WindowConfig;
+use meter_core::window::WindowedCollector;
+
+fn id(timestamp_millis: u64, sequence: u64) -> RecordId {
+    RecordId::new(timestamp_millis, 1, 0, sequence)
+}
+
+#[test]
+fn drops_duplicates_within_the_window() {
+    let dedup = Deduplicator::new(Duration::from_secs(10), 100);
+    assert!(dedup.check(id(1_000, 0)));
+    assert!(dedup.check(id(1_000, 1)));
+    assert!(!dedup.check(id(1_000, 0)));
+    assert!(!dedup.check(id(1_000, 1)));
+    assert_eq!(dedup.duplicates(), 2);
+}
+
+#[test]
+fn forgets_ids_older_than_the_window() {
+    let dedup = Deduplicator::new(Duration::from_secs(10), 100);
+    assert!(dedup.check(id(1_000, 0)));
+    assert!(dedup.check(id(11_000, 0)));
+    // Still at the edge of the window.
+    assert!(!dedup.check(id(1_000, 0)));
+
+    assert!(dedup.check(id(11_001, 0)));
+    // Beyond the window, it can no longer be verified and is accepted.
+    assert!(dedup.check(id(1_000, 0)));
+}
+
+#[test]
+fn evicts_the_oldest_ids_at_capacity() {
+    let dedup = Deduplicator::new(Duration::from_secs(600), 3);
+    for sequence in 0..4 {
+        assert!(dedup.check(id(1_000 + sequence, sequence)));
+    }
+
+    // The oldest was evicted, the three newest are still remembered.
+    assert!(dedup.check(id(1_000, 0)));
+    for sequence in 2..4 {
+        assert!(!dedup.check(id(1_000 + sequence, sequence)));
+    }
+}
+
+#[test]
+fn keeps_records_without_ids() {
+    let dedup = Deduplicator::new(Duration::from_secs(10), 100);
+    let record = MeterRecord::new("greptime".into(), "public".into(), 1, 0);
+    let mut records = vec![
+        record.clone(),
+        record.clone().with_id(id(0, 0)),
+        record.clone().with_id(id(0, 0)),
+        record,
+    ];
+
+    dedup.retain_unique(&mut records);
+    assert_eq!(records.len(), 3);
+    assert_eq!(dedup.duplicates(), 1);
+}
+
+#[test]
+fn collector_forwards_reads_and_writes_once() {
+    let inner = Arc::new(WindowedCollector::new(WindowConfig::tumbling(
+        Duration::from_secs(60),
+    )));
+    let dedup = DedupCollector::new(
+        Deduplicator::new(Duration::from_secs(600), 100),
+        inner.clone(),
+    );
+    let record = |sequence| {
+        MeterRecord::new("greptime".into(), "public".into(), 10, 0)
+            .with_timestamp_millis(0)
+            .with_id(id(0, sequence))
+    };
+
+    dedup.on_read(record(0));
+    dedup.on_read(record(0));
+    dedup.on_write(record(1));
+    // The same ID is a duplicate whatever the kind.
+    dedup.on_write(record(0));
+
+    let usage = inner.flush()[0].usage;
+    assert_eq!((usage.read_units, usage.write_units), (10, 10));
+    assert_eq!(dedup.deduplicator().duplicates(), 2);
+}
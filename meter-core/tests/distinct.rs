@@ -0,0 +1,127 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::data::SchemaId;
+use meter_core::distinct::DistinctCollector;
+use meter_core::distinct::DistinctSummary;
+use meter_core::sketch::hll::merge_by_label;
+use meter_core::sketch::hll::HyperLogLog;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+fn hll(precision: u8, values: impl IntoIterator<Item = u64>) -> HyperLogLog {
+    let mut hll = HyperLogLog::new(precision);
+    for value in values {
+        hll.insert(format!("table_{}", value).as_bytes());
+    }
+    hll
+}
+
+fn summary(precision: u8, tables: &[&str]) -> DistinctSummary {
+    let collector = DistinctCollector::new(["table"]).with_precision(precision);
+    for table in tables {
+        let record = MeterRecord::new("greptime".into(), "public".into(), 1, 0);
+        collector.on_write(record.with_label("table", *table));
+    }
+    collector
+        .drain()
+        .remove(&SchemaId::new("greptime", "public"))
+        .unwrap()
+}
+
+#[test]
+fn estimates_are_within_three_standard_errors() {
+    for precision in [10, 14] {
+        let standard_error = 1.04 / ((1u64 << precision) as f64).sqrt();
+        for n in [10, 100, 1_000, 10_000, 100_000] {
+            let estimate = hll(precision, 0..n).estimate() as f64;
+            let error = (estimate - n as f64).abs() / n as f64;
+            assert!(
+                error <= 3.0 * standard_error,
+                "precision {}: estimate {} of {} is off by {}",
+                precision,
+                estimate,
+                n,
+                error
+            );
+        }
+    }
+}
+
+#[test]
+fn duplicates_are_counted_once() {
+    let once = hll(12, 0..1_000);
+    let twice = hll(12, (0..1_000).chain(0..1_000));
+    assert_eq!(once, twice);
+}
+
+proptest! {
+    #[test]
+    fn merge_equals_a_single_sketch_of_the_union(
+        a in vec(0..5_000u64, 0..500),
+        b in vec(0..5_000u64, 0..500),
+    ) {
+        let mut ab = hll(8, a.clone());
+        ab.merge(&hll(8, b.clone())).unwrap();
+        let mut ba = hll(8, b.clone());
+        ba.merge(&hll(8, a.clone())).unwrap();
+
+        prop_assert_eq!(&ab, &ba);
+        prop_assert_eq!(ab, hll(8, a.into_iter().chain(b)));
+    }
+}
+
+#[test]
+fn merge_rejects_different_precision() {
+    let mut a = hll(10, 0..100);
+    assert!(a.merge(&hll(12, 0..100)).is_err());
+    assert_eq!(a, hll(10, 0..100));
+}
+
+#[test]
+fn merges_by_label() {
+    let mut into = BTreeMap::from([("table".to_string(), hll(10, 0..100))]);
+    let from = BTreeMap::from([
+        ("table".to_string(), hll(10, 50..150)),
+        ("region".to_string(), hll(10, 0..3)),
+    ]);
+    merge_by_label(&mut into, &from).unwrap();
+    assert_eq!(into["table"], hll(10, 0..150));
+    assert_eq!(into["region"], hll(10, 0..3));
+
+    // Nothing is merged if any label has a different precision.
+    let mismatched = BTreeMap::from([
+        ("region".to_string(), hll(10, 3..6)),
+        ("table".to_string(), hll(12, 0..1)),
+    ]);
+    let before = into.clone();
+    assert!(merge_by_label(&mut into, &mismatched).is_err());
+    assert_eq!(into, before);
+}
+
+#[test]
+fn merges_summaries_of_other_nodes() {
+    let mut merged = summary(10, &["t1", "t2"]);
+    merged.merge(&summary(10, &["t2", "t3"])).unwrap();
+    assert_eq!(merged.usage.write_units, 4);
+    assert_eq!(merged.distinct_counts()["table"], 3);
+
+    let before = merged.clone();
+    assert!(merged.merge(&summary(12, &["t4"])).is_err());
+    assert_eq!(merged, before);
+}
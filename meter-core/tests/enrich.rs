@@ -0,0 +1,151 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use common::record;
+use common::Recorder;
+use meter_core::data::MeterRecord;
+use meter_core::enrich::scope;
+use meter_core::enrich::ContextLabels;
+use meter_core::enrich::StaticLabels;
+use meter_core::registry::Registry;
+
+fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn registry() -> (Registry, Arc<Recorder>) {
+    let registry = Registry::default();
+    let recorder = Arc::new(Recorder::default());
+    registry.set_collector(recorder.clone());
+    (registry, recorder)
+}
+
+fn last_labels(recorder: &Recorder) -> BTreeMap<String, String> {
+    recorder.records().pop().unwrap().1.labels
+}
+
+#[test]
+fn record_labels_win_over_enrichers() {
+    let (registry, recorder) = registry();
+    registry.add_enricher(Arc::new(
+        StaticLabels::new().with_label("region", "us-west-2"),
+    ));
+    registry.add_enricher(Arc::new(ContextLabels));
+
+    scope(labels(&[("region", "eu-central-1")]), || {
+        registry.record_write(record("greptime", "public", 1).with_label("region", "local"));
+    });
+    assert_eq!(last_labels(&recorder), labels(&[("region", "local")]));
+}
+
+#[test]
+fn earlier_enrichers_win_over_later_ones() {
+    let (registry, recorder) = registry();
+    registry.add_enricher(Arc::new(
+        StaticLabels::new().with_label("region", "us-west-2"),
+    ));
+    registry.add_enricher(Arc::new(ContextLabels));
+    registry.add_enricher(Arc::new(
+        StaticLabels::new()
+            .with_label("region", "ignored")
+            .with_label("cluster", "c1"),
+    ));
+
+    scope(
+        labels(&[("region", "eu-central-1"), ("user", "alice")]),
+        || {
+            registry.record_read(record("greptime", "public", 1));
+        },
+    );
+    assert_eq!(
+        last_labels(&recorder),
+        labels(&[
+            ("cluster", "c1"),
+            ("region", "us-west-2"),
+            ("user", "alice")
+        ])
+    );
+}
+
+#[test]
+fn closures_run_in_the_order_added_and_may_overwrite() {
+    let (registry, recorder) = registry();
+    registry.add_enricher(Arc::new(StaticLabels::new().with_label("tier", "free")));
+    registry.add_enricher(Arc::new(|record: &mut MeterRecord| {
+        if record.catalog == "enterprise" {
+            record.labels.insert("tier".to_string(), "paid".to_string());
+        }
+    }));
+
+    registry.record_write(record("enterprise", "public", 1));
+    assert_eq!(last_labels(&recorder), labels(&[("tier", "paid")]));
+}
+
+#[test]
+fn innermost_scope_wins() {
+    let (registry, recorder) = registry();
+    registry.add_enricher(Arc::new(ContextLabels));
+
+    scope(labels(&[("user", "alice"), ("app", "web")]), || {
+        scope(labels(&[("user", "bob")]), || {
+            registry.record_write(record("greptime", "public", 1));
+        });
+        assert_eq!(
+            last_labels(&recorder),
+            labels(&[("app", "web"), ("user", "bob")])
+        );
+
+        registry.record_write(record("greptime", "public", 1));
+        assert_eq!(
+            last_labels(&recorder),
+            labels(&[("app", "web"), ("user", "alice")])
+        );
+    });
+
+    registry.record_write(record("greptime", "public", 1));
+    assert!(last_labels(&recorder).is_empty());
+}
+
+#[test]
+fn scopes_end_on_panic() {
+    let (registry, recorder) = registry();
+    registry.add_enricher(Arc::new(ContextLabels));
+
+    let result = std::panic::catch_unwind(|| scope(labels(&[("user", "alice")]), || panic!()));
+    assert!(result.is_err());
+
+    registry.record_write(record("greptime", "public", 1));
+    assert!(last_labels(&recorder).is_empty());
+}
+
+#[test]
+fn scopes_are_per_thread() {
+    let (registry, recorder) = registry();
+    registry.add_enricher(Arc::new(ContextLabels));
+
+    scope(labels(&[("user", "alice")]), || {
+        std::thread::scope(|s| {
+            s.spawn(|| registry.record_write(record("greptime", "public", 1)));
+        });
+    });
+    assert!(last_labels(&recorder).is_empty());
+}
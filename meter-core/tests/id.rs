@@ -0,0 +1,33 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use meter_core::id::RecordIdGenerator;
+
+#[test]
+fn concurrent_node_and_epoch_updates_are_both_kept() {
+    for _ in 0..100 {
+        let generator = Arc::new(RecordIdGenerator::new(0, 0));
+        let node = {
+            let generator = generator.clone();
+            std::thread::spawn(move || generator.set_node_id(7))
+        };
+        generator.set_epoch(9);
+        node.join().unwrap();
+
+        let id = generator.next(1);
+        assert_eq!((id.node_id(), id.epoch()), (7, 9));
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::sync::Arc;
+
+use common::record;
+use common::Recorder;
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_core::layer::Filter;
+use meter_core::layer::Layer;
+use meter_core::layer::Map;
+use meter_core::layer::PipelineBuilder;
+use meter_core::layer::Sample;
+use meter_core::layer::Stage;
+
+/// Appends `name` to the `path` label of every record.
+fn tag(name: &'static str) -> Map<impl Fn(MeterKind, MeterRecord) -> MeterRecord> {
+    Map(move |_, mut record: MeterRecord| {
+        let path = record.labels.entry("path".to_string()).or_default();
+        path.push_str(name);
+        record
+    })
+}
+
+fn paths(recorder: &Recorder) -> Vec<String> {
+    recorder
+        .records()
+        .into_iter()
+        .map(|(_, r)| r.labels.get("path").cloned().unwrap_or_default())
+        .collect()
+}
+
+#[test]
+fn stages_run_in_the_order_added() {
+    let recorder = Arc::new(Recorder::default());
+    let pipeline = PipelineBuilder::new()
+        .stage(tag("a"))
+        .stage(tag("b"))
+        .stage(tag("c"))
+        .build(recorder.clone());
+
+    pipeline.on_write(record("greptime", "public", 1));
+    assert_eq!(paths(&recorder), vec!["abc"]);
+}
+
+/// A [Layer] tagging records on their way in.
+struct Tagging(&'static str);
+
+impl Layer for Tagging {
+    fn layer(&self, inner: Arc<dyn Collect>) -> Arc<dyn Collect> {
+        PipelineBuilder::new().stage(tag(self.0)).build(inner)
+    }
+}
+
+#[test]
+fn layers_and_stages_nest_in_the_order_added() {
+    let recorder = Arc::new(Recorder::default());
+    let pipeline = PipelineBuilder::new()
+        .layer(Tagging("outer"))
+        .stage(tag("-stage-"))
+        .layer(Tagging("inner"))
+        .build(recorder.clone());
+
+    pipeline.on_read(record("greptime", "public", 1));
+    assert_eq!(paths(&recorder), vec!["outer-stage-inner"]);
+    assert_eq!(recorder.records()[0].0, MeterKind::Read);
+}
+
+#[test]
+fn filters_see_what_earlier_stages_did() {
+    let recorder = Arc::new(Recorder::default());
+    let has_path = |_: MeterKind, record: &MeterRecord| record.labels.contains_key("path");
+    let filter_first = PipelineBuilder::new()
+        .stage(Filter(has_path))
+        .stage(tag("a"))
+        .build(recorder.clone());
+    let map_first = PipelineBuilder::new()
+        .stage(tag("a"))
+        .stage(Filter(has_path))
+        .build(recorder.clone());
+
+    filter_first.on_write(record("greptime", "public", 1));
+    assert!(recorder.records().is_empty());
+    map_first.on_write(record("greptime", "public", 1));
+    assert_eq!(paths(&recorder), vec!["a"]);
+}
+
+#[test]
+fn stages_are_testable_alone() {
+    let filter = Filter(|kind, _: &MeterRecord| kind == MeterKind::Read);
+    assert!(filter
+        .process(MeterKind::Read, record("greptime", "public", 1))
+        .is_some());
+    assert!(filter
+        .process(MeterKind::Write, record("greptime", "public", 1))
+        .is_none());
+}
+
+#[test]
+fn sampling_keeps_one_in_n_and_scales_values() {
+    let sample = Sample::one_in(3);
+    let kept = (0..9)
+        .filter_map(|_| sample.process(MeterKind::Write, record("greptime", "public", 10)))
+        .collect::<Vec<_>>();
+
+    assert_eq!(kept.len(), 3);
+    assert!(kept.iter().all(|record| record.value == 30));
+
+    // Scaling saturates instead of overflowing.
+    let sample = Sample::one_in(2);
+    let record = sample.process(MeterKind::Write, record("greptime", "public", u64::MAX));
+    assert_eq!(record.unwrap().value, u64::MAX);
+}
@@ -0,0 +1,140 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_core::data::SchemaId;
+use meter_core::quantile::QuantileCollector;
+use meter_core::sketch::ddsketch::DDSketch;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+const QS: [f64; 6] = [0.0, 0.25, 0.5, 0.9, 0.99, 1.0];
+
+fn sketch(relative_accuracy: f64, max_bins: usize, values: &[u64]) -> DDSketch {
+    let mut sketch = DDSketch::new(relative_accuracy, max_bins);
+    for value in values {
+        sketch.add(*value);
+    }
+    sketch
+}
+
+/// The value of rank `q * (n - 1)` of the sorted `values`.
+fn exact(values: &[u64], q: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted[(q * (sorted.len() - 1) as f64) as usize] as f64
+}
+
+fn assert_within(estimate: f64, exact: f64, relative_accuracy: f64) {
+    // A little slack for the floating point rounding of the bin boundaries.
+    let bound = exact * relative_accuracy * (1.0 + 1e-9);
+    assert!(
+        (estimate - exact).abs() <= bound,
+        "estimate {} of {} is off by more than {}",
+        estimate,
+        exact,
+        relative_accuracy
+    );
+}
+
+proptest! {
+    #[test]
+    fn quantiles_are_within_relative_accuracy(
+        relative_accuracy in prop_oneof![Just(0.01), Just(0.05)],
+        values in vec(prop_oneof![0..1_000u64, 0..u64::MAX], 1..300),
+    ) {
+        let sketch = sketch(relative_accuracy, 4096, &values);
+        for q in QS {
+            assert_within(sketch.quantile(q).unwrap(), exact(&values, q), relative_accuracy);
+        }
+        prop_assert_eq!(sketch.count(), values.len() as u64);
+        prop_assert_eq!(sketch.min(), values.iter().min().copied());
+        prop_assert_eq!(sketch.max(), values.iter().max().copied());
+    }
+
+    #[test]
+    fn merged_quantiles_are_within_relative_accuracy(
+        a in vec(1..100_000u64, 1..200),
+        b in vec(1..100_000u64, 1..200),
+    ) {
+        let mut merged = sketch(0.01, 4096, &a);
+        merged.merge(&sketch(0.01, 4096, &b)).unwrap();
+
+        let all = [a, b].concat();
+        for q in QS {
+            assert_within(merged.quantile(q).unwrap(), exact(&all, q), 0.01);
+        }
+        prop_assert_eq!(merged, sketch(0.01, 4096, &all));
+    }
+}
+
+#[test]
+fn collapsing_keeps_the_high_quantiles() {
+    let values = (1..=10_000).collect::<Vec<u64>>();
+    let sketch = sketch(0.01, 64, &values);
+
+    assert_eq!(sketch.count(), 10_000);
+    for q in [0.9, 0.99, 0.999, 1.0] {
+        assert_within(sketch.quantile(q).unwrap(), exact(&values, q), 0.01);
+    }
+    assert_eq!(sketch.max(), Some(10_000));
+    // The lowest bins were collapsed into one, so the low quantiles are lost.
+    let p10 = sketch.quantile(0.1).unwrap();
+    assert!((p10 - 1_000.0).abs() > 1_000.0 * 0.01);
+}
+
+#[test]
+fn merge_rejects_different_relative_accuracy() {
+    let mut a = sketch(0.01, 2048, &[1, 2, 3]);
+    let b = sketch(0.02, 2048, &[4]);
+
+    assert!(a.merge(&b).is_err());
+    assert_eq!(a, sketch(0.01, 2048, &[1, 2, 3]));
+}
+
+#[test]
+fn collector_keeps_a_sketch_per_schema_and_kind() {
+    let collector = QuantileCollector::new(0.01, 2048);
+    for value in 1..=100 {
+        collector.on_read(MeterRecord::new(
+            "greptime".into(),
+            "public".into(),
+            value,
+            0,
+        ));
+        collector.on_write(MeterRecord::new(
+            "greptime".into(),
+            "tenant".into(),
+            value * 10,
+            0,
+        ));
+    }
+
+    let reads = collector.quantiles(MeterKind::Read, &[0.5]);
+    assert_eq!(reads.len(), 1);
+    assert_within(reads[&SchemaId::new("greptime", "public")][0], 50.0, 0.01);
+    let writes = collector.sketches(MeterKind::Write);
+    assert_eq!(
+        writes[&SchemaId::new("greptime", "tenant")].max(),
+        Some(1_000)
+    );
+}
+
+#[test]
+#[should_panic(expected = "relative accuracy must be in (0, 1)")]
+fn collector_rejects_bad_settings_up_front() {
+    QuantileCollector::new(1.5, 2048);
+}
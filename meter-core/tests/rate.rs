@@ -0,0 +1,73 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::rate::RateCollector;
+use meter_core::rate::RateConfig;
+
+fn read(collector: &RateCollector, schema: &str, value: u64) {
+    let record = MeterRecord::new("greptime".to_string(), schema.to_string(), value, 0);
+    collector.on_read(record.with_timestamp_millis(0));
+}
+
+#[test]
+fn overflowed_schemas_share_the_overflow_rate() {
+    let collector = RateCollector::new(RateConfig::default()).with_max_keys(1);
+    read(&collector, "public", 10);
+    read(&collector, "tenant1", 100);
+    read(&collector, "tenant2", 200);
+
+    let rate = |schema| {
+        collector
+            .current_rate_at("greptime", schema, 0)
+            .read_units_per_sec
+    };
+    assert_eq!(rate("public"), 1.0);
+    // Both overflowed schemas, and any other unseen one, see the shared bucket.
+    assert_eq!(rate("tenant1"), 30.0);
+    assert_eq!(rate("tenant2"), 30.0);
+    assert_eq!(rate("tenant3"), 30.0);
+    assert_eq!(collector.overflowed_records(), 2);
+}
+
+#[test]
+fn unseen_schemas_have_no_rate_below_the_cap() {
+    let collector = RateCollector::new(RateConfig::default()).with_max_keys(2);
+    read(&collector, "public", 10);
+
+    assert_eq!(
+        collector
+            .current_rate_at("greptime", "tenant", 0)
+            .read_units_per_sec,
+        0.0
+    );
+}
+
+#[test]
+fn rates_saturate_on_large_units() {
+    let collector = RateCollector::new(RateConfig::default());
+    for ts in [0, 1_000] {
+        let record = MeterRecord::new("greptime".to_string(), "public".to_string(), u64::MAX, 0);
+        collector.on_read(record.clone().with_timestamp_millis(ts));
+        collector.on_read(record.with_timestamp_millis(ts));
+    }
+
+    assert_eq!(
+        collector
+            .current_rate_at("greptime", "public", 1_000)
+            .read_units_per_sec,
+        u64::MAX as f64 / 10.0
+    );
+}
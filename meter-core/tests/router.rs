@@ -0,0 +1,121 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::sync::Arc;
+
+use common::record;
+use common::Recorder;
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::router::Glob;
+use meter_core::router::RouteRule;
+use meter_core::router::RouterCollector;
+
+#[test]
+fn globs_match_wildcards() {
+    let cases = [
+        ("*", "", true),
+        ("*", "anything", true),
+        ("greptime_*", "greptime_private", true),
+        ("greptime_*", "greptime", false),
+        ("*_private", "greptime_private", true),
+        ("g?eptime", "greptime", true),
+        ("g?eptime", "geptime", false),
+        ("a*b*c", "aXXbYYc", true),
+        ("a*b*c", "aXXbYY", false),
+        ("a*a", "aaa", true),
+        ("", "", true),
+        ("", "a", false),
+        ("public", "public", true),
+        ("public", "public1", false),
+        ("*数据*", "时序数据库", true),
+    ];
+    for (pattern, text, expected) in cases {
+        assert_eq!(
+            Glob::new(pattern).matches(text),
+            expected,
+            "{} ~ {}",
+            pattern,
+            text
+        );
+    }
+}
+
+#[test]
+fn first_matching_rule_wins() {
+    let private = Arc::new(Recorder::default());
+    let metrics = Arc::new(Recorder::default());
+    let default = Arc::new(Recorder::default());
+    let router = RouterCollector::new(vec![default.clone()])
+        .with_rule(RouteRule::new(vec![private.clone()]).catalog("greptime_private*"))
+        .with_rule(RouteRule::new(vec![metrics.clone()]).schema("metrics_*"));
+
+    router.on_write(record("greptime_private", "metrics_1", 1));
+    router.on_write(record("customer", "metrics_2", 1));
+    router.on_write(record("customer", "public", 1));
+
+    assert_eq!(private.schemas(), vec!["greptime_private.metrics_1"]);
+    assert_eq!(metrics.schemas(), vec!["customer.metrics_2"]);
+    assert_eq!(default.schemas(), vec!["customer.public"]);
+}
+
+#[test]
+fn rules_match_every_condition() {
+    let matched = Arc::new(Recorder::default());
+    let default = Arc::new(Recorder::default());
+    let router = RouterCollector::new(vec![default.clone()]).with_rule(
+        RouteRule::new(vec![matched.clone()])
+            .catalog("greptime")
+            .source(1)
+            .kind(MeterKind::Read),
+    );
+
+    let mut from_source = record("greptime", "public", 1);
+    from_source.source = 1;
+    router.on_read(from_source.clone());
+    router.on_write(from_source);
+    router.on_read(record("greptime", "public", 2));
+
+    let matched = matched.records();
+    assert_eq!(matched.len(), 1);
+    assert_eq!(matched[0].0, MeterKind::Read);
+    assert_eq!(default.records().len(), 2);
+}
+
+#[test]
+fn sends_to_every_target_of_the_rule() {
+    let a = Arc::new(Recorder::default());
+    let b = Arc::new(Recorder::default());
+    let router = RouterCollector::new(vec![]).with_rule(RouteRule::new(vec![a.clone(), b.clone()]));
+
+    router.on_read(record("greptime", "public", 7));
+
+    for target in [a, b] {
+        let records = target.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].0, records[0].1.value), (MeterKind::Read, 7));
+    }
+}
+
+#[test]
+fn drops_unmatched_records_without_default_targets() {
+    let matched = Arc::new(Recorder::default());
+    let router =
+        RouterCollector::new(vec![]).with_rule(RouteRule::new(vec![matched.clone()]).schema("x"));
+
+    router.on_write(record("greptime", "public", 1));
+    assert!(matched.records().is_empty());
+}
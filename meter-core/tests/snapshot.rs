@@ -0,0 +1,223 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::snapshot::AggregateSnapshot;
+use meter_core::snapshot::SnapshotCollector;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// A record fed to a node.
+#[derive(Debug, Clone)]
+struct Op {
+    read: bool,
+    catalog: u8,
+    schema: u8,
+    source: u8,
+    value: u64,
+    timestamp_millis: u64,
+    table: u8,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    (
+        any::<bool>(),
+        0..2u8,
+        0..3u8,
+        0..2u8,
+        // Large values too, so that sums and counts saturate.
+        prop_oneof![0..100_000u64, any::<u64>()],
+        0..1_000_000u64,
+        0..20u8,
+    )
+        .prop_map(
+            |(read, catalog, schema, source, value, timestamp_millis, table)| Op {
+                read,
+                catalog,
+                schema,
+                source,
+                value,
+                timestamp_millis,
+                table,
+            },
+        )
+}
+
+/// The ID of a node and the records it collected.
+fn node() -> impl Strategy<Value = (u16, Vec<Op>)> {
+    (0..8u16, vec(op(), 0..30))
+}
+
+fn collector(node_id: u16) -> SnapshotCollector {
+    collector_with(node_id, 4, 0.05, 8)
+}
+
+fn collector_with(
+    node_id: u16,
+    precision: u8,
+    relative_accuracy: f64,
+    max_bins: usize,
+) -> SnapshotCollector {
+    // Small sketches, so that distributions collapse bins while merging.
+    SnapshotCollector::new(node_id)
+        .with_distinct_labels(["table"])
+        .with_precision(precision)
+        .with_quantile_sketch(relative_accuracy, max_bins)
+}
+
+fn feed(collector: &SnapshotCollector, ops: &[Op]) {
+    for op in ops {
+        let record = MeterRecord::new(
+            format!("catalog{}", op.catalog),
+            format!("schema{}", op.schema),
+            op.value,
+            op.source,
+        )
+        .with_timestamp_millis(op.timestamp_millis)
+        .with_label("table", format!("table{}", op.table));
+        if op.read {
+            collector.on_read(record);
+        } else {
+            collector.on_write(record);
+        }
+    }
+}
+
+fn snapshot((node_id, ops): &(u16, Vec<Op>)) -> AggregateSnapshot {
+    let collector = collector(*node_id);
+    feed(&collector, ops);
+    collector.take()
+}
+
+fn merged<'a>(snapshots: impl IntoIterator<Item = &'a AggregateSnapshot>) -> AggregateSnapshot {
+    let mut merged = AggregateSnapshot::default();
+    for snapshot in snapshots {
+        merged.merge(snapshot).unwrap();
+    }
+    merged
+}
+
+proptest! {
+    #[test]
+    fn merge_is_commutative(a in node(), b in node()) {
+        let (a, b) = (snapshot(&a), snapshot(&b));
+        prop_assert_eq!(merged([&a, &b]), merged([&b, &a]));
+    }
+
+    #[test]
+    fn merge_is_associative(a in node(), b in node(), c in node()) {
+        let (a, b, c) = (snapshot(&a), snapshot(&b), snapshot(&c));
+
+        let mut left = a.clone();
+        left.merge(&b).unwrap();
+        left.merge(&c).unwrap();
+
+        let mut bc = b.clone();
+        bc.merge(&c).unwrap();
+        let mut right = a.clone();
+        right.merge(&bc).unwrap();
+
+        prop_assert_eq!(left, right);
+    }
+
+    #[test]
+    fn empty_snapshot_is_identity(a in node()) {
+        let a = snapshot(&a);
+        prop_assert_eq!(merged([&a]), a.clone());
+
+        let mut merged = a.clone();
+        merged.merge(&AggregateSnapshot::default()).unwrap();
+        prop_assert_eq!(merged, a);
+    }
+
+    #[test]
+    fn merge_order_does_not_matter(
+        (nodes, shuffled) in vec(node(), 1..6)
+            .prop_flat_map(|nodes| (Just(nodes.clone()), Just(nodes).prop_shuffle()))
+    ) {
+        let snapshots = nodes.iter().map(snapshot).collect::<Vec<_>>();
+        let shuffled = shuffled.iter().map(snapshot).collect::<Vec<_>>();
+        prop_assert_eq!(merged(&snapshots), merged(&shuffled));
+    }
+
+    #[test]
+    fn merge_matches_a_single_collector(nodes in vec(node(), 1..6)) {
+        let snapshots = nodes.iter().map(snapshot).collect::<Vec<_>>();
+        let merged = merged(&snapshots);
+
+        let single = collector(0);
+        for (_, ops) in &nodes {
+            feed(&single, ops);
+        }
+        let single = single.take();
+
+        prop_assert_eq!(merged.window, single.window);
+        prop_assert_eq!(merged.entries, single.entries);
+    }
+
+    #[test]
+    fn merge_rejects_mismatched_settings(
+        (a, b) in (node(), node()).prop_filter("both nodes collected", |(a, b)| {
+            !a.1.is_empty() && !b.1.is_empty()
+        }),
+        mismatch in 0..3usize,
+    ) {
+        let a = snapshot(&a);
+        let other = match mismatch {
+            0 => collector_with(b.0, 5, 0.05, 8),
+            1 => collector_with(b.0, 4, 0.02, 8),
+            _ => collector_with(b.0, 4, 0.05, 16),
+        };
+        feed(&other, &b.1);
+        let b = other.take();
+
+        let mut merged = a.clone();
+        prop_assert!(merged.merge(&b).is_err());
+        prop_assert_eq!(&merged, &a);
+
+        let mut merged = b.clone();
+        prop_assert!(merged.merge(&a).is_err());
+        prop_assert_eq!(merged, b);
+    }
+
+    #[test]
+    fn encode_roundtrips(a in node()) {
+        let a = snapshot(&a);
+        prop_assert_eq!(AggregateSnapshot::decode(&a.encode()).unwrap(), a);
+    }
+
+    #[test]
+    fn decode_rejects_corruption(a in node(), index in any::<prop::sample::Index>(), flip in 1..=255u8) {
+        let mut encoded = snapshot(&a).encode();
+        let i = index.index(encoded.len());
+        encoded[i] ^= flip;
+        prop_assert!(AggregateSnapshot::decode(&encoded).is_err());
+    }
+}
+
+#[test]
+fn rejects_snapshots_with_mixed_settings() {
+    let one = |collector: SnapshotCollector, catalog: &str| {
+        collector.on_write(MeterRecord::new(catalog.into(), "public".into(), 1, 0));
+        collector.take()
+    };
+    let mut mixed = one(collector_with(0, 4, 0.05, 8), "a");
+    mixed
+        .entries
+        .extend(one(collector_with(0, 5, 0.05, 8), "b").entries);
+
+    assert!(AggregateSnapshot::default().merge(&mixed).is_err());
+    assert!(AggregateSnapshot::decode(&mixed.encode()).is_err());
+}
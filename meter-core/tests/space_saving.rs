@@ -0,0 +1,114 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use meter_core::sketch::space_saving::SpaceSaving;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// A stream of keys with their weights, skewed so that a few keys are heavy.
+fn stream() -> impl Strategy<Value = Vec<(u8, u64)>> {
+    vec((prop_oneof![0..3u8, 0..40u8], 1..1_000u64), 0..200)
+}
+
+fn sketch(capacity: usize, stream: &[(u8, u64)]) -> SpaceSaving<u8> {
+    let mut sketch = SpaceSaving::new(capacity);
+    for (key, weight) in stream {
+        sketch.add(*key, *weight);
+    }
+    sketch
+}
+
+fn weights<'a>(streams: impl IntoIterator<Item = &'a [(u8, u64)]>) -> HashMap<u8, u64> {
+    let mut weights = HashMap::new();
+    for (key, weight) in streams.into_iter().flatten() {
+        *weights.entry(*key).or_default() += weight;
+    }
+    weights
+}
+
+proptest! {
+    #[test]
+    fn estimates_are_within_the_error_bound(capacity in 1..10usize, stream in stream()) {
+        let sketch = sketch(capacity, &stream);
+        let weights = weights([stream.as_slice()]);
+        prop_assert_eq!(sketch.total(), weights.values().sum::<u64>());
+
+        let top = sketch.top(capacity);
+        for hitter in &top {
+            let weight = weights[&hitter.key];
+            prop_assert!(hitter.count - hitter.error <= weight);
+            prop_assert!(weight <= hitter.count);
+            prop_assert!(hitter.error <= sketch.max_error());
+        }
+        // Every key heavier than the bound is tracked.
+        for (key, weight) in &weights {
+            if *weight > sketch.max_error() {
+                prop_assert!(top.iter().any(|hitter| hitter.key == *key));
+            }
+        }
+    }
+
+    #[test]
+    fn merged_estimates_bound_the_combined_weights(
+        capacity in 1..10usize,
+        a in stream(),
+        b in stream(),
+    ) {
+        let mut merged = sketch(capacity, &a);
+        merged.merge(&sketch(capacity, &b));
+        let weights = weights([a.as_slice(), b.as_slice()]);
+        prop_assert_eq!(merged.total(), weights.values().sum::<u64>());
+
+        let top = merged.top(capacity);
+        prop_assert!(top.len() <= capacity);
+        for hitter in &top {
+            let weight = weights[&hitter.key];
+            prop_assert!(hitter.count - hitter.error <= weight);
+            prop_assert!(weight <= hitter.count);
+        }
+    }
+}
+
+#[test]
+fn weights_saturate() {
+    let mut sketch = SpaceSaving::new(1);
+    sketch.add("public", u64::MAX);
+    sketch.add("public", 1);
+    sketch.add("tenant", u64::MAX);
+    assert_eq!(sketch.total(), u64::MAX);
+
+    let top = sketch.top(1);
+    assert_eq!(top[0].key, "tenant");
+    assert_eq!((top[0].count, top[0].error), (u64::MAX, u64::MAX));
+
+    let mut merged = sketch.clone();
+    merged.merge(&sketch);
+    assert_eq!(merged.total(), u64::MAX);
+    assert_eq!(merged.top(1)[0].count, u64::MAX);
+}
+
+#[test]
+fn evicts_the_smallest_counter() {
+    let mut sketch = SpaceSaving::new(2);
+    sketch.add("a", 5);
+    sketch.add("b", 1);
+    sketch.add("a", 1);
+    sketch.add("c", 2);
+
+    let top = sketch.top(2);
+    assert_eq!((top[0].key, top[0].count, top[0].error), ("a", 6, 0));
+    assert_eq!((top[1].key, top[1].count, top[1].error), ("c", 3, 1));
+}
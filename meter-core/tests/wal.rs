@@ -0,0 +1,170 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::wal::DurableCollector;
+use meter_core::wal::WalConfig;
+use parking_lot::Mutex;
+
+/// Keeps the written values it receives.
+#[derive(Default)]
+struct Recorder(Mutex<Vec<u64>>);
+
+impl Recorder {
+    fn values(&self) -> Vec<u64> {
+        let mut values = self.0.lock().clone();
+        values.sort_unstable();
+        values
+    }
+}
+
+impl Collect for Recorder {
+    fn on_write(&self, record: MeterRecord) {
+        self.0.lock().push(record.value);
+    }
+
+    fn on_read(&self, _: MeterRecord) {}
+}
+
+fn write(durable: &DurableCollector, value: u64) {
+    durable.on_write(MeterRecord::new(
+        "greptime".to_string(),
+        "public".to_string(),
+        value,
+        0,
+    ));
+}
+
+fn segments(config: &WalConfig) -> Vec<PathBuf> {
+    let mut segments = fs::read_dir(&config.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    segments.sort();
+    segments
+}
+
+fn replay(config: &WalConfig) -> Vec<u64> {
+    let recorder = Arc::new(Recorder::default());
+    drop(DurableCollector::open(config.clone(), recorder.clone()).unwrap());
+    recorder.values()
+}
+
+#[test]
+fn replays_unacknowledged_segments() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig::new(dir.path())
+        .with_sync_batch(1)
+        .with_segment_size(1);
+
+    let recorder = Arc::new(Recorder::default());
+    let durable = DurableCollector::open(config.clone(), recorder.clone()).unwrap();
+    for value in 1..=3 {
+        write(&durable, value);
+    }
+    drop(durable);
+
+    assert_eq!(recorder.values(), vec![1, 2, 3]);
+    assert!(segments(&config).len() >= 3);
+    assert_eq!(replay(&config), vec![1, 2, 3]);
+}
+
+#[test]
+fn torn_tail_keeps_earlier_frames_and_later_segments() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig::new(dir.path()).with_sync_batch(1);
+
+    let durable = DurableCollector::open(config.clone(), Arc::new(Recorder::default())).unwrap();
+    write(&durable, 1);
+    write(&durable, 2);
+    let (checkpoint, _) = durable.checkpoint(|| ()).unwrap();
+    write(&durable, 3);
+    drop(durable);
+
+    // Tear the last frame of the first segment, as a crash mid-write would.
+    let torn = &segments(&config)[0];
+    let file = OpenOptions::new().write(true).open(torn).unwrap();
+    file.set_len(file.metadata().unwrap().len() - 3).unwrap();
+
+    assert_eq!(replay(&config), vec![1, 3]);
+
+    let durable = DurableCollector::open(config.clone(), Arc::new(Recorder::default())).unwrap();
+    durable.ack(checkpoint).unwrap();
+    drop(durable);
+    assert_eq!(replay(&config), vec![3]);
+}
+
+#[test]
+fn ack_deletes_only_checkpointed_segments() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig::new(dir.path()).with_sync_batch(1);
+
+    let recorder = Arc::new(Recorder::default());
+    let durable = DurableCollector::open(config.clone(), recorder.clone()).unwrap();
+    write(&durable, 1);
+    write(&durable, 2);
+    let (checkpoint, drained) = durable
+        .checkpoint(|| std::mem::take(&mut *recorder.0.lock()))
+        .unwrap();
+    write(&durable, 3);
+    durable.ack(checkpoint).unwrap();
+    drop(durable);
+
+    assert_eq!(drained, vec![1, 2]);
+    assert_eq!(replay(&config), vec![3]);
+}
+
+#[test]
+fn syncs_pending_records_after_the_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig::new(dir.path())
+        .with_sync_batch(1_000)
+        .with_sync_interval(Duration::from_millis(10));
+
+    let recorder = Arc::new(Recorder::default());
+    let durable = DurableCollector::open(config, recorder.clone()).unwrap();
+    write(&durable, 1);
+
+    // No further append triggers the sync.
+    for _ in 0..100 {
+        if !recorder.values().is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(recorder.values(), vec![1]);
+}
+
+#[test]
+fn drop_forwards_pending_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = WalConfig::new(dir.path())
+        .with_sync_batch(1_000)
+        .with_sync_interval(Duration::from_secs(3600));
+
+    let recorder = Arc::new(Recorder::default());
+    let durable = DurableCollector::open(config, recorder.clone()).unwrap();
+    write(&durable, 1);
+    assert!(recorder.values().is_empty());
+
+    drop(durable);
+    assert_eq!(recorder.values(), vec![1]);
+}
@@ -0,0 +1,163 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::window::GroupKey;
+use meter_core::window::Window;
+use meter_core::window::WindowAggregate;
+use meter_core::window::WindowConfig;
+use meter_core::window::WindowedCollector;
+
+fn write(collector: &WindowedCollector, schema: &str, value: u64, timestamp_millis: u64) {
+    let record = MeterRecord::new("greptime".to_string(), schema.to_string(), value, 0);
+    collector.on_write(record.with_timestamp_millis(timestamp_millis));
+}
+
+/// The window, schema and write units of each aggregate.
+fn writes(aggregates: &[WindowAggregate]) -> Vec<(u64, u64, &str, u64)> {
+    aggregates
+        .iter()
+        .map(|a| {
+            (
+                a.window.start_millis,
+                a.window.end_millis,
+                a.key.schema.as_str(),
+                a.usage.write_units,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn out_of_order_records_join_open_windows() {
+    let collector = WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(1)));
+    write(&collector, "public", 1, 900);
+    write(&collector, "public", 2, 100);
+    write(&collector, "tenant", 4, 500);
+    assert_eq!(collector.watermark(), 900);
+    assert!(collector.drain_closed().is_empty());
+
+    write(&collector, "public", 8, 1_000);
+    assert_eq!(
+        writes(&collector.drain_closed()),
+        vec![(0, 1_000, "public", 3), (0, 1_000, "tenant", 4)]
+    );
+    assert_eq!(collector.late_records(), 0);
+}
+
+#[test]
+fn drops_records_of_closed_windows() {
+    let collector = WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(1)));
+    write(&collector, "public", 1, 100);
+    write(&collector, "public", 2, 1_500);
+    write(&collector, "public", 4, 999);
+
+    assert_eq!(collector.late_records(), 1);
+    assert_eq!(
+        writes(&collector.drain_closed()),
+        vec![(0, 1_000, "public", 1)]
+    );
+}
+
+#[test]
+fn allowed_lateness_keeps_windows_open() {
+    let config = WindowConfig::tumbling(Duration::from_secs(1))
+        .with_allowed_lateness(Duration::from_millis(500));
+    let collector = WindowedCollector::new(config);
+    write(&collector, "public", 1, 100);
+    write(&collector, "public", 2, 1_400);
+    write(&collector, "public", 4, 999);
+    assert!(collector.drain_closed().is_empty());
+
+    collector.advance_watermark(1_500);
+    assert_eq!(
+        writes(&collector.drain_closed()),
+        vec![(0, 1_000, "public", 5)]
+    );
+
+    write(&collector, "public", 8, 999);
+    assert_eq!(collector.late_records(), 1);
+}
+
+#[test]
+fn watermark_never_goes_back() {
+    let collector = WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(1)));
+    collector.advance_watermark(5_000);
+    collector.advance_watermark(1_000);
+    assert_eq!(collector.watermark(), 5_000);
+
+    write(&collector, "public", 1, 4_999);
+    assert_eq!(collector.late_records(), 1);
+}
+
+#[test]
+fn hopping_windows_count_records_in_each_overlap() {
+    let config = WindowConfig::hopping(Duration::from_secs(10), Duration::from_secs(5));
+    assert_eq!(
+        config.assign(12_000).collect::<Vec<_>>(),
+        vec![Window::new(10_000, 20_000), Window::new(5_000, 15_000)]
+    );
+    // Windows never start before the epoch.
+    assert_eq!(
+        config.assign(2_000).collect::<Vec<_>>(),
+        vec![Window::new(0, 10_000)]
+    );
+
+    let collector = WindowedCollector::new(config);
+    write(&collector, "public", 1, 2_000);
+    write(&collector, "public", 10, 12_000);
+    assert_eq!(
+        writes(&collector.flush()),
+        vec![
+            (0, 10_000, "public", 1),
+            (5_000, 15_000, "public", 10),
+            (10_000, 20_000, "public", 10),
+        ]
+    );
+}
+
+#[test]
+fn flush_closes_every_window() {
+    let collector = WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(1)));
+    write(&collector, "public", 1, 100);
+    write(&collector, "public", 2, 1_100);
+
+    let flushed = collector.flush();
+    assert_eq!(
+        writes(&flushed),
+        vec![(0, 1_000, "public", 1), (1_000, 2_000, "public", 2)]
+    );
+    assert!(collector.flush().is_empty());
+}
+
+#[test]
+fn overflowed_keys_share_one_bucket_per_window() {
+    let collector =
+        WindowedCollector::new(WindowConfig::tumbling(Duration::from_secs(1))).with_max_keys(1);
+    write(&collector, "public", 1, 100);
+    write(&collector, "tenant1", 2, 200);
+    write(&collector, "tenant2", 4, 300);
+
+    let flushed = collector.flush();
+    let overflow = flushed
+        .iter()
+        .find(|a| a.key == GroupKey::overflow(0))
+        .unwrap();
+    assert_eq!(overflow.usage.write_units, 6);
+    assert_eq!(flushed.len(), 2);
+    assert_eq!(collector.overflowed_records(), 2);
+}
@@ -28,6 +28,9 @@ use meter_example::CalcImpl;
 use meter_example::MockInsertRequest;
 use meter_macros::read_meter;
 use meter_macros::write_meter;
+use meter_reporter::schedule::Schedule;
+use meter_reporter::schedule::Scheduler;
+use meter_reporter::schedule::SchedulerHandle;
 
 fn main() {
     tracing::subscriber::set_global_default(tracing_subscriber::FmtSubscriber::builder().finish())
@@ -38,12 +41,14 @@ fn main() {
 
 #[tokio::main]
 async fn run() {
-    setup_global_registry().await;
+    let scheduler = setup_global_registry().await;
 
     do_some_record().await;
+
+    scheduler.shutdown().await;
 }
 
-async fn setup_global_registry() {
+async fn setup_global_registry() -> SchedulerHandle {
     let collector = Arc::new(SimpleCollector::new(w_calc, r_calc));
     let reporter = Arc::new(SimpleReporter::new(collector.clone()));
 
@@ -60,9 +65,8 @@ async fn setup_global_registry() {
     let read_item_calc = calc_impl as Arc<dyn ItemCalculator<ReadItem>>;
     r.register_calculator(read_item_calc);
 
-    tokio::spawn(async move {
-        reporter.start().await;
-    });
+    let schedule = Schedule::every(Duration::from_secs(5)).aligned();
+    Scheduler::new(schedule, reporter).start()
 }
 
 async fn do_some_record() {
@@ -13,23 +13,26 @@
 // limitations under the License.
 
 use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use meter_core::cardinality::CardinalityLimit;
 use meter_core::collect::Collect;
 use meter_core::data::MeterRecord;
+pub use meter_core::data::SchemaId;
 
 pub struct SimpleCollector<W, R> {
     read_data: DashMap<SchemaId, Vec<MeterRecord>>,
     write_data: DashMap<SchemaId, Vec<MeterRecord>>,
+    /// The number of keys of `read_data` and `write_data`, reserved before a
+    /// key is inserted so the cap holds under concurrent writers.
+    read_keys: AtomicUsize,
+    write_keys: AtomicUsize,
     w_calc: W,
     r_calc: R,
-}
-
-/// The SchemaId identifies a database.
-#[derive(Eq, PartialEq, Hash, Clone, Debug)]
-pub struct SchemaId {
-    pub catalog: String,
-    pub schema: String,
+    limit: CardinalityLimit,
 }
 
 impl<W, R> SimpleCollector<W, R> {
@@ -37,10 +40,59 @@ impl<W, R> SimpleCollector<W, R> {
         Self {
             read_data: DashMap::default(),
             write_data: DashMap::default(),
+            read_keys: AtomicUsize::new(0),
+            write_keys: AtomicUsize::new(0),
             w_calc,
             r_calc,
+            limit: CardinalityLimit::unlimited(),
         }
     }
+
+    /// Caps the number of distinct schemas tracked for reads and for writes.
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.limit = CardinalityLimit::new(max_keys);
+        self
+    }
+
+    /// The number of records accounted to [SchemaId::overflow].
+    pub fn overflowed_records(&self) -> u64 {
+        self.limit.overflowed_records()
+    }
+
+    /// Pushes a record under its schema, or under [SchemaId::overflow] once
+    /// the cap is reached.
+    fn push(
+        &self,
+        data: &DashMap<SchemaId, Vec<MeterRecord>>,
+        keys: &AtomicUsize,
+        record: MeterRecord,
+    ) {
+        let record = match data.entry(SchemaId::of(&record)) {
+            Entry::Occupied(mut entry) => return entry.get_mut().push(record),
+            Entry::Vacant(entry) => {
+                let reserved = keys.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    (!self.limit.is_full(n)).then_some(n + 1)
+                });
+                let admitted = match reserved {
+                    Ok(_) => true,
+                    Err(n) => self.limit.admit(false, n),
+                };
+                if admitted {
+                    entry.insert(vec![record]);
+                    return;
+                }
+                record
+            }
+        };
+
+        // The entry above is released, the overflow key may share its shard.
+        data.entry(SchemaId::overflow())
+            .or_insert_with(|| {
+                keys.fetch_add(1, Ordering::AcqRel);
+                vec![]
+            })
+            .push(record);
+    }
 }
 
 impl<W, R> SimpleCollector<W, R>
@@ -51,6 +103,8 @@ where
     pub fn clear(&self) {
         self.read_data.clear();
         self.write_data.clear();
+        self.read_keys.store(0, Ordering::Release);
+        self.write_keys.store(0, Ordering::Release);
     }
 
     pub fn schema_ws(&self) -> HashMap<SchemaId, u64> {
@@ -88,24 +142,10 @@ where
     W: Send + Sync,
 {
     fn on_read(&self, record: MeterRecord) {
-        let schema_id = SchemaId {
-            catalog: record.catalog.clone(),
-            schema: record.schema.clone(),
-        };
-
-        let mut entry = self.read_data.entry(schema_id).or_default();
-
-        entry.push(record)
+        self.push(&self.read_data, &self.read_keys, record);
     }
 
     fn on_write(&self, record: MeterRecord) {
-        let schema_id = SchemaId {
-            catalog: record.catalog.clone(),
-            schema: record.schema.clone(),
-        };
-
-        let mut entry = self.write_data.entry(schema_id).or_default();
-
-        entry.push(record)
+        self.push(&self.write_data, &self.write_keys, record);
     }
 }
@@ -16,6 +16,7 @@ use meter_core::data::ReadItem;
 use meter_core::ItemCalculator;
 
 pub mod collector;
+pub mod output;
 pub mod reporter;
 
 pub struct MockInsertRequest;
@@ -0,0 +1,531 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use meter_core::data::MeterKind;
+use meter_core::distinct::DistinctSummary;
+use serde_json::json;
+
+use crate::collector::SchemaId;
+
+/// How [SimpleReporter](crate::reporter::SimpleReporter) outputs the usage of
+/// each schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Separate lines of ws and rs, e.g. `catalog greptime, schema db1, ws: 10`.
+    #[default]
+    Log,
+
+    /// An aligned table with deltas and percentages.
+    Table,
+
+    /// A single line of JSON.
+    Json,
+
+    /// CSV lines with a header.
+    Csv,
+}
+
+/// The order of the schemas in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// By catalog then schema name.
+    #[default]
+    Schema,
+
+    /// By descending write units.
+    WriteUnits,
+
+    /// By descending read units.
+    ReadUnits,
+
+    /// By descending write plus read units.
+    TotalUnits,
+}
+
+/// The usage of a schema during a report period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRow {
+    pub id: SchemaId,
+    pub write_units: u64,
+    pub read_units: u64,
+
+    /// The change of write units since the previous period.
+    pub write_delta: i64,
+
+    /// The change of read units since the previous period.
+    pub read_delta: i64,
+
+    /// The share of all write units in the period.
+    pub write_percent: f64,
+
+    /// The share of all read units in the period.
+    pub read_percent: f64,
+}
+
+/// The p50 and p99 of the per-request cost of a schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantileRow {
+    pub id: SchemaId,
+    pub kind: MeterKind,
+    pub p50: f64,
+    pub p99: f64,
+}
+
+/// The approximate distinct values of the chosen labels of a schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistinctRow {
+    pub id: SchemaId,
+    pub write_units: u64,
+    pub read_units: u64,
+    pub distinct: BTreeMap<String, u64>,
+}
+
+/// Merges the ws and rs of a period into rows, comparing them with the
+/// `previous` period. Schemas only used in the `previous` period get a row
+/// of zeros, so that their drop shows in the deltas.
+pub fn usage_rows(
+    ws: &HashMap<SchemaId, u64>,
+    rs: &HashMap<SchemaId, u64>,
+    previous: &HashMap<SchemaId, (u64, u64)>,
+) -> Vec<UsageRow> {
+    let total_ws = saturating_sum(ws.values());
+    let total_rs = saturating_sum(rs.values());
+    let ids = ws
+        .keys()
+        .chain(rs.keys())
+        .chain(previous.keys())
+        .collect::<BTreeSet<_>>();
+
+    ids.into_iter()
+        .map(|id| {
+            let write_units = ws.get(id).copied().unwrap_or_default();
+            let read_units = rs.get(id).copied().unwrap_or_default();
+            let (prev_ws, prev_rs) = previous.get(id).copied().unwrap_or_default();
+            UsageRow {
+                id: id.clone(),
+                write_units,
+                read_units,
+                write_delta: delta(write_units, prev_ws),
+                read_delta: delta(read_units, prev_rs),
+                write_percent: percent(write_units, total_ws),
+                read_percent: percent(read_units, total_rs),
+            }
+        })
+        .collect()
+}
+
+/// Sorts the rows, ties broken by catalog and schema name.
+pub fn sort_rows(rows: &mut [UsageRow], by: SortBy) {
+    match by {
+        SortBy::Schema => rows.sort_by(|a, b| a.id.cmp(&b.id)),
+        SortBy::WriteUnits => rows.sort_by_key(|r| (Reverse(r.write_units), r.id.clone())),
+        SortBy::ReadUnits => rows.sort_by_key(|r| (Reverse(r.read_units), r.id.clone())),
+        SortBy::TotalUnits => rows.sort_by_key(|r| {
+            (
+                Reverse(r.write_units.saturating_add(r.read_units)),
+                r.id.clone(),
+            )
+        }),
+    }
+}
+
+/// Merges the `[p50, p99]` of writes and reads into rows, writes first,
+/// each ordered by catalog and schema name.
+pub fn quantile_rows(
+    write: HashMap<SchemaId, Vec<f64>>,
+    read: HashMap<SchemaId, Vec<f64>>,
+) -> Vec<QuantileRow> {
+    let rows = |kind, quantiles: HashMap<SchemaId, Vec<f64>>| {
+        quantiles
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(move |(id, q)| QuantileRow {
+                id,
+                kind,
+                p50: q[0],
+                p99: q[1],
+            })
+    };
+    rows(MeterKind::Write, write)
+        .chain(rows(MeterKind::Read, read))
+        .collect()
+}
+
+/// Turns the summaries of a period into rows, ordered by catalog and schema
+/// name.
+pub fn distinct_rows(summaries: HashMap<SchemaId, DistinctSummary>) -> Vec<DistinctRow> {
+    summaries
+        .into_iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(id, summary)| DistinctRow {
+            id,
+            write_units: summary.usage.write_units,
+            read_units: summary.usage.read_units,
+            distinct: summary.distinct_counts(),
+        })
+        .collect()
+}
+
+/// Renders the rows as output lines.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::HashMap;
+///
+/// use meter_example::collector::SchemaId;
+/// use meter_example::output::render;
+/// use meter_example::output::usage_rows;
+/// use meter_example::output::OutputFormat;
+///
+/// let ws = HashMap::from([(SchemaId::new("greptime", "db1"), 30), (SchemaId::new("greptime", "db2"), 10)]);
+/// let rs = HashMap::from([(SchemaId::new("greptime", "db1"), 5)]);
+/// let previous = HashMap::from([(SchemaId::new("greptime", "db1"), (40, 5))]);
+///
+/// let rows = usage_rows(&ws, &rs, &previous);
+/// assert_eq!(
+///     render(&rows, OutputFormat::Table, 5),
+///     vec![
+///         "CATALOG   SCHEMA  WS  WS DELTA   WS %  RS  RS DELTA    RS %",
+///         "greptime  db1     30       -10  75.0%   5        +0  100.0%",
+///         "greptime  db2     10       +10  25.0%   0        +0    0.0%",
+///     ]
+/// );
+/// ```
+pub fn render(rows: &[UsageRow], format: OutputFormat, elapsed_secs: u64) -> Vec<String> {
+    match format {
+        OutputFormat::Log => render_log(rows, elapsed_secs),
+        OutputFormat::Table => render_table(rows),
+        OutputFormat::Json => vec![render_json(rows, elapsed_secs)],
+        OutputFormat::Csv => render_csv(rows),
+    }
+}
+
+fn render_log(rows: &[UsageRow], elapsed_secs: u64) -> Vec<String> {
+    let mut lines = vec![format!(
+        "The number of Ws consumed in the last {} seconds:",
+        elapsed_secs
+    )];
+    for row in rows.iter().filter(|r| r.write_units > 0) {
+        lines.push(format!(
+            "catalog {}, schema {}, ws: {}",
+            row.id.catalog, row.id.schema, row.write_units
+        ));
+    }
+
+    lines.push(format!(
+        "The number of Rs consumed in the last {} seconds:",
+        elapsed_secs
+    ));
+    for row in rows.iter().filter(|r| r.read_units > 0) {
+        lines.push(format!(
+            "catalog {}, schema {}, rs: {}",
+            row.id.catalog, row.id.schema, row.read_units
+        ));
+    }
+    lines
+}
+
+fn render_table(rows: &[UsageRow]) -> Vec<String> {
+    let header = [
+        "CATALOG", "SCHEMA", "WS", "WS DELTA", "WS %", "RS", "RS DELTA", "RS %",
+    ];
+    let cells = rows
+        .iter()
+        .map(|row| {
+            vec![
+                row.id.catalog.clone(),
+                row.id.schema.clone(),
+                row.write_units.to_string(),
+                format!("{:+}", row.write_delta),
+                format!("{:.1}%", row.write_percent),
+                row.read_units.to_string(),
+                format!("{:+}", row.read_delta),
+                format!("{:.1}%", row.read_percent),
+            ]
+        })
+        .collect::<Vec<_>>();
+    table(&header, &cells, 2)
+}
+
+/// Aligns the cells under the header. The first `names` columns are
+/// left-aligned, the numbers after them right-aligned.
+fn table(header: &[&str], cells: &[Vec<String>], names: usize) -> Vec<String> {
+    let mut widths = header.iter().map(|h| h.len()).collect::<Vec<_>>();
+    for row in cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let line = |cells: &[&str]| {
+        let columns = cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| {
+                if i < names {
+                    format!("{:<width$}", cell)
+                } else {
+                    format!("{:>width$}", cell)
+                }
+            })
+            .collect::<Vec<_>>();
+        columns.join("  ").trim_end().to_string()
+    };
+
+    let mut lines = vec![line(header)];
+    for row in cells {
+        lines.push(line(&row.iter().map(String::as_str).collect::<Vec<_>>()));
+    }
+    lines
+}
+
+fn render_json(rows: &[UsageRow], elapsed_secs: u64) -> String {
+    let schemas = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "catalog": row.id.catalog,
+                "schema": row.id.schema,
+                "write_units": row.write_units,
+                "write_delta": row.write_delta,
+                "write_percent": row.write_percent,
+                "read_units": row.read_units,
+                "read_delta": row.read_delta,
+                "read_percent": row.read_percent,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "elapsed_secs": elapsed_secs,
+        "schemas": schemas,
+    })
+    .to_string()
+}
+
+fn render_csv(rows: &[UsageRow]) -> Vec<String> {
+    let header = [
+        "catalog",
+        "schema",
+        "write_units",
+        "write_delta",
+        "write_percent",
+        "read_units",
+        "read_delta",
+        "read_percent",
+    ];
+    let cells = rows
+        .iter()
+        .map(|row| {
+            vec![
+                row.id.catalog.clone(),
+                row.id.schema.clone(),
+                row.write_units.to_string(),
+                row.write_delta.to_string(),
+                format!("{:.2}", row.write_percent),
+                row.read_units.to_string(),
+                row.read_delta.to_string(),
+                format!("{:.2}", row.read_percent),
+            ]
+        })
+        .collect::<Vec<_>>();
+    csv_lines(&header, &cells)
+}
+
+/// One line per record, unless a quoted name holds a line break.
+fn csv_lines(header: &[&str], cells: &[Vec<String>]) -> Vec<String> {
+    let mut lines = vec![csv_record(header)];
+    for row in cells {
+        lines.push(csv_record(
+            &row.iter().map(String::as_str).collect::<Vec<_>>(),
+        ));
+    }
+    lines
+}
+
+fn csv_record(fields: &[&str]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    // Writing to memory cannot fail.
+    writer.write_record(fields).unwrap();
+    let record = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+    record.trim_end_matches(['\r', '\n']).to_string()
+}
+
+/// Renders the quantile rows as output lines, in the same format as the usage.
+pub fn render_quantiles(
+    rows: &[QuantileRow],
+    format: OutputFormat,
+    elapsed_secs: u64,
+) -> Vec<String> {
+    let cells = rows
+        .iter()
+        .map(|row| {
+            vec![
+                row.id.catalog.clone(),
+                row.id.schema.clone(),
+                kind_name(row.kind).to_string(),
+                format!("{:.0}", row.p50),
+                format!("{:.0}", row.p99),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        OutputFormat::Log => {
+            let mut lines = vec![format!(
+                "The quantiles of w/r per request in the last {} seconds:",
+                elapsed_secs
+            )];
+            for row in rows {
+                let kind = match row.kind {
+                    MeterKind::Write => "w",
+                    MeterKind::Read => "r",
+                };
+                lines.push(format!(
+                    "catalog {}, schema {}, {kind} p50: {:.0}, {kind} p99: {:.0}",
+                    row.id.catalog, row.id.schema, row.p50, row.p99
+                ));
+            }
+            lines
+        }
+        OutputFormat::Table => table(&["CATALOG", "SCHEMA", "KIND", "P50", "P99"], &cells, 3),
+        OutputFormat::Json => {
+            let quantiles = rows
+                .iter()
+                .map(|row| {
+                    json!({
+                        "catalog": row.id.catalog,
+                        "schema": row.id.schema,
+                        "kind": kind_name(row.kind),
+                        "p50": row.p50,
+                        "p99": row.p99,
+                    })
+                })
+                .collect::<Vec<_>>();
+            vec![json!({
+                "elapsed_secs": elapsed_secs,
+                "quantiles": quantiles,
+            })
+            .to_string()]
+        }
+        OutputFormat::Csv => csv_lines(&["catalog", "schema", "kind", "p50", "p99"], &cells),
+    }
+}
+
+/// Renders the distinct rows as output lines, in the same format as the
+/// usage. Tables and CSV have a line per label.
+pub fn render_distinct(
+    rows: &[DistinctRow],
+    format: OutputFormat,
+    elapsed_secs: u64,
+) -> Vec<String> {
+    let cells = rows
+        .iter()
+        .flat_map(|row| {
+            row.distinct.iter().map(|(label, count)| {
+                vec![
+                    row.id.catalog.clone(),
+                    row.id.schema.clone(),
+                    label.clone(),
+                    count.to_string(),
+                    row.write_units.to_string(),
+                    row.read_units.to_string(),
+                ]
+            })
+        })
+        .collect::<Vec<_>>();
+
+    match format {
+        OutputFormat::Log => {
+            let mut lines = vec![format!(
+                "The distinct label values in the last {} seconds:",
+                elapsed_secs
+            )];
+            for row in rows {
+                lines.push(format!(
+                    "catalog {}, schema {}, ws: {}, rs: {}, distinct: {:?}",
+                    row.id.catalog, row.id.schema, row.write_units, row.read_units, row.distinct
+                ));
+            }
+            lines
+        }
+        OutputFormat::Table => table(
+            &["CATALOG", "SCHEMA", "LABEL", "DISTINCT", "WS", "RS"],
+            &cells,
+            3,
+        ),
+        OutputFormat::Json => {
+            let schemas = rows
+                .iter()
+                .map(|row| {
+                    json!({
+                        "catalog": row.id.catalog,
+                        "schema": row.id.schema,
+                        "write_units": row.write_units,
+                        "read_units": row.read_units,
+                        "distinct": row.distinct,
+                    })
+                })
+                .collect::<Vec<_>>();
+            vec![json!({
+                "elapsed_secs": elapsed_secs,
+                "distinct": schemas,
+            })
+            .to_string()]
+        }
+        OutputFormat::Csv => csv_lines(
+            &[
+                "catalog",
+                "schema",
+                "label",
+                "distinct",
+                "write_units",
+                "read_units",
+            ],
+            &cells,
+        ),
+    }
+}
+
+fn kind_name(kind: MeterKind) -> &'static str {
+    match kind {
+        MeterKind::Write => "write",
+        MeterKind::Read => "read",
+    }
+}
+
+fn saturating_sum<'a>(values: impl Iterator<Item = &'a u64>) -> u64 {
+    values.fold(0, |sum, value| sum.saturating_add(*value))
+}
+
+/// `current - previous`, saturated to the range of i64.
+fn delta(current: u64, previous: u64) -> i64 {
+    (current as i128 - previous as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+fn percent(value: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        value as f64 * 100.0 / total as f64
+    }
+}
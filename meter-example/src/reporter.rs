@@ -12,61 +12,210 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
 use std::marker::PhantomData;
 use std::sync::Arc;
-use std::time::Duration;
 
+use meter_core::data::current_time_millis;
+use meter_core::data::MeterKind;
 use meter_core::data::MeterRecord;
+use meter_core::distinct::DistinctCollector;
+use meter_core::quantile::QuantileCollector;
+use meter_core::window::WindowedCollector;
+use meter_reporter::report::Report;
+use meter_reporter::report::ReportContext;
+use parking_lot::Mutex;
+use tracing::error;
 use tracing::info;
 
+use crate::collector::SchemaId;
 use crate::collector::SimpleCollector;
+use crate::output::distinct_rows;
+use crate::output::quantile_rows;
+use crate::output::render;
+use crate::output::render_distinct;
+use crate::output::render_quantiles;
+use crate::output::sort_rows;
+use crate::output::usage_rows;
+use crate::output::OutputFormat;
+use crate::output::SortBy;
 
-/// A simple reporter that outputs w/r information to stdout.
+/// A simple reporter that outputs w/r information.
+///
+/// The usage, quantiles and distinct counts in [OutputFormat::Log] are logged
+/// like the rest of the report. The other formats are written to the output,
+/// stdout by default, so that JSON and CSV stay machine-readable.
 pub struct SimpleReporter<W, R> {
     collector: Arc<SimpleCollector<W, R>>,
+    quantiles: Option<Arc<QuantileCollector>>,
+    distinct: Option<Arc<DistinctCollector>>,
+    format: OutputFormat,
+    sort_by: SortBy,
+    limit: Option<usize>,
+    output: Mutex<Box<dyn Write + Send>>,
+    /// The ws and rs of the previous report, for deltas.
+    previous: Mutex<HashMap<SchemaId, (u64, u64)>>,
     p1: PhantomData<W>,
     p2: PhantomData<R>,
 }
 
+/// The quantiles of per-request cost output by [SimpleReporter].
+const QUANTILES: [f64; 2] = [0.5, 0.99];
+
 impl<W, R> SimpleReporter<W, R> {
     pub fn new(collector: Arc<SimpleCollector<W, R>>) -> Self {
         Self {
             collector,
+            quantiles: None,
+            distinct: None,
+            format: OutputFormat::default(),
+            sort_by: SortBy::default(),
+            limit: None,
+            output: Mutex::new(Box::new(io::stdout())),
+            previous: Mutex::default(),
             p1: PhantomData,
             p2: PhantomData,
         }
     }
+
+    /// Also outputs the p50/p99 of per-request w/r values from `quantiles`.
+    pub fn with_quantiles(mut self, quantiles: Arc<QuantileCollector>) -> Self {
+        self.quantiles = Some(quantiles);
+        self
+    }
+
+    /// Also outputs the approximate distinct label values from `distinct`.
+    pub fn with_distinct(mut self, distinct: Arc<DistinctCollector>) -> Self {
+        self.distinct = Some(distinct);
+        self
+    }
+
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_sort_by(mut self, sort_by: SortBy) -> Self {
+        self.sort_by = sort_by;
+        self
+    }
+
+    /// Only outputs the first `limit` schemas after sorting.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Writes the usage, quantiles and distinct counts to `output` instead of
+    /// stdout, unless the format is [OutputFormat::Log].
+    pub fn with_output(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Mutex::new(Box::new(output));
+        self
+    }
+
+    fn output(&self, lines: &[String], what: &str) {
+        if self.format == OutputFormat::Log {
+            for line in lines {
+                info!("{}", line);
+            }
+        } else if let Err(e) = self.write_lines(lines) {
+            error!("failed to write the {}: {}", what, e);
+        }
+    }
+
+    fn write_lines(&self, lines: &[String]) -> io::Result<()> {
+        let mut output = self.output.lock();
+        for line in lines {
+            writeln!(output, "{}", line)?;
+        }
+        output.flush()
+    }
 }
 
-impl<W, R> SimpleReporter<W, R>
+impl<W, R> Report for SimpleReporter<W, R>
 where
     W: Fn(&MeterRecord) -> u64 + Send + Sync,
     R: Fn(&MeterRecord) -> u64 + Send + Sync,
 {
-    pub async fn start(&self) {
-        loop {
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            info!("===============================================================");
-
-            let ws = self.collector.schema_ws();
-            let rs = self.collector.schema_rs();
-            self.collector.clear();
-
-            info!("The number of Ws consumed in the last 5 seconds:");
-            for (id, w_number) in ws {
-                info!(
-                    "catalog {}, schema {}, ws: {}",
-                    id.catalog, id.schema, w_number
-                );
-            }
+    fn report(&self, ctx: &ReportContext) {
+        let secs = ctx.elapsed_millis / 1000;
+        info!("===============================================================");
 
-            info!("The number of Rs consumed in the last 5 seconds:");
-            for (id, r_number) in rs {
-                info!(
-                    "catalog {}, schema {}, rs: {}",
-                    id.catalog, id.schema, r_number
-                );
-            }
+        let ws = self.collector.schema_ws();
+        let rs = self.collector.schema_rs();
+        self.collector.clear();
+
+        let mut rows = {
+            let mut previous = self.previous.lock();
+            let rows = usage_rows(&ws, &rs, &previous);
+            // Schemas unused in this period are compared once, then forgotten.
+            *previous = rows
+                .iter()
+                .filter(|row| row.write_units > 0 || row.read_units > 0)
+                .map(|row| (row.id.clone(), (row.write_units, row.read_units)))
+                .collect();
+            rows
+        };
+        sort_rows(&mut rows, self.sort_by);
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+
+        self.output(&render(&rows, self.format, secs), "usage");
+
+        if let Some(quantiles) = &self.quantiles {
+            let rows = quantile_rows(
+                quantiles.quantiles(MeterKind::Write, &QUANTILES),
+                quantiles.quantiles(MeterKind::Read, &QUANTILES),
+            );
+            quantiles.clear();
+            self.output(&render_quantiles(&rows, self.format, secs), "quantiles");
+        }
+
+        if let Some(distinct) = &self.distinct {
+            let rows = distinct_rows(distinct.drain());
+            self.output(
+                &render_distinct(&rows, self.format, secs),
+                "distinct counts",
+            );
+        }
+    }
+}
+
+/// A reporter that outputs the closed windows of a [WindowedCollector] to stdout.
+pub struct WindowReporter {
+    collector: Arc<WindowedCollector>,
+}
+
+impl WindowReporter {
+    pub fn new(collector: Arc<WindowedCollector>) -> Self {
+        Self { collector }
+    }
+}
+
+impl Report for WindowReporter {
+    fn report(&self, ctx: &ReportContext) {
+        let aggregates = if ctx.is_final {
+            self.collector.flush()
+        } else {
+            // Close windows by wall clock as well, so idle tenants are still reported.
+            self.collector.advance_watermark(current_time_millis());
+            self.collector.drain_closed()
+        };
+
+        for aggregate in aggregates {
+            info!(
+                "window [{}, {}), catalog {}, schema {}, source {}, ws: {}, rs: {}",
+                aggregate.window.start_millis,
+                aggregate.window.end_millis,
+                aggregate.key.catalog,
+                aggregate.key.schema,
+                aggregate.key.source,
+                aggregate.usage.write_units,
+                aggregate.usage.read_units
+            );
         }
     }
 }
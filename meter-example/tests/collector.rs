@@ -0,0 +1,48 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_example::collector::SchemaId;
+use meter_example::collector::SimpleCollector;
+
+#[test]
+fn concurrent_writers_respect_max_keys() {
+    let collector = Arc::new(
+        SimpleCollector::new(|r: &MeterRecord| r.value, |r: &MeterRecord| r.value).with_max_keys(4),
+    );
+
+    let handles = (0..8)
+        .map(|thread| {
+            let collector = collector.clone();
+            std::thread::spawn(move || {
+                for i in 0..100 {
+                    let schema = format!("schema{}", thread * 100 + i);
+                    collector.on_write(MeterRecord::new("greptime".to_string(), schema, 1, 0));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let ws = collector.schema_ws();
+    assert_eq!(ws.len(), 5);
+    assert_eq!(ws.values().sum::<u64>(), 800);
+    assert_eq!(ws[&SchemaId::overflow()], 796);
+    assert_eq!(collector.overflowed_records(), 796);
+}
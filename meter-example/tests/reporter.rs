@@ -0,0 +1,266 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::distinct::DistinctCollector;
+use meter_core::quantile::QuantileCollector;
+use meter_example::collector::SimpleCollector;
+use meter_example::output::OutputFormat;
+use meter_example::output::SortBy;
+use meter_example::reporter::SimpleReporter;
+use meter_reporter::report::Report;
+use meter_reporter::report::ReportContext;
+use parking_lot::Mutex;
+use serde_json::json;
+use serde_json::Value;
+
+type Collector = SimpleCollector<fn(&MeterRecord) -> u64, fn(&MeterRecord) -> u64>;
+
+/// An output shared with the test.
+#[derive(Default, Clone)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn take(&self) -> String {
+        String::from_utf8(std::mem::take(&mut *self.0.lock())).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn collector() -> Arc<Collector> {
+    Arc::new(SimpleCollector::new(|r| r.value, |r| r.value))
+}
+
+fn record(schema: &str, value: u64) -> MeterRecord {
+    MeterRecord::new("greptime".to_string(), schema.to_string(), value, 0)
+}
+
+fn ctx() -> ReportContext {
+    ReportContext {
+        scheduled_millis: 10_000,
+        elapsed_millis: 10_000,
+        is_final: false,
+    }
+}
+
+#[test]
+fn writes_json_to_the_output() {
+    let collector = collector();
+    let output = Buffer::default();
+    let reporter = SimpleReporter::new(collector.clone())
+        .with_format(OutputFormat::Json)
+        .with_output(output.clone());
+
+    collector.on_write(record("db1", 30));
+    collector.on_read(record("db1", 10));
+    reporter.report(&ctx());
+
+    let output = output.take();
+    assert_eq!(output.lines().count(), 1);
+    let value: Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "elapsed_secs": 10,
+            "schemas": [{
+                "catalog": "greptime",
+                "schema": "db1",
+                "write_units": 30,
+                "write_delta": 30,
+                "write_percent": 100.0,
+                "read_units": 10,
+                "read_delta": 10,
+                "read_percent": 100.0,
+            }],
+        })
+    );
+}
+
+#[test]
+fn writes_quoted_csv_with_deltas() {
+    let collector = collector();
+    let output = Buffer::default();
+    let reporter = SimpleReporter::new(collector.clone())
+        .with_format(OutputFormat::Csv)
+        .with_output(output.clone());
+
+    collector.on_write(record("db,1", u64::MAX));
+    reporter.report(&ctx());
+    output.take();
+
+    // The delta from u64::MAX to 0 saturates instead of wrapping.
+    collector.on_write(record("db,1", 0));
+    reporter.report(&ctx());
+
+    let output = output.take();
+    let mut reader = csv::Reader::from_reader(output.as_bytes());
+    let rows = reader
+        .records()
+        .map(|r| r.unwrap().iter().map(str::to_string).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![vec![
+            "greptime",
+            "db,1",
+            "0",
+            &i64::MIN.to_string(),
+            "0.00",
+            "0",
+            "0",
+            "0.00"
+        ]]
+    );
+}
+
+#[test]
+fn sorts_and_limits_rows() {
+    let collector = collector();
+    let output = Buffer::default();
+    let reporter = SimpleReporter::new(collector.clone())
+        .with_format(OutputFormat::Csv)
+        .with_sort_by(SortBy::WriteUnits)
+        .with_limit(2)
+        .with_output(output.clone());
+
+    collector.on_write(record("db1", 10));
+    collector.on_write(record("db2", 30));
+    collector.on_write(record("db3", 20));
+    reporter.report(&ctx());
+
+    let schemas = output
+        .take()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(1).unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(schemas, vec!["db2", "db3"]);
+}
+
+/// The CSV lines written by a report, without the header.
+fn csv_rows(output: &Buffer) -> Vec<String> {
+    output.take().lines().skip(1).map(str::to_string).collect()
+}
+
+#[test]
+fn shows_the_drop_of_schemas_unused_since_the_previous_report() {
+    let collector = collector();
+    let output = Buffer::default();
+    let reporter = SimpleReporter::new(collector.clone())
+        .with_format(OutputFormat::Csv)
+        .with_output(output.clone());
+
+    collector.on_write(record("db1", 10));
+    reporter.report(&ctx());
+    output.take();
+
+    collector.on_write(record("db2", 5));
+    reporter.report(&ctx());
+    assert_eq!(
+        csv_rows(&output),
+        vec![
+            "greptime,db1,0,-10,0.00,0,0,0.00",
+            "greptime,db2,5,5,100.00,0,0,0.00",
+        ]
+    );
+
+    // Only the drop is shown, not the unused schema from then on.
+    reporter.report(&ctx());
+    assert_eq!(csv_rows(&output), vec!["greptime,db2,0,-5,0.00,0,0,0.00"]);
+}
+
+#[test]
+fn sorts_by_total_units_without_overflow() {
+    let collector = collector();
+    let output = Buffer::default();
+    let reporter = SimpleReporter::new(collector.clone())
+        .with_format(OutputFormat::Csv)
+        .with_sort_by(SortBy::TotalUnits)
+        .with_output(output.clone());
+
+    collector.on_write(record("db1", 1));
+    collector.on_write(record("db2", u64::MAX));
+    collector.on_read(record("db2", u64::MAX));
+    collector.on_write(record("db3", u64::MAX));
+    reporter.report(&ctx());
+
+    let schemas = csv_rows(&output)
+        .iter()
+        .map(|line| line.split(',').nth(1).unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(schemas, vec!["db2", "db3", "db1"]);
+}
+
+#[test]
+fn writes_quantiles_and_distinct_counts_in_the_format() {
+    let collector = collector();
+    let quantiles = Arc::new(QuantileCollector::new(0.01, 1024));
+    let distinct = Arc::new(DistinctCollector::new(["user"]));
+    let output = Buffer::default();
+    let reporter = SimpleReporter::new(collector.clone())
+        .with_quantiles(quantiles.clone())
+        .with_distinct(distinct.clone())
+        .with_format(OutputFormat::Json)
+        .with_output(output.clone());
+
+    for user in ["alice", "bob"] {
+        let record = record("db1", 100).with_label("user", user);
+        collector.on_write(record.clone());
+        quantiles.on_write(record.clone());
+        distinct.on_write(record);
+    }
+    reporter.report(&ctx());
+
+    let lines = output
+        .take()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+
+    let quantile = &lines[1]["quantiles"][0];
+    assert_eq!(quantile["schema"], "db1");
+    assert_eq!(quantile["kind"], "write");
+    assert!((quantile["p50"].as_f64().unwrap() - 100.0).abs() <= 1.0);
+    assert!((quantile["p99"].as_f64().unwrap() - 100.0).abs() <= 1.0);
+
+    assert_eq!(
+        lines[2],
+        json!({
+            "elapsed_secs": 10,
+            "distinct": [{
+                "catalog": "greptime",
+                "schema": "db1",
+                "write_units": 200,
+                "read_units": 0,
+                "distinct": {"user": 2},
+            }],
+        })
+    );
+}
@@ -0,0 +1,87 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::io;
+
+/// The error of exporting meter data.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+
+    /// The request could not be sent or its response could not be read.
+    Transport(String),
+
+    /// The server answered with a non-success status.
+    Http {
+        status: u16,
+        body: String,
+    },
+
+    /// The data could not be encoded in the output format.
+    Encode(String),
+
+    /// The local database rejected a statement.
+    Database(String),
+
+    /// The local database is locked by another connection for longer than
+    /// the busy timeout.
+    DatabaseBusy(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether the failed export may succeed if sent again: I/O and transport
+    /// errors, timeouts, throttling, server errors and a busy database.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Io(_) | Error::Transport(_) | Error::DatabaseBusy(_) => true,
+            Error::Http { status, .. } => matches!(status, 408 | 429 | 500..=599),
+            Error::Encode(_) | Error::Database(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Http { status, body } => write!(f, "http status {}: {}", status, body),
+            Error::Encode(e) => write!(f, "encode error: {}", e),
+            Error::Database(e) => write!(f, "database error: {}", e),
+            Error::DatabaseBusy(e) => write!(f, "database busy: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Transport(_)
+            | Error::Http { .. }
+            | Error::Encode(_)
+            | Error::Database(_)
+            | Error::DatabaseBusy(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use meter_core::window::WindowAggregate;
+use tracing::info;
+
+use crate::error::Result;
+use crate::export::Export;
+
+/// The target of the events emitted by [TracingExporter], for filtering.
+pub const TARGET: &str = "greptime_meter";
+
+/// An [Export] that emits one structured `tracing` event per aggregate, so
+/// that a JSON log pipeline can ingest usage without parsing prose.
+///
+/// Events are emitted at the `INFO` level with the target [TARGET] and the
+/// typed fields `catalog`, `schema`, `source`, `window_start`, `window_end`,
+/// `read_units`, `write_units`, `read_count` and `write_count`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use meter_core::window::WindowConfig;
+/// use meter_core::window::WindowedCollector;
+/// use meter_reporter::event::TracingExporter;
+/// use meter_reporter::export::ExportReporter;
+///
+/// let collector = Arc::new(WindowedCollector::new(WindowConfig::tumbling(
+///     Duration::from_secs(60),
+/// )));
+/// let reporter = ExportReporter::new(collector, Arc::new(TracingExporter));
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingExporter;
+
+impl Export for TracingExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        for aggregate in aggregates {
+            info!(
+                target: TARGET,
+                catalog = aggregate.key.catalog.as_str(),
+                schema = aggregate.key.schema.as_str(),
+                source = aggregate.key.source,
+                window_start = aggregate.window.start_millis,
+                window_end = aggregate.window.end_millis,
+                read_units = aggregate.usage.read_units,
+                write_units = aggregate.usage.write_units,
+                read_count = aggregate.usage.read_count,
+                write_count = aggregate.usage.write_count,
+                "meter usage"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use meter_core::data::current_time_millis;
+use meter_core::wal::DurableCollector;
+use meter_core::window::WindowAggregate;
+use meter_core::window::WindowedCollector;
+use parking_lot::Mutex;
+use tracing::error;
+
+use crate::error::Result;
+use crate::report::Report;
+use crate::report::ReportContext;
+
+/// Trait representing a destination of meter aggregates.
+///
+/// Most exporters add aggregates up, into counters, archives or billing
+/// totals, so they expect tumbling windows: the aggregates of hopping windows
+/// overlap and would count every record several times.
+pub trait Export: Send + Sync {
+    /// Exports the aggregates of closed windows.
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()>;
+
+    /// Called by [ExportReporter] after every run, whether it exported
+    /// aggregates or not, e.g. to rotate files on schedule. On the final run,
+    /// exporters should finish whatever they hold.
+    fn on_report(&self, _ctx: &ReportContext) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A [Report] that hands the closed windows of a [WindowedCollector] to an
+/// [Export] on every run.
+///
+/// Aggregates that fail to export are logged and dropped, unless the
+/// collector is fed through a [DurableCollector] set with
+/// [ExportReporter::with_wal]. Then the log is only acknowledged once the
+/// export succeeded, and failed aggregates are exported again, together with
+/// the next ones, on the following runs.
+pub struct ExportReporter {
+    collector: Arc<WindowedCollector>,
+    exporter: Arc<dyn Export>,
+    wal: Option<Arc<DurableCollector>>,
+    /// The aggregates drained from the log but not exported yet.
+    unacked: Mutex<Vec<WindowAggregate>>,
+}
+
+impl ExportReporter {
+    pub fn new(collector: Arc<WindowedCollector>, exporter: Arc<dyn Export>) -> Self {
+        Self {
+            collector,
+            exporter,
+            wal: None,
+            unacked: Mutex::default(),
+        }
+    }
+
+    /// Acknowledges the records of `wal`, whose inner collector is the
+    /// collector of this reporter, once their aggregates are exported.
+    pub fn with_wal(mut self, wal: Arc<DurableCollector>) -> Self {
+        self.wal = Some(wal);
+        self
+    }
+
+    fn drain(&self, ctx: &ReportContext) -> Vec<WindowAggregate> {
+        if ctx.is_final {
+            self.collector.flush()
+        } else {
+            // Close windows by wall clock as well, so idle tenants are still reported.
+            self.collector.advance_watermark(current_time_millis());
+            self.collector.drain_closed()
+        }
+    }
+
+    fn export(&self, aggregates: &[WindowAggregate]) -> bool {
+        if aggregates.is_empty() {
+            return true;
+        }
+        match self.exporter.export(aggregates) {
+            Ok(()) => true,
+            Err(e) => {
+                error!(
+                    "[meter]failed to export {} aggregates: {}",
+                    aggregates.len(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    fn export_durable(&self, wal: &DurableCollector, ctx: &ReportContext) {
+        let (checkpoint, aggregates) = match wal.checkpoint(|| self.drain(ctx)) {
+            Ok(drained) => drained,
+            Err(e) => {
+                error!("[meter]failed to checkpoint wal: {}", e);
+                return;
+            }
+        };
+
+        let mut unacked = self.unacked.lock();
+        unacked.extend(aggregates);
+        if !self.export(&unacked) {
+            return;
+        }
+        unacked.clear();
+        if let Err(e) = wal.ack(checkpoint) {
+            error!("[meter]failed to acknowledge wal: {}", e);
+        }
+    }
+}
+
+impl Report for ExportReporter {
+    fn report(&self, ctx: &ReportContext) {
+        match &self.wal {
+            Some(wal) => self.export_durable(wal, ctx),
+            None => {
+                self.export(&self.drain(ctx));
+            }
+        }
+
+        if let Err(e) = self.exporter.on_report(ctx) {
+            error!("[meter]failed to finish export run: {}", e);
+        }
+    }
+}
@@ -0,0 +1,688 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exporters writing aggregates and raw records to local files, rotated by
+//! size and age, for offline reconciliation.
+//!
+//! Files are written under a temporary name and renamed once finished, so a
+//! file with its final name is always complete.
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use flate2::write::GzEncoder;
+use meter_core::collect::Collect;
+use meter_core::data::current_time_millis;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_core::window::WindowAggregate;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::error;
+use tracing::warn;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::export::Export;
+use crate::report::Report;
+use crate::report::ReportContext;
+
+/// The layout of the exported files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// One JSON object per line.
+    Jsonl,
+
+    /// Comma-separated values with a header line.
+    Csv,
+
+    /// Parquet files partitioned by date and catalog, e.g.
+    /// `date=2024-01-01/catalog=greptime/`.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// How the exported files are compressed. Parquet applies it to its pages
+/// rather than the whole file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Where and how a file exporter writes.
+#[derive(Debug, Clone)]
+pub struct FileConfig {
+    /// The directory of the files.
+    pub dir: PathBuf,
+
+    /// The start of every file name, followed by its creation time and a
+    /// sequence number.
+    pub prefix: String,
+
+    pub format: FileFormat,
+
+    pub compression: Compression,
+
+    /// Files are finished once this many bytes were written, before
+    /// compression for JSONL and CSV.
+    pub max_bytes: u64,
+
+    /// Files are finished by the first report this long after they were
+    /// created.
+    pub max_age: Duration,
+}
+
+impl FileConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: "meter".to_string(),
+            format: FileFormat::Jsonl,
+            compression: Compression::None,
+            max_bytes: 64 * 1024 * 1024,
+            max_age: Duration::from_secs(3600),
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    fn extension(&self) -> String {
+        let base = match self.format {
+            FileFormat::Jsonl => "jsonl",
+            FileFormat::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet => return "parquet".to_string(),
+        };
+        match self.compression {
+            Compression::None => base.to_string(),
+            Compression::Gzip => format!("{}.gz", base),
+            Compression::Zstd => format!("{}.zst", base),
+        }
+    }
+}
+
+/// A row of the aggregate files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AggregateRow {
+    pub window_start: u64,
+    pub window_end: u64,
+    pub catalog: String,
+    pub schema: String,
+    pub source: u8,
+    pub read_units: u64,
+    pub write_units: u64,
+    pub read_count: u64,
+    pub write_count: u64,
+}
+
+impl From<&WindowAggregate> for AggregateRow {
+    fn from(aggregate: &WindowAggregate) -> Self {
+        Self {
+            window_start: aggregate.window.start_millis,
+            window_end: aggregate.window.end_millis,
+            catalog: aggregate.key.catalog.clone(),
+            schema: aggregate.key.schema.clone(),
+            source: aggregate.key.source,
+            read_units: aggregate.usage.read_units,
+            write_units: aggregate.usage.write_units,
+            read_count: aggregate.usage.read_count,
+            write_count: aggregate.usage.write_count,
+        }
+    }
+}
+
+/// A row of the raw record files. Labels are a JSON object, so that every
+/// format has the same columns.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordRow {
+    pub timestamp: u64,
+    pub kind: String,
+    pub catalog: String,
+    pub schema: String,
+    pub source: u8,
+    pub value: u64,
+    pub id: Option<String>,
+    pub labels: String,
+}
+
+impl RecordRow {
+    pub fn new(kind: MeterKind, record: &MeterRecord) -> Self {
+        let kind = match kind {
+            MeterKind::Read => "read",
+            MeterKind::Write => "write",
+        };
+        Self {
+            timestamp: record.timestamp_millis,
+            kind: kind.to_string(),
+            catalog: record.catalog.clone(),
+            schema: record.schema.clone(),
+            source: record.source,
+            value: record.value,
+            id: record.id.map(|id| id.to_string()),
+            labels: serde_json::to_string(&record.labels).unwrap_or_default(),
+        }
+    }
+}
+
+/// A row that can be written by a [RotatingFiles].
+trait Row: Serialize + Sized {
+    /// The time the row is partitioned by.
+    #[cfg(feature = "parquet")]
+    fn timestamp_millis(&self) -> u64;
+
+    #[cfg(feature = "parquet")]
+    fn catalog(&self) -> &str;
+
+    #[cfg(feature = "parquet")]
+    fn arrow_schema() -> arrow_schema::SchemaRef;
+
+    #[cfg(feature = "parquet")]
+    fn to_batch(rows: &[&Self]) -> Result<arrow_array::RecordBatch>;
+}
+
+impl Row for AggregateRow {
+    #[cfg(feature = "parquet")]
+    fn timestamp_millis(&self) -> u64 {
+        self.window_start
+    }
+
+    #[cfg(feature = "parquet")]
+    fn catalog(&self) -> &str {
+        &self.catalog
+    }
+
+    #[cfg(feature = "parquet")]
+    fn arrow_schema() -> arrow_schema::SchemaRef {
+        parquet::aggregate_schema()
+    }
+
+    #[cfg(feature = "parquet")]
+    fn to_batch(rows: &[&Self]) -> Result<arrow_array::RecordBatch> {
+        parquet::aggregate_batch(rows)
+    }
+}
+
+impl Row for RecordRow {
+    #[cfg(feature = "parquet")]
+    fn timestamp_millis(&self) -> u64 {
+        self.timestamp
+    }
+
+    #[cfg(feature = "parquet")]
+    fn catalog(&self) -> &str {
+        &self.catalog
+    }
+
+    #[cfg(feature = "parquet")]
+    fn arrow_schema() -> arrow_schema::SchemaRef {
+        parquet::record_schema()
+    }
+
+    #[cfg(feature = "parquet")]
+    fn to_batch(rows: &[&Self]) -> Result<arrow_array::RecordBatch> {
+        parquet::record_batch(rows)
+    }
+}
+
+/// An [Export] that writes aggregates to local files.
+///
+/// Files are rotated by size as aggregates are written, and by age on every
+/// report, so rotation follows the reporter schedule. The final report
+/// finishes the open files.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use meter_reporter::export::Export;
+/// use meter_reporter::file::Compression;
+/// use meter_reporter::file::FileConfig;
+/// use meter_reporter::file::FileExporter;
+/// use meter_reporter::file::FileFormat;
+/// use meter_reporter::report::ReportContext;
+///
+/// let dir = std::env::temp_dir().join(format!("meter-doc-{}", std::process::id()));
+/// let exporter = FileExporter::new(
+///     FileConfig::new(&dir)
+///         .with_format(FileFormat::Csv)
+///         .with_compression(Compression::Gzip)
+///         .with_max_age(Duration::from_secs(60)),
+/// );
+///
+/// exporter.export(&[]).unwrap();
+/// exporter
+///     .on_report(&ReportContext {
+///         scheduled_millis: 0,
+///         elapsed_millis: 0,
+///         is_final: true,
+///     })
+///     .unwrap();
+/// # std::fs::remove_dir_all(&dir).ok();
+/// ```
+pub struct FileExporter {
+    files: Mutex<RotatingFiles>,
+}
+
+impl FileExporter {
+    pub fn new(config: FileConfig) -> Self {
+        Self {
+            files: Mutex::new(RotatingFiles::new(config)),
+        }
+    }
+
+    /// Finishes the open files.
+    pub fn rotate(&self) -> Result<()> {
+        self.files.lock().rotate()
+    }
+}
+
+impl Export for FileExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let rows = aggregates
+            .iter()
+            .map(AggregateRow::from)
+            .collect::<Vec<_>>();
+        self.files.lock().write(&rows, current_time_millis())
+    }
+
+    fn on_report(&self, ctx: &ReportContext) -> Result<()> {
+        self.files.lock().on_report(ctx)
+    }
+}
+
+/// A [Collect] that writes raw records to local files when it is reported,
+/// rotating them like a [FileExporter].
+///
+/// Records that fail to be written with an I/O error, e.g. a full disk, are
+/// kept for the next report. Some of them may have been written before the
+/// error, so the files may hold such records twice, with the same ID. The
+/// buffer is bounded, the oldest records are dropped when it is full.
+pub struct FileRecordCollector {
+    max_buffered: usize,
+    buffer: Mutex<Vec<RecordRow>>,
+    dropped: AtomicU64,
+    files: Mutex<RotatingFiles>,
+}
+
+impl FileRecordCollector {
+    pub fn new(config: FileConfig) -> Self {
+        Self {
+            max_buffered: 100_000,
+            buffer: Mutex::default(),
+            dropped: AtomicU64::new(0),
+            files: Mutex::new(RotatingFiles::new(config)),
+        }
+    }
+
+    /// Caps the number of records waiting to be written.
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    /// The number of records waiting to be written.
+    pub fn buffered(&self) -> usize {
+        self.buffer.lock().len()
+    }
+
+    /// The number of records dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, kind: MeterKind, record: MeterRecord) {
+        let row = RecordRow::new(kind, &record);
+        let mut buffer = self.buffer.lock();
+        buffer.push(row);
+        self.truncate(&mut buffer);
+    }
+
+    /// Drops the oldest records beyond the capacity.
+    fn truncate(&self, buffer: &mut Vec<RecordRow>) {
+        let excess = buffer.len().saturating_sub(self.max_buffered);
+        if excess > 0 {
+            buffer.drain(..excess);
+            if self.dropped.fetch_add(excess as u64, Ordering::Relaxed) == 0 {
+                warn!("[meter]record buffer is full, dropping the oldest records");
+            }
+        }
+    }
+}
+
+impl Collect for FileRecordCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.push(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.push(MeterKind::Read, record);
+    }
+}
+
+impl Report for FileRecordCollector {
+    fn report(&self, ctx: &ReportContext) {
+        let rows = std::mem::take(&mut *self.buffer.lock());
+        let mut files = self.files.lock();
+        if !rows.is_empty() {
+            match files.write(&rows, ctx.scheduled_millis) {
+                Ok(()) => {}
+                Err(e) if e.is_retryable() => {
+                    error!(
+                        "[meter]failed to write {} records, keeping them: {}",
+                        rows.len(),
+                        e
+                    );
+                    // Put the rows back in front of those buffered meanwhile.
+                    let mut buffer = self.buffer.lock();
+                    let newer = std::mem::take(&mut *buffer);
+                    buffer.extend(rows);
+                    buffer.extend(newer);
+                    self.truncate(&mut buffer);
+                }
+                Err(e) => error!("[meter]failed to write {} records: {}", rows.len(), e),
+            }
+        }
+        if let Err(e) = files.on_report(ctx) {
+            error!("[meter]failed to rotate record files: {}", e);
+        }
+    }
+}
+
+/// The set of files being written, one per partition, finished together.
+struct RotatingFiles {
+    config: FileConfig,
+    seq: u64,
+    opened_millis: u64,
+    open: BTreeMap<PathBuf, OpenFile>,
+}
+
+impl RotatingFiles {
+    fn new(config: FileConfig) -> Self {
+        Self {
+            config,
+            seq: 0,
+            opened_millis: 0,
+            open: BTreeMap::new(),
+        }
+    }
+
+    fn write<R: Row>(&mut self, rows: &[R], now_millis: u64) -> Result<()> {
+        let mut partitions: BTreeMap<PathBuf, Vec<&R>> = BTreeMap::new();
+        for row in rows {
+            partitions.entry(self.partition(row)).or_default().push(row);
+        }
+
+        for (dir, rows) in partitions {
+            if self.open.is_empty() {
+                self.opened_millis = now_millis;
+            }
+            if !self.open.contains_key(&dir) {
+                let file = self.create::<R>(&dir, now_millis)?;
+                self.open.insert(dir.clone(), file);
+            }
+            if let Some(file) = self.open.get_mut(&dir) {
+                file.write(&rows)?;
+            }
+        }
+
+        let bytes = self.open.values().map(|file| file.bytes()).sum::<u64>();
+        if bytes >= self.config.max_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn on_report(&mut self, ctx: &ReportContext) -> Result<()> {
+        let max_age = self.config.max_age.as_millis() as u64;
+        let expired = ctx.scheduled_millis.saturating_sub(self.opened_millis) >= max_age;
+        if !self.open.is_empty() && (ctx.is_final || expired) {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes every open file, returning the first error.
+    fn rotate(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for (_, file) in std::mem::take(&mut self.open) {
+            if let Err(e) = file.finish() {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    #[cfg_attr(not(feature = "parquet"), allow(unused_variables))]
+    fn partition<R: Row>(&self, row: &R) -> PathBuf {
+        match self.config.format {
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet => self.config.dir.join(parquet::partition(row)),
+            _ => self.config.dir.clone(),
+        }
+    }
+
+    fn create<R: Row>(&mut self, dir: &Path, now_millis: u64) -> Result<OpenFile> {
+        fs::create_dir_all(dir)?;
+        let name = format!(
+            "{}-{}-{:06}.{}",
+            self.config.prefix,
+            now_millis,
+            self.seq,
+            self.config.extension()
+        );
+        self.seq += 1;
+
+        let path = dir.join(&name);
+        let temp = dir.join(format!(".{}.tmp", name));
+        let file = File::create(&temp)?;
+
+        let encoder = match self.config.format {
+            FileFormat::Jsonl => {
+                Encoder::Jsonl(Counting::new(Sink::new(file, self.config.compression)?))
+            }
+            FileFormat::Csv => Encoder::Csv(Box::new(csv::Writer::from_writer(Counting::new(
+                Sink::new(file, self.config.compression)?,
+            )))),
+            #[cfg(feature = "parquet")]
+            FileFormat::Parquet => Encoder::Parquet(parquet::Writer::new(
+                file,
+                R::arrow_schema(),
+                self.config.compression,
+            )?),
+        };
+
+        Ok(OpenFile {
+            temp,
+            path,
+            encoder,
+        })
+    }
+}
+
+/// A file being written under its temporary name.
+struct OpenFile {
+    temp: PathBuf,
+    path: PathBuf,
+    encoder: Encoder,
+}
+
+enum Encoder {
+    Jsonl(Counting<Sink>),
+    Csv(Box<csv::Writer<Counting<Sink>>>),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::Writer),
+}
+
+impl OpenFile {
+    fn write<R: Row>(&mut self, rows: &[&R]) -> Result<()> {
+        match &mut self.encoder {
+            Encoder::Jsonl(writer) => {
+                for row in rows {
+                    serde_json::to_writer(&mut *writer, row)
+                        .map_err(|e| Error::Encode(e.to_string()))?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            Encoder::Csv(writer) => {
+                for row in rows {
+                    writer
+                        .serialize(row)
+                        .map_err(|e| Error::Encode(e.to_string()))?;
+                }
+                writer.flush()?;
+            }
+            #[cfg(feature = "parquet")]
+            Encoder::Parquet(writer) => writer.write(&R::to_batch(rows)?)?,
+        }
+        Ok(())
+    }
+
+    /// The bytes written so far.
+    fn bytes(&self) -> u64 {
+        match &self.encoder {
+            Encoder::Jsonl(writer) => writer.bytes,
+            Encoder::Csv(writer) => writer.get_ref().bytes,
+            #[cfg(feature = "parquet")]
+            Encoder::Parquet(writer) => writer.bytes(),
+        }
+    }
+
+    /// Completes the file, syncs it and gives it its final name.
+    fn finish(self) -> Result<()> {
+        let file = match self.encoder {
+            Encoder::Jsonl(writer) => writer.inner.finish()?,
+            Encoder::Csv(writer) => writer
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .inner
+                .finish()?,
+            #[cfg(feature = "parquet")]
+            Encoder::Parquet(writer) => writer.finish()?,
+        };
+        file.sync_all()?;
+        fs::rename(&self.temp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// The possibly compressed stream of a JSONL or CSV file.
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn new(file: File, compression: Compression) -> io::Result<Self> {
+        let writer = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Sink::Plain(writer),
+            Compression::Gzip => Sink::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+            Compression::Zstd => Sink::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    /// Writes the end of the compressed stream and returns the file.
+    fn finish(self) -> io::Result<File> {
+        let writer = match self {
+            Sink::Plain(writer) => writer,
+            Sink::Gzip(writer) => writer.finish()?,
+            Sink::Zstd(writer) => writer.finish()?,
+        };
+        writer.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(writer) => writer.write(buf),
+            Sink::Gzip(writer) => writer.write(buf),
+            Sink::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(writer) => writer.flush(),
+            Sink::Gzip(writer) => writer.flush(),
+            Sink::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Counts the bytes written through it.
+struct Counting<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W> Counting<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, bytes: 0 }
+    }
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
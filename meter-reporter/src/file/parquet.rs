@@ -0,0 +1,166 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arrow_array::ArrayRef;
+use arrow_array::RecordBatch;
+use arrow_array::StringArray;
+use arrow_array::TimestampMillisecondArray;
+use arrow_array::UInt64Array;
+use arrow_array::UInt8Array;
+use arrow_schema::DataType;
+use arrow_schema::Field;
+use arrow_schema::Schema;
+use arrow_schema::SchemaRef;
+use arrow_schema::TimeUnit;
+use chrono::DateTime;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::GzipLevel;
+use parquet::basic::ZstdLevel;
+use parquet::file::properties::WriterProperties;
+
+use super::AggregateRow;
+use super::Compression;
+use super::RecordRow;
+use super::Row;
+use crate::error::Error;
+use crate::error::Result;
+
+/// The directory of a row relative to the export directory, e.g.
+/// `date=2024-01-01/catalog=greptime`, by the UTC date of the row.
+pub(super) fn partition(row: &impl Row) -> PathBuf {
+    let date = DateTime::from_timestamp_millis(row.timestamp_millis() as i64)
+        .unwrap_or_default()
+        .format("%Y-%m-%d");
+    // Keep the catalog a single path component.
+    let catalog = row.catalog().replace(['/', '\\'], "_");
+    PathBuf::from(format!("date={}", date)).join(format!("catalog={}", catalog))
+}
+
+pub(super) fn aggregate_schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Millisecond, None);
+    Arc::new(Schema::new(vec![
+        Field::new("window_start", timestamp.clone(), false),
+        Field::new("window_end", timestamp, false),
+        Field::new("catalog", DataType::Utf8, false),
+        Field::new("schema", DataType::Utf8, false),
+        Field::new("source", DataType::UInt8, false),
+        Field::new("read_units", DataType::UInt64, false),
+        Field::new("write_units", DataType::UInt64, false),
+        Field::new("read_count", DataType::UInt64, false),
+        Field::new("write_count", DataType::UInt64, false),
+    ]))
+}
+
+pub(super) fn aggregate_batch(rows: &[&AggregateRow]) -> Result<RecordBatch> {
+    let columns: Vec<ArrayRef> = vec![
+        timestamps(rows.iter().map(|r| r.window_start)),
+        timestamps(rows.iter().map(|r| r.window_end)),
+        strings(rows.iter().map(|r| r.catalog.as_str())),
+        strings(rows.iter().map(|r| r.schema.as_str())),
+        Arc::new(UInt8Array::from_iter_values(rows.iter().map(|r| r.source))),
+        uints(rows.iter().map(|r| r.read_units)),
+        uints(rows.iter().map(|r| r.write_units)),
+        uints(rows.iter().map(|r| r.read_count)),
+        uints(rows.iter().map(|r| r.write_count)),
+    ];
+    RecordBatch::try_new(aggregate_schema(), columns).map_err(|e| Error::Encode(e.to_string()))
+}
+
+pub(super) fn record_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            false,
+        ),
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("catalog", DataType::Utf8, false),
+        Field::new("schema", DataType::Utf8, false),
+        Field::new("source", DataType::UInt8, false),
+        Field::new("value", DataType::UInt64, false),
+        Field::new("id", DataType::Utf8, true),
+        Field::new("labels", DataType::Utf8, false),
+    ]))
+}
+
+pub(super) fn record_batch(rows: &[&RecordRow]) -> Result<RecordBatch> {
+    let columns: Vec<ArrayRef> = vec![
+        timestamps(rows.iter().map(|r| r.timestamp)),
+        strings(rows.iter().map(|r| r.kind.as_str())),
+        strings(rows.iter().map(|r| r.catalog.as_str())),
+        strings(rows.iter().map(|r| r.schema.as_str())),
+        Arc::new(UInt8Array::from_iter_values(rows.iter().map(|r| r.source))),
+        uints(rows.iter().map(|r| r.value)),
+        Arc::new(StringArray::from_iter(rows.iter().map(|r| r.id.as_deref()))),
+        strings(rows.iter().map(|r| r.labels.as_str())),
+    ];
+    RecordBatch::try_new(record_schema(), columns).map_err(|e| Error::Encode(e.to_string()))
+}
+
+fn timestamps(values: impl Iterator<Item = u64>) -> ArrayRef {
+    Arc::new(TimestampMillisecondArray::from_iter_values(
+        values.map(|v| v as i64),
+    ))
+}
+
+fn strings<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+fn uints(values: impl Iterator<Item = u64>) -> ArrayRef {
+    Arc::new(UInt64Array::from_iter_values(values))
+}
+
+/// A Parquet file, with one row group per write so that its size is known.
+pub(super) struct Writer {
+    inner: ArrowWriter<File>,
+}
+
+impl Writer {
+    pub(super) fn new(file: File, schema: SchemaRef, compression: Compression) -> Result<Self> {
+        let compression = match compression {
+            Compression::None => parquet::basic::Compression::UNCOMPRESSED,
+            Compression::Gzip => parquet::basic::Compression::GZIP(GzipLevel::default()),
+            Compression::Zstd => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
+        };
+        let properties = WriterProperties::builder()
+            .set_compression(compression)
+            .build();
+        let inner = ArrowWriter::try_new(file, schema, Some(properties))
+            .map_err(|e| Error::Encode(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    pub(super) fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.inner
+            .write(batch)
+            .and_then(|_| self.inner.flush())
+            .map_err(|e| Error::Encode(e.to_string()))
+    }
+
+    pub(super) fn bytes(&self) -> u64 {
+        self.inner.bytes_written() as u64
+    }
+
+    /// Writes the footer and returns the file.
+    pub(super) fn finish(self) -> Result<File> {
+        self.inner
+            .into_inner()
+            .map_err(|e| Error::Encode(e.to_string()))
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_core::window::WindowAggregate;
+use parking_lot::Mutex;
+use tracing::error;
+use tracing::warn;
+use ureq::Agent;
+
+use crate::error::Result;
+use crate::export::Export;
+use crate::http;
+use crate::report::Report;
+use crate::report::ReportContext;
+use crate::retry::RetryPolicy;
+
+/// The default table of aggregates.
+pub const DEFAULT_TABLE: &str = "greptime_meter";
+
+/// The default table of raw records.
+pub const DEFAULT_RECORD_TABLE: &str = "greptime_meter_records";
+
+/// The default number of lines sent in one request.
+pub const DEFAULT_BATCH_SIZE: usize = 5000;
+
+/// The tags of the record table set from the record itself. Labels of the
+/// same name are written with the [LABEL_PREFIX].
+const RESERVED_TAGS: [&str; 4] = ["catalog", "schema", "source", "kind"];
+
+/// The prefix of labels named like a [RESERVED_TAGS] entry.
+const LABEL_PREFIX: &str = "label_";
+
+/// An [Export] that writes aggregates into a GreptimeDB database through its
+/// InfluxDB line protocol endpoint, in batches, with retries.
+///
+/// Each aggregate is one row of the aggregate table, tagged by `catalog`,
+/// `schema` and `source`, with the window start as the timestamp:
+///
+/// ```text
+/// greptime_meter,catalog=greptime,schema=public,source=0 read_units=7u,write_units=11u,read_count=1u,write_count=1u,window_end=60000u 0
+/// ```
+///
+/// Raw records are written through a [GreptimeRecordCollector].
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_reporter::greptimedb::GreptimeExporter;
+///
+/// let exporter = GreptimeExporter::new("http://localhost:4000", "public")
+///     .with_table("meter")
+///     .with_basic_auth("greptime", "secret");
+///
+/// assert_eq!(
+///     exporter.url(),
+///     "http://localhost:4000/v1/influxdb/write?db=public&precision=ms"
+/// );
+/// ```
+pub struct GreptimeExporter {
+    url: String,
+    table: String,
+    record_table: String,
+    batch_size: usize,
+    headers: Vec<(String, String)>,
+    retry: RetryPolicy,
+    agent: Agent,
+}
+
+impl GreptimeExporter {
+    /// Creates an exporter writing into database `db` of the GreptimeDB
+    /// frontend serving HTTP at `endpoint`, e.g. `http://localhost:4000`.
+    pub fn new(endpoint: impl AsRef<str>, db: impl AsRef<str>) -> Self {
+        Self {
+            url: format!(
+                "{}/v1/influxdb/write?db={}&precision=ms",
+                endpoint.as_ref().trim_end_matches('/'),
+                http::encode_query_value(db.as_ref())
+            ),
+            table: DEFAULT_TABLE.to_string(),
+            record_table: DEFAULT_RECORD_TABLE.to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            headers: vec![],
+            retry: RetryPolicy::default(),
+            agent: http::agent(http::DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// Sets the table of aggregates.
+    pub fn with_table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Sets the table of raw records.
+    pub fn with_record_table(mut self, record_table: impl Into<String>) -> Self {
+        self.record_table = record_table.into();
+        self
+    }
+
+    /// Sets the maximum number of lines sent in one request.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn with_basic_auth(self, username: &str, password: &str) -> Self {
+        let credentials = STANDARD.encode(format!("{}:{}", username, password));
+        self.with_header("Authorization", format!("Basic {}", credentials))
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http::agent(timeout);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Renders an aggregate as a line of the aggregate table.
+    pub fn aggregate_line(&self, aggregate: &WindowAggregate) -> String {
+        let mut line = escape_name(&self.table);
+        push_tag(&mut line, "catalog", &aggregate.key.catalog);
+        push_tag(&mut line, "schema", &aggregate.key.schema);
+        push_tag(&mut line, "source", &aggregate.key.source.to_string());
+
+        let usage = &aggregate.usage;
+        let _ = write!(
+            line,
+            " read_units={}u,write_units={}u,read_count={}u,write_count={}u,window_end={}u {}",
+            usage.read_units,
+            usage.write_units,
+            usage.read_count,
+            usage.write_count,
+            aggregate.window.end_millis,
+            aggregate.window.start_millis
+        );
+        line
+    }
+
+    /// Renders a record as a line of the record table, tagged by its kind and
+    /// labels besides catalog, schema and source.
+    ///
+    /// A label named `catalog`, `schema`, `source` or `kind` is tagged as
+    /// `label_catalog` and so on, so it cannot override the record's own.
+    pub fn record_line(&self, kind: MeterKind, record: &MeterRecord) -> String {
+        let mut line = escape_name(&self.record_table);
+        push_tag(&mut line, "catalog", &record.catalog);
+        push_tag(&mut line, "schema", &record.schema);
+        push_tag(&mut line, "source", &record.source.to_string());
+        let kind = match kind {
+            MeterKind::Read => "read",
+            MeterKind::Write => "write",
+        };
+        push_tag(&mut line, "kind", kind);
+        for (key, value) in &record.labels {
+            if RESERVED_TAGS.contains(&key.as_str()) {
+                push_tag(&mut line, &format!("{}{}", LABEL_PREFIX, key), value);
+            } else {
+                push_tag(&mut line, key, value);
+            }
+        }
+
+        let _ = write!(line, " value={}u", record.value);
+        if let Some(id) = record.id {
+            let _ = write!(line, ",id=\"{}\"", id);
+        }
+        let _ = write!(line, " {}", record.timestamp_millis);
+        line
+    }
+
+    /// Writes one batch of lines, with retries.
+    fn write_batch(&self, batch: &[String]) -> Result<()> {
+        let body = batch.join("\n");
+        self.retry
+            .run(|| http::post(&self.agent, &self.url, &self.headers, body.as_bytes()))
+            .map(|_| ())
+    }
+}
+
+impl Export for GreptimeExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let lines = aggregates
+            .iter()
+            .map(|aggregate| self.aggregate_line(aggregate))
+            .collect::<Vec<_>>();
+        for batch in lines.chunks(self.batch_size) {
+            self.write_batch(batch)?;
+        }
+        Ok(())
+    }
+}
+
+/// A [Collect] that buffers raw records as lines and writes them into
+/// GreptimeDB through a [GreptimeExporter] whenever it is reported.
+///
+/// Lines of a write that failed with a retryable error are kept for the next
+/// run. A batch the server rejects, e.g. with `400 Bad Request`, would be
+/// rejected again, so it is dropped and counted by
+/// [GreptimeRecordCollector::rejected]. The buffer is bounded, the oldest
+/// lines are dropped when it is full.
+pub struct GreptimeRecordCollector {
+    exporter: Arc<GreptimeExporter>,
+    max_buffered: usize,
+    buffer: Mutex<Vec<String>>,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl GreptimeRecordCollector {
+    pub fn new(exporter: Arc<GreptimeExporter>) -> Self {
+        Self {
+            exporter,
+            max_buffered: 100_000,
+            buffer: Mutex::default(),
+            dropped: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Caps the number of lines waiting to be written.
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    /// The number of lines waiting to be written.
+    pub fn buffered(&self) -> usize {
+        self.buffer.lock().len()
+    }
+
+    /// The number of lines dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of lines dropped because the server rejected them.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, kind: MeterKind, record: MeterRecord) {
+        let line = self.exporter.record_line(kind, &record);
+        let mut buffer = self.buffer.lock();
+        buffer.push(line);
+        self.truncate(&mut buffer);
+    }
+
+    /// Drops the oldest lines beyond the capacity.
+    fn truncate(&self, buffer: &mut Vec<String>) {
+        let excess = buffer.len().saturating_sub(self.max_buffered);
+        if excess > 0 {
+            buffer.drain(..excess);
+            if self.dropped.fetch_add(excess as u64, Ordering::Relaxed) == 0 {
+                warn!("[meter]record buffer is full, dropping the oldest records");
+            }
+        }
+    }
+}
+
+impl Collect for GreptimeRecordCollector {
+    fn on_write(&self, record: MeterRecord) {
+        self.push(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.push(MeterKind::Read, record);
+    }
+}
+
+impl Report for GreptimeRecordCollector {
+    fn report(&self, _ctx: &ReportContext) {
+        let lines = std::mem::take(&mut *self.buffer.lock());
+        if lines.is_empty() {
+            return;
+        }
+
+        let mut written = 0;
+        for batch in lines.chunks(self.exporter.batch_size) {
+            match self.exporter.write_batch(batch) {
+                Ok(()) => {}
+                Err(e) if !e.is_retryable() => {
+                    error!(
+                        "[meter]server rejected {} records, dropping them: {}",
+                        batch.len(),
+                        e
+                    );
+                    self.rejected
+                        .fetch_add(batch.len() as u64, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    error!(
+                        "[meter]failed to write {} records: {}",
+                        lines.len() - written,
+                        e
+                    );
+                    // Put the unwritten lines back in front of those buffered meanwhile.
+                    let mut buffer = self.buffer.lock();
+                    let newer = std::mem::take(&mut *buffer);
+                    buffer.extend(lines.into_iter().skip(written));
+                    buffer.extend(newer);
+                    self.truncate(&mut buffer);
+                    return;
+                }
+            }
+            written += batch.len();
+        }
+    }
+}
+
+/// Escapes a measurement name.
+fn escape_name(name: &str) -> String {
+    escape(name, &[',', ' '])
+}
+
+/// Appends `,key=value`, skipping empty values which line protocol rejects.
+fn push_tag(line: &mut String, key: &str, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    line.push(',');
+    line.push_str(&escape(key, &[',', '=', ' ']));
+    line.push('=');
+    line.push_str(&escape(value, &[',', '=', ' ']));
+}
+
+/// Escapes `special`, backslashes and line breaks, so a value can neither
+/// end the line nor change how the rest of it is parsed.
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => {
+                if special.contains(&c) {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+        }
+    }
+    escaped
+}
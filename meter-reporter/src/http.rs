@@ -0,0 +1,78 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use ureq::Agent;
+
+use crate::error::Error;
+use crate::error::Result;
+
+/// The default timeout of a request made by an HTTP exporter.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builds an agent that reports non-success statuses as responses, so they
+/// can be classified by [Error::is_retryable].
+pub(crate) fn agent(timeout: Duration) -> Agent {
+    Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986, for
+/// a value in a query string.
+pub(crate) fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Posts `body` to `url` and returns the response body of a success status.
+pub(crate) fn post(
+    agent: &Agent,
+    url: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Result<Vec<u8>> {
+    let mut request = agent.post(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+
+    let mut response = request
+        .send(body)
+        .map_err(|e| Error::Transport(e.to_string()))?;
+    let status = response.status().as_u16();
+    let body = response
+        .body_mut()
+        .read_to_vec()
+        .map_err(|e| Error::Transport(e.to_string()))?;
+
+    if (200..300).contains(&status) {
+        Ok(body)
+    } else {
+        Err(Error::Http {
+            status,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
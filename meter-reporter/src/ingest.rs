@@ -0,0 +1,531 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shipping raw records from every node to a central aggregator.
+//!
+//! Nodes collect records with an [IngestExporter], which posts them as
+//! protobuf [IngestBatch]es to the [IngestServer] of the aggregator. The
+//! server hands every record to a [Collect], so the aggregator runs the same
+//! pipeline a single node does. The wire format is:
+//!
+//! ```protobuf
+//! syntax = "proto3";
+//!
+//! package greptime.meter.v1;
+//!
+//! enum IngestKind {
+//!   INGEST_KIND_WRITE = 0;
+//!   INGEST_KIND_READ = 1;
+//! }
+//!
+//! message IngestRecord {
+//!   IngestKind kind = 1;
+//!   string catalog = 2;
+//!   string schema = 3;
+//!   uint64 value = 4;
+//!   uint32 source = 5;
+//!   uint64 timestamp_millis = 6;
+//!   map<string, string> labels = 7;
+//!   // The big-endian record ID, empty if unset.
+//!   bytes id = 8;
+//! }
+//!
+//! message IngestBatch {
+//!   repeated IngestRecord records = 1;
+//! }
+//! ```
+//!
+//! Batches are posted to [INGEST_PATH] over plain HTTP with the
+//! [CONTENT_TYPE] content type.
+//!
+//! A batch retried after a timeout may have been processed already. Every
+//! record carries a [RecordId], so put a
+//! [DedupCollector](meter_core::dedup::DedupCollector) in front of the
+//! collector of the server to count such records once.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_core::id::RecordId;
+use meter_core::id::RecordIdGenerator;
+use parking_lot::Mutex;
+use prost::Message;
+use tiny_http::Method;
+use tiny_http::Request;
+use tiny_http::Response;
+use tiny_http::Server;
+use tracing::error;
+use tracing::warn;
+use ureq::Agent;
+
+use crate::error::Error;
+use crate::http;
+use crate::report::Report;
+use crate::report::ReportContext;
+use crate::retry::RetryPolicy;
+
+/// The path batches are posted to.
+pub const INGEST_PATH: &str = "/v1/meter/records";
+
+/// The content type of an encoded [IngestBatch].
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// The largest request body the server accepts.
+pub const MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+
+const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// Whether an [IngestRecord] was written or read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum IngestKind {
+    Write = 0,
+    Read = 1,
+}
+
+/// A [MeterRecord] on the wire.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IngestRecord {
+    #[prost(enumeration = "IngestKind", tag = "1")]
+    pub kind: i32,
+    #[prost(string, tag = "2")]
+    pub catalog: String,
+    #[prost(string, tag = "3")]
+    pub schema: String,
+    #[prost(uint64, tag = "4")]
+    pub value: u64,
+    #[prost(uint32, tag = "5")]
+    pub source: u32,
+    #[prost(uint64, tag = "6")]
+    pub timestamp_millis: u64,
+    #[prost(btree_map = "string, string", tag = "7")]
+    pub labels: BTreeMap<String, String>,
+    #[prost(bytes = "vec", tag = "8")]
+    pub id: Vec<u8>,
+}
+
+impl IngestRecord {
+    pub fn new(kind: MeterKind, record: &MeterRecord) -> Self {
+        let kind = match kind {
+            MeterKind::Write => IngestKind::Write,
+            MeterKind::Read => IngestKind::Read,
+        };
+        Self {
+            kind: kind as i32,
+            catalog: record.catalog.clone(),
+            schema: record.schema.clone(),
+            value: record.value,
+            source: record.source as u32,
+            timestamp_millis: record.timestamp_millis,
+            labels: record.labels.clone(),
+            id: record
+                .id
+                .map(|id| id.as_u128().to_be_bytes().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Converts back into a record, failing on values no record can hold.
+    pub fn into_record(self) -> Result<(MeterKind, MeterRecord), String> {
+        let kind = match IngestKind::try_from(self.kind) {
+            Ok(IngestKind::Write) => MeterKind::Write,
+            Ok(IngestKind::Read) => MeterKind::Read,
+            Err(_) => return Err(format!("unknown kind {}", self.kind)),
+        };
+        let source = u8::try_from(self.source)
+            .map_err(|_| format!("source {} out of range", self.source))?;
+
+        let mut record = MeterRecord::new(self.catalog, self.schema, self.value, source)
+            .with_timestamp_millis(self.timestamp_millis);
+        record.labels = self.labels;
+        if !self.id.is_empty() {
+            let id = <[u8; 16]>::try_from(self.id.as_slice())
+                .map_err(|_| format!("id of {} bytes, expected 16", self.id.len()))?;
+            record = record.with_id(RecordId::from_u128(u128::from_be_bytes(id)));
+        }
+        Ok((kind, record))
+    }
+}
+
+/// The body of an ingestion request.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IngestBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<IngestRecord>,
+}
+
+/// The HTTP endpoint of the aggregator, which decodes posted batches and
+/// hands their records to a [Collect]. Stopped when dropped.
+///
+/// A batch is rejected as a whole with `400 Bad Request` if any of its
+/// records is invalid, so a client may safely send it again.
+pub struct IngestServer {
+    server: Arc<Server>,
+    local_addr: SocketAddr,
+    received: Arc<AtomicU64>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl IngestServer {
+    /// Serves [INGEST_PATH] at `addr` from a background thread.
+    pub fn start(addr: impl ToSocketAddrs, collector: Arc<dyn Collect>) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        let server = Arc::new(server);
+        let local_addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| io::Error::other("not an ip address"))?;
+        let received = Arc::new(AtomicU64::new(0));
+
+        let handle = {
+            let server = server.clone();
+            let received = received.clone();
+            std::thread::Builder::new()
+                .name("meter-ingest".to_string())
+                .spawn(move || {
+                    for mut request in server.incoming_requests() {
+                        let response = match ingest(&mut request, collector.as_ref()) {
+                            Ok(n) => {
+                                received.fetch_add(n as u64, Ordering::Relaxed);
+                                Response::from_string("")
+                            }
+                            Err((status, message)) => {
+                                Response::from_string(message).with_status_code(status)
+                            }
+                        };
+                        if let Err(e) = request.respond(response) {
+                            warn!("[meter]failed to respond to ingestion request: {}", e);
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            server,
+            local_addr,
+            received,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The number of records handed to the collector so far.
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for IngestServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Decodes a request and collects its records, returning how many there
+/// were, or the status and message to reject it with.
+fn ingest(request: &mut Request, collector: &dyn Collect) -> Result<usize, (u16, String)> {
+    if request.url() != INGEST_PATH {
+        return Err((404, "not found".to_string()));
+    }
+    if *request.method() != Method::Post {
+        return Err((405, "method not allowed".to_string()));
+    }
+
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err((413, "payload too large".to_string()));
+    }
+
+    let batch = IngestBatch::decode(body.as_slice()).map_err(|e| (400, e.to_string()))?;
+    let records = batch
+        .records
+        .into_iter()
+        .map(IngestRecord::into_record)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| (400, e))?;
+
+    let n = records.len();
+    for (kind, record) in records {
+        match kind {
+            MeterKind::Write => collector.on_write(record),
+            MeterKind::Read => collector.on_read(record),
+        }
+    }
+    Ok(n)
+}
+
+/// A [Collect] that buffers records on a node and posts them to the
+/// [IngestServer] of the aggregator whenever it is reported.
+///
+/// Records of a failed post are kept for the next run, unless the server
+/// rejected their batch as invalid: those are dropped and counted by
+/// [IngestExporter::rejected]. Batches too large for the server are split in
+/// halves and sent again. The buffer is bounded, the oldest
+/// records are dropped beyond [IngestExporter::with_max_buffered].
+///
+/// A record without a [RecordId] gets one here, so that the server can drop
+/// the records of a batch sent twice, see the [module](self) docs. The IDs
+/// carry the node ID the exporter is created with, which must differ between
+/// the nodes of an aggregator, or the records of one node would be dropped
+/// as duplicates of another's.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::atomic::AtomicU64;
+/// use std::sync::atomic::Ordering;
+/// use std::sync::Arc;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_reporter::ingest::IngestExporter;
+/// use meter_reporter::ingest::IngestServer;
+/// use meter_reporter::report::Report;
+/// use meter_reporter::report::ReportContext;
+///
+/// struct Count(AtomicU64);
+///
+/// impl Collect for Count {
+///     fn on_write(&self, record: MeterRecord) {
+///         self.0.fetch_add(record.value, Ordering::Relaxed);
+///     }
+///
+///     fn on_read(&self, _record: MeterRecord) {}
+/// }
+///
+/// let count = Arc::new(Count(Default::default()));
+/// let server = IngestServer::start("127.0.0.1:0", count.clone()).unwrap();
+///
+/// let exporter = IngestExporter::new(format!("http://{}", server.local_addr()), 1);
+/// exporter.on_write(MeterRecord::new("greptime".to_string(), "public".to_string(), 7, 0));
+/// exporter.report(&ReportContext {
+///     scheduled_millis: 0,
+///     elapsed_millis: 0,
+///     is_final: false,
+/// });
+///
+/// assert_eq!(server.received(), 1);
+/// assert_eq!(count.0.load(Ordering::Relaxed), 7);
+/// ```
+pub struct IngestExporter {
+    url: String,
+    batch_size: usize,
+    max_buffered: usize,
+    headers: Vec<(String, String)>,
+    retry: RetryPolicy,
+    agent: Agent,
+    ids: RecordIdGenerator,
+    buffer: Mutex<Vec<IngestRecord>>,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl IngestExporter {
+    /// Creates an exporter posting to the [IngestServer] at `endpoint`, e.g.
+    /// `http://aggregator:4010`, from the node `node_id`.
+    pub fn new(endpoint: impl AsRef<str>, node_id: u16) -> Self {
+        let ids = RecordIdGenerator::default();
+        ids.set_node_id(node_id);
+        Self {
+            url: format!("{}{}", endpoint.as_ref().trim_end_matches('/'), INGEST_PATH),
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_buffered: 100_000,
+            headers: vec![("Content-Type".to_string(), CONTENT_TYPE.to_string())],
+            retry: RetryPolicy::default(),
+            agent: http::agent(http::DEFAULT_TIMEOUT),
+            ids,
+            buffer: Mutex::default(),
+            dropped: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the maximum number of records sent in one request.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Caps the number of records waiting to be sent.
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http::agent(timeout);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The number of records waiting to be sent.
+    pub fn buffered(&self) -> usize {
+        self.buffer.lock().len()
+    }
+
+    /// The number of records dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of records dropped because the server rejected their
+    /// batch, which sending again would not change.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, kind: MeterKind, mut record: MeterRecord) {
+        if record.id.is_none() {
+            let id = self.ids.next(record.timestamp_millis);
+            record = record.with_id(id);
+        }
+        let record = IngestRecord::new(kind, &record);
+        let mut buffer = self.buffer.lock();
+        buffer.push(record);
+        self.truncate(&mut buffer);
+    }
+
+    /// Drops the oldest records beyond the capacity.
+    fn truncate(&self, buffer: &mut Vec<IngestRecord>) {
+        let excess = buffer.len().saturating_sub(self.max_buffered);
+        if excess > 0 {
+            buffer.drain(..excess);
+            if self.dropped.fetch_add(excess as u64, Ordering::Relaxed) == 0 {
+                warn!("[meter]record buffer is full, dropping the oldest records");
+            }
+        }
+    }
+
+    /// Posts records in batches of the configured size, retrying every batch.
+    ///
+    /// Fails with the number of records sent or dropped before the first
+    /// batch that may succeed later, along with its error.
+    fn send(&self, records: &[IngestRecord]) -> Result<(), (usize, Error)> {
+        let mut done = 0;
+        for chunk in records.chunks(self.batch_size) {
+            self.send_batch(chunk, &mut done).map_err(|e| (done, e))?;
+        }
+        Ok(())
+    }
+
+    /// Posts one batch, split in halves while it is larger than the server
+    /// accepts. A batch failing with an error that is not retryable is
+    /// dropped. Adds the records sent or dropped to `done`.
+    fn send_batch(&self, records: &[IngestRecord], done: &mut usize) -> Result<(), Error> {
+        let body = IngestBatch {
+            records: records.to_vec(),
+        }
+        .encode_to_vec();
+        let result = if body.len() as u64 > MAX_BODY_BYTES {
+            Err(Error::Http {
+                status: 413,
+                body: "payload too large".to_string(),
+            })
+        } else {
+            self.retry
+                .run(|| http::post(&self.agent, &self.url, &self.headers, &body))
+                .map(|_| ())
+        };
+
+        match result {
+            Ok(()) => {}
+            Err(Error::Http { status: 413, .. }) if records.len() > 1 => {
+                let (first, second) = records.split_at(records.len() / 2);
+                self.send_batch(first, done)?;
+                return self.send_batch(second, done);
+            }
+            Err(e) if !e.is_retryable() => {
+                error!(
+                    "[meter]server rejected {} records, dropping them: {}",
+                    records.len(),
+                    e
+                );
+                self.rejected
+                    .fetch_add(records.len() as u64, Ordering::Relaxed);
+            }
+            Err(e) => return Err(e),
+        }
+        *done += records.len();
+        Ok(())
+    }
+}
+
+impl Collect for IngestExporter {
+    fn on_write(&self, record: MeterRecord) {
+        self.push(MeterKind::Write, record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.push(MeterKind::Read, record);
+    }
+}
+
+impl Report for IngestExporter {
+    fn report(&self, _ctx: &ReportContext) {
+        let records = std::mem::take(&mut *self.buffer.lock());
+        if records.is_empty() {
+            return;
+        }
+
+        if let Err((sent, e)) = self.send(&records) {
+            error!(
+                "[meter]failed to send {} records: {}",
+                records.len() - sent,
+                e
+            );
+            // Put the unsent records back in front of those buffered meanwhile.
+            let mut buffer = self.buffer.lock();
+            let newer = std::mem::take(&mut *buffer);
+            buffer.extend(records.into_iter().skip(sent));
+            buffer.extend(newer);
+            self.truncate(&mut buffer);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod error;
+pub mod event;
+pub mod export;
+#[cfg(feature = "file")]
+pub mod file;
+#[cfg(feature = "greptimedb")]
+pub mod greptimedb;
+#[cfg(any(
+    feature = "greptimedb",
+    feature = "ingest",
+    feature = "otlp",
+    feature = "webhook"
+))]
+mod http;
+#[cfg(feature = "ingest")]
+pub mod ingest;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod report;
+pub mod retry;
+#[cfg(feature = "rollup")]
+pub mod rollup;
+pub mod schedule;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "statsd")]
+pub mod statsd;
+#[cfg(feature = "webhook")]
+pub mod webhook;
@@ -0,0 +1,251 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use meter_core::window::Usage;
+use meter_core::window::WindowAggregate;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceResponse;
+use opentelemetry_proto::tonic::common::v1::any_value;
+use opentelemetry_proto::tonic::common::v1::AnyValue;
+use opentelemetry_proto::tonic::common::v1::InstrumentationScope;
+use opentelemetry_proto::tonic::common::v1::KeyValue;
+use opentelemetry_proto::tonic::metrics::v1::metric;
+use opentelemetry_proto::tonic::metrics::v1::number_data_point;
+use opentelemetry_proto::tonic::metrics::v1::AggregationTemporality;
+use opentelemetry_proto::tonic::metrics::v1::Metric;
+use opentelemetry_proto::tonic::metrics::v1::NumberDataPoint;
+use opentelemetry_proto::tonic::metrics::v1::ResourceMetrics;
+use opentelemetry_proto::tonic::metrics::v1::ScopeMetrics;
+use opentelemetry_proto::tonic::metrics::v1::Sum;
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use tracing::warn;
+use ureq::Agent;
+
+use crate::error::Result;
+use crate::export::Export;
+use crate::http;
+use crate::retry::RetryPolicy;
+
+/// The exported sums: name, unit, description and the value of a [Usage].
+const METRICS: [OtlpMetric; 4] = [
+    (
+        "greptime.meter.read_units",
+        "{unit}",
+        "Read units consumed.",
+        |u| u.read_units,
+    ),
+    (
+        "greptime.meter.write_units",
+        "{unit}",
+        "Write units consumed.",
+        |u| u.write_units,
+    ),
+    (
+        "greptime.meter.read_requests",
+        "{request}",
+        "Metered read requests.",
+        |u| u.read_count,
+    ),
+    (
+        "greptime.meter.write_requests",
+        "{request}",
+        "Metered write requests.",
+        |u| u.write_count,
+    ),
+];
+
+type OtlpMetric = (&'static str, &'static str, &'static str, fn(&Usage) -> u64);
+
+/// An [Export] that sends aggregates as OpenTelemetry metrics over OTLP/HTTP
+/// with protobuf encoding.
+///
+/// Every aggregate becomes one data point of each monotonic delta Sum,
+/// spanning its window and carrying `catalog`, `schema` and `source`
+/// attributes. Failed requests are retried by the [RetryPolicy].
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_reporter::otlp::OtlpExporter;
+///
+/// let exporter = OtlpExporter::new("http://localhost:4318")
+///     .with_node("datanode-0")
+///     .with_cluster("greptime");
+///
+/// assert_eq!(exporter.url(), "http://localhost:4318/v1/metrics");
+/// ```
+pub struct OtlpExporter {
+    url: String,
+    resource: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    retry: RetryPolicy,
+    agent: Agent,
+}
+
+impl OtlpExporter {
+    /// Creates an exporter sending to the OTLP/HTTP collector at `endpoint`,
+    /// e.g. `http://localhost:4318`. Metrics are posted to `/v1/metrics`.
+    pub fn new(endpoint: impl AsRef<str>) -> Self {
+        Self {
+            url: format!("{}/v1/metrics", endpoint.as_ref().trim_end_matches('/')),
+            resource: vec![],
+            headers: vec![(
+                "Content-Type".to_string(),
+                "application/x-protobuf".to_string(),
+            )],
+            retry: RetryPolicy::default(),
+            agent: http::agent(http::DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// Sets the `node` resource attribute.
+    pub fn with_node(self, node: impl Into<String>) -> Self {
+        self.with_resource_attribute("node", node)
+    }
+
+    /// Sets the `cluster` resource attribute.
+    pub fn with_cluster(self, cluster: impl Into<String>) -> Self {
+        self.with_resource_attribute("cluster", cluster)
+    }
+
+    pub fn with_resource_attribute(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        let key = key.into();
+        self.resource.retain(|(k, _)| *k != key);
+        self.resource.push((key, value.into()));
+        self
+    }
+
+    /// Adds a header to every request, e.g. for authentication.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http::agent(timeout);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Maps the aggregates to an OTLP export request.
+    pub fn to_request(&self, aggregates: &[WindowAggregate]) -> ExportMetricsServiceRequest {
+        let metrics = METRICS
+            .iter()
+            .map(|(name, unit, description, value)| Metric {
+                name: name.to_string(),
+                unit: unit.to_string(),
+                description: description.to_string(),
+                data: Some(metric::Data::Sum(Sum {
+                    data_points: aggregates
+                        .iter()
+                        .map(|aggregate| data_point(aggregate, value(&aggregate.usage)))
+                        .collect(),
+                    aggregation_temporality: AggregationTemporality::Delta as i32,
+                    is_monotonic: true,
+                })),
+                ..Default::default()
+            })
+            .collect();
+
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: self
+                        .resource
+                        .iter()
+                        .map(|(k, v)| string_attribute(k, v))
+                        .collect(),
+                    ..Default::default()
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                        ..Default::default()
+                    }),
+                    metrics,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+}
+
+impl Export for OtlpExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let body = self.to_request(aggregates).encode_to_vec();
+        let response = self
+            .retry
+            .run(|| http::post(&self.agent, &self.url, &self.headers, &body))?;
+
+        if let Ok(response) = ExportMetricsServiceResponse::decode(response.as_slice()) {
+            if let Some(partial) = response.partial_success {
+                if partial.rejected_data_points > 0 {
+                    warn!(
+                        "[meter]otlp receiver rejected {} data points: {}",
+                        partial.rejected_data_points, partial.error_message
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn data_point(aggregate: &WindowAggregate, value: u64) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes: vec![
+            string_attribute("catalog", &aggregate.key.catalog),
+            string_attribute("schema", &aggregate.key.schema),
+            attribute(
+                "source",
+                any_value::Value::IntValue(aggregate.key.source as i64),
+            ),
+        ],
+        start_time_unix_nano: aggregate.window.start_millis.saturating_mul(1_000_000),
+        time_unix_nano: aggregate.window.end_millis.saturating_mul(1_000_000),
+        // OTLP sums are signed, clamp rather than wrap into negative values.
+        value: Some(number_data_point::Value::AsInt(
+            i64::try_from(value).unwrap_or(i64::MAX),
+        )),
+        ..Default::default()
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    attribute(key, any_value::Value::StringValue(value.to_string()))
+}
+
+fn attribute(key: &str, value: any_value::Value) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(value) }),
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use meter_core::window::GroupKey;
+use meter_core::window::Usage;
+use meter_core::window::WindowAggregate;
+use parking_lot::Mutex;
+use tiny_http::Header;
+use tiny_http::Response;
+use tiny_http::Server;
+use tracing::warn;
+
+use crate::error::Result;
+use crate::export::Export;
+
+/// The content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The exported counters: name suffix, help text and the value of a [Usage].
+const METRICS: [Metric; 4] = [
+    ("read_units_total", "Read units consumed.", |u| u.read_units),
+    ("write_units_total", "Write units consumed.", |u| {
+        u.write_units
+    }),
+    ("read_requests_total", "Metered read requests.", |u| {
+        u.read_count
+    }),
+    ("write_requests_total", "Metered write requests.", |u| {
+        u.write_count
+    }),
+];
+
+type Metric = (&'static str, &'static str, fn(&Usage) -> u64);
+
+/// An [Export] that accumulates aggregates into counters labeled by catalog,
+/// schema and source, rendered in the Prometheus text exposition format.
+///
+/// Mount [PrometheusExporter::render] on an existing HTTP server, or start
+/// the embedded one with [PrometheusExporter::serve].
+pub struct PrometheusExporter {
+    namespace: String,
+    counters: Mutex<BTreeMap<GroupKey, Usage>>,
+}
+
+impl Default for PrometheusExporter {
+    fn default() -> Self {
+        Self::new("greptime_meter")
+    }
+}
+
+impl PrometheusExporter {
+    /// Creates an exporter whose metric names start with `namespace`.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            counters: Mutex::default(),
+        }
+    }
+
+    /// Renders the counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock();
+        let mut out = String::new();
+
+        for (suffix, help, value) in METRICS {
+            let name = format!("{}_{}", self.namespace, suffix);
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (key, usage) in counters.iter() {
+                let _ = writeln!(
+                    out,
+                    "{}{{catalog=\"{}\",schema=\"{}\",source=\"{}\"}} {}",
+                    name,
+                    escape(&key.catalog),
+                    escape(&key.schema),
+                    key.source,
+                    value(usage)
+                );
+            }
+        }
+
+        out
+    }
+
+    /// Serves [PrometheusExporter::render] on `GET /metrics` at `addr` from a
+    /// background thread.
+    pub fn serve(self: &Arc<Self>, addr: impl ToSocketAddrs) -> io::Result<PrometheusServer> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        let server = Arc::new(server);
+        let local_addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| io::Error::other("not an ip address"))?;
+
+        let exporter = self.clone();
+        let handle = {
+            let server = server.clone();
+            std::thread::Builder::new()
+                .name("meter-prometheus".to_string())
+                .spawn(move || {
+                    for request in server.incoming_requests() {
+                        let response = if request.url() == "/metrics" {
+                            let header = Header::from_bytes("Content-Type", CONTENT_TYPE).unwrap();
+                            Response::from_string(exporter.render()).with_header(header)
+                        } else {
+                            Response::from_string("not found").with_status_code(404)
+                        };
+                        if let Err(e) = request.respond(response) {
+                            warn!("[meter]failed to respond to prometheus scrape: {}", e);
+                        }
+                    }
+                })?
+        };
+
+        Ok(PrometheusServer {
+            server,
+            local_addr,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Export for PrometheusExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let mut counters = self.counters.lock();
+        for aggregate in aggregates {
+            counters
+                .entry(aggregate.key.clone())
+                .or_default()
+                .merge(&aggregate.usage);
+        }
+        Ok(())
+    }
+}
+
+/// The embedded HTTP endpoint started by [PrometheusExporter::serve], stopped
+/// when dropped.
+pub struct PrometheusServer {
+    server: Arc<Server>,
+    local_addr: SocketAddr,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PrometheusServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for PrometheusServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Escapes a label value as required by the text exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,42 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Information about the current run of a [Report].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportContext {
+    /// The time the run was scheduled at, before jitter.
+    ///
+    /// Unit is millisecond since the Unix epoch.
+    pub scheduled_millis: u64,
+
+    /// The time elapsed since the previous run was scheduled.
+    ///
+    /// Unit is millisecond.
+    pub elapsed_millis: u64,
+
+    /// Whether this is the last run, on shutdown. Reporters should flush
+    /// everything they hold.
+    pub is_final: bool,
+}
+
+/// Trait representing a periodic report of the collected data, driven by a
+/// [ThreadScheduler](crate::schedule::ThreadScheduler), or a `Scheduler` on
+/// tokio.
+pub trait Report: Send + Sync {
+    /// Reports what has been collected since the previous run.
+    ///
+    /// May block, e.g. on network I/O, the schedulers run it off any async
+    /// runtime.
+    fn report(&self, ctx: &ReportContext);
+}
@@ -0,0 +1,139 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::error::Result;
+
+/// How an exporter retries a failed request, with exponential backoff.
+///
+/// Only errors that are [retryable](crate::error::Error::is_retryable) are
+/// retried, and the backoff sleeps the calling thread, which is fine for a
+/// [Report](crate::report::Report) as it runs off the async runtime.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use meter_reporter::retry::RetryPolicy;
+///
+/// let policy = RetryPolicy::default()
+///     .with_initial_backoff(Duration::from_millis(100))
+///     .with_max_backoff(Duration::from_millis(300));
+///
+/// assert_eq!(policy.backoff(0), Duration::from_millis(100));
+/// assert_eq!(policy.backoff(1), Duration::from_millis(200));
+/// assert_eq!(policy.backoff(2), Duration::from_millis(300));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt.
+    pub max_retries: usize,
+
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+
+    /// The upper bound of the delay between two attempts.
+    pub max_backoff: Duration,
+
+    /// Whether each delay is randomized between half and all of the backoff,
+    /// so that clients failing together do not retry together.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            jitter: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay before the retry following `attempt`, counted from zero,
+    /// before jitter.
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let factor = 1u32.checked_shl(attempt as u32).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Runs `f` until it succeeds, fails with an error that is not
+    /// retryable, or runs out of retries.
+    pub fn run<T>(&self, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Ok(v) => return Ok(v),
+                Err(e) if e.is_retryable() && attempt < self.max_retries => {
+                    let backoff = self.delay(attempt);
+                    warn!(
+                        "[meter]attempt {} failed: {}, retrying in {:?}",
+                        attempt + 1,
+                        e,
+                        backoff
+                    );
+                    std::thread::sleep(backoff);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The backoff of `attempt` with jitter applied.
+    fn delay(&self, attempt: usize) -> Duration {
+        let backoff = self.backoff(attempt);
+        if !self.jitter {
+            return backoff;
+        }
+        let half = backoff / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
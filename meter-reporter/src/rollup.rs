@@ -0,0 +1,363 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rollups of window aggregates into daily and monthly totals per catalog,
+//! exported as invoice line items.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Days;
+use chrono::Months;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use chrono::TimeZone;
+use chrono_tz::Tz;
+use meter_core::window::Usage;
+use meter_core::window::WindowAggregate;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::export::Export;
+
+/// The billed meters: name, unit and the quantity of a [Usage].
+const METERS: [Meter; 4] = [
+    ("read_units", "unit", |u| u.read_units),
+    ("write_units", "unit", |u| u.write_units),
+    ("read_requests", "request", |u| u.read_count),
+    ("write_requests", "request", |u| u.write_count),
+];
+
+type Meter = (&'static str, &'static str, fn(&Usage) -> u64);
+
+/// The length of a billing period, starting at local midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BillingPeriod {
+    Daily,
+    Monthly,
+}
+
+impl BillingPeriod {
+    /// The first day of the period containing `date`.
+    fn first_day(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            BillingPeriod::Daily => date,
+            BillingPeriod::Monthly => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// The first day of the period after the one starting on `first_day`.
+    fn next(&self, first_day: NaiveDate) -> NaiveDate {
+        match self {
+            BillingPeriod::Daily => first_day + Days::new(1),
+            BillingPeriod::Monthly => first_day + Months::new(1),
+        }
+    }
+
+    /// The name of the period starting on `first_day`, e.g. `2024-01-31` or
+    /// `2024-01`.
+    fn label(&self, first_day: NaiveDate) -> String {
+        match self {
+            BillingPeriod::Daily => first_day.format("%Y-%m-%d").to_string(),
+            BillingPeriod::Monthly => first_day.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// The quantity of one meter a tenant consumed in a billing period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvoiceLineItem {
+    /// The catalog billed.
+    pub tenant: String,
+
+    /// The local date of a daily period, e.g. `2024-01-31`, or the month of
+    /// a monthly one, e.g. `2024-01`.
+    pub period: String,
+
+    /// The local midnight the period starts at, in milliseconds since the
+    /// Unix epoch.
+    pub period_start: u64,
+
+    /// The local midnight the next period starts at.
+    pub period_end: u64,
+
+    /// `read_units`, `write_units`, `read_requests` or `write_requests`.
+    pub meter: String,
+
+    pub quantity: u64,
+
+    /// `unit` for the units, `request` for the requests.
+    pub unit: String,
+
+    /// Whether the quantity arrived after the period was drained, and so is
+    /// billed on top of the line items drained before.
+    #[serde(default)]
+    pub adjustment: bool,
+}
+
+/// An [Export] that folds aggregates into daily and monthly totals per
+/// catalog, with billing periods starting at midnight in its time zone.
+///
+/// An aggregate belongs to the period its window starts in. Take the line
+/// items of the finished periods with [BillingRollup::drain_closed], or look
+/// at the running totals with [BillingRollup::line_items].
+///
+/// An aggregate is never split, so a window straddling local midnight is
+/// billed entirely to the day it starts in, and logged with a warning.
+/// Windows are aligned to the Unix epoch, so this happens whenever the local
+/// offset is not a multiple of their size: hourly windows straddle midnight in
+/// Asia/Kolkata, at UTC+05:30, and daily ones in every zone but UTC. Use
+/// windows no larger than the offset allows, e.g. 30 minutes for Asia/Kolkata
+/// or 15 minutes for Asia/Kathmandu, at UTC+05:45.
+///
+/// An aggregate arriving after its period was drained is never added to the
+/// drained totals again. It goes to an adjustment, drained as line items
+/// marked [InvoiceLineItem::adjustment] by the next
+/// [BillingRollup::drain_closed].
+///
+/// # Persistence
+///
+/// The totals and the drained periods live only in memory, so a restart
+/// loses the totals of the periods not drained yet. Rebuild them from a
+/// durable store of the aggregates, e.g. a `SqliteArchive`, and call
+/// [BillingRollup::drain_closed] with the time of the last drain before
+/// exporting again, so late aggregates of the drained periods still become
+/// adjustments.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::window::GroupKey;
+/// use meter_core::window::Usage;
+/// use meter_core::window::Window;
+/// use meter_core::window::WindowAggregate;
+/// use meter_reporter::export::Export;
+/// use meter_reporter::rollup::BillingPeriod;
+/// use meter_reporter::rollup::BillingRollup;
+///
+/// let rollup = BillingRollup::new("Asia/Shanghai".parse().unwrap());
+///
+/// let mut usage = Usage::default();
+/// usage.add_write(10);
+/// // 2024-01-31T16:00:00Z, midnight of February 1st in Shanghai.
+/// let start = 1_706_716_800_000;
+/// rollup
+///     .export(&[WindowAggregate {
+///         window: Window::new(start, start + 5_000),
+///         key: GroupKey::new("greptime", "public", 0),
+///         usage,
+///     }])
+///     .unwrap();
+///
+/// let items = rollup.line_items(BillingPeriod::Monthly);
+/// assert_eq!(items.len(), 2);
+/// assert_eq!(items[0].period, "2024-02");
+/// assert_eq!(items[0].meter, "write_units");
+/// assert_eq!(items[0].quantity, 10);
+/// assert_eq!(items[1].meter, "write_requests");
+/// assert_eq!(items[1].quantity, 1);
+/// ```
+pub struct BillingRollup {
+    tz: Tz,
+    state: Mutex<State>,
+}
+
+type TotalKey = (BillingPeriod, NaiveDate, String);
+
+#[derive(Default)]
+struct State {
+    totals: BTreeMap<TotalKey, Usage>,
+
+    /// The usage of periods already drained, by period, first day and tenant.
+    adjustments: BTreeMap<TotalKey, Usage>,
+
+    /// The first day of the earliest period not drained yet, per period.
+    open_from: BTreeMap<BillingPeriod, NaiveDate>,
+}
+
+impl BillingRollup {
+    pub fn new(tz: Tz) -> Self {
+        Self {
+            tz,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The line items of every period so far, including the running ones,
+    /// ordered by period and tenant, followed by the adjustments not drained
+    /// yet. Meters with nothing consumed are left out.
+    pub fn line_items(&self, period: BillingPeriod) -> Vec<InvoiceLineItem> {
+        let state = self.state.lock();
+        let mut items = vec![];
+        for (totals, adjustment) in [(&state.totals, false), (&state.adjustments, true)] {
+            for ((p, first_day, tenant), usage) in totals {
+                if *p == period {
+                    self.push_items(&mut items, period, *first_day, tenant, usage, adjustment);
+                }
+            }
+        }
+        items
+    }
+
+    /// Removes and returns the line items of the periods that ended at or
+    /// before `now_millis`, followed by the adjustments of the periods
+    /// drained before.
+    pub fn drain_closed(&self, period: BillingPeriod, now_millis: u64) -> Vec<InvoiceLineItem> {
+        let mut state = self.state.lock();
+        let closed = state
+            .totals
+            .keys()
+            .filter(|(p, first_day, _)| {
+                *p == period && self.start_millis(period.next(*first_day)) <= now_millis
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut items = vec![];
+        for key in closed {
+            if let Some(usage) = state.totals.remove(&key) {
+                let (_, first_day, tenant) = key;
+                self.push_items(&mut items, period, first_day, &tenant, &usage, false);
+            }
+        }
+
+        let adjustments = std::mem::take(&mut state.adjustments);
+        for (key, usage) in adjustments {
+            let (p, first_day, tenant) = &key;
+            if *p == period {
+                self.push_items(&mut items, period, *first_day, tenant, &usage, true);
+            } else {
+                state.adjustments.insert(key, usage);
+            }
+        }
+
+        // The period containing `now_millis` is still open.
+        let open_from = period.first_day(self.local_date(now_millis));
+        let watermark = state.open_from.entry(period).or_insert(open_from);
+        *watermark = (*watermark).max(open_from);
+        items
+    }
+
+    fn push_items(
+        &self,
+        items: &mut Vec<InvoiceLineItem>,
+        period: BillingPeriod,
+        first_day: NaiveDate,
+        tenant: &str,
+        usage: &Usage,
+        adjustment: bool,
+    ) {
+        let period_start = self.start_millis(first_day);
+        let period_end = self.start_millis(period.next(first_day));
+        for (meter, unit, quantity) in METERS {
+            let quantity = quantity(usage);
+            if quantity == 0 {
+                continue;
+            }
+            items.push(InvoiceLineItem {
+                tenant: tenant.to_string(),
+                period: period.label(first_day),
+                period_start,
+                period_end,
+                meter: meter.to_string(),
+                quantity,
+                unit: unit.to_string(),
+                adjustment,
+            });
+        }
+    }
+
+    /// The local date of a timestamp.
+    fn local_date(&self, millis: u64) -> NaiveDate {
+        DateTime::from_timestamp_millis(millis as i64)
+            .unwrap_or_default()
+            .with_timezone(&self.tz)
+            .date_naive()
+    }
+
+    /// The first instant of a local date. Midnight may be skipped by a
+    /// daylight saving change, then the day starts an hour later.
+    fn start_millis(&self, date: NaiveDate) -> u64 {
+        let start = self
+            .tz
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+            .or_else(|| {
+                let one = NaiveTime::from_hms_opt(1, 0, 0).unwrap_or(NaiveTime::MIN);
+                self.tz.from_local_datetime(&date.and_time(one)).earliest()
+            });
+        start.map_or(0, |start| start.timestamp_millis().max(0) as u64)
+    }
+}
+
+impl Export for BillingRollup {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        for aggregate in aggregates {
+            let date = self.local_date(aggregate.window.start_millis);
+            let last_date = self.local_date(aggregate.window.end_millis.saturating_sub(1));
+            if last_date != date {
+                warn!(
+                    "[meter]window [{}, {}) of catalog {} straddles midnight in {}, billing it to {}",
+                    aggregate.window.start_millis,
+                    aggregate.window.end_millis,
+                    aggregate.key.catalog,
+                    self.tz,
+                    date
+                );
+            }
+            for period in [BillingPeriod::Daily, BillingPeriod::Monthly] {
+                let first_day = period.first_day(date);
+                let drained = state
+                    .open_from
+                    .get(&period)
+                    .is_some_and(|open_from| first_day < *open_from);
+                let totals = if drained {
+                    &mut state.adjustments
+                } else {
+                    &mut state.totals
+                };
+                totals
+                    .entry((period, first_day, aggregate.key.catalog.clone()))
+                    .or_default()
+                    .merge(&aggregate.usage);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes line items as CSV with a header line.
+pub fn write_csv(items: &[InvoiceLineItem], writer: impl Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for item in items {
+        writer
+            .serialize(item)
+            .map_err(|e| Error::Encode(e.to_string()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes line items as a JSON array.
+pub fn write_json(items: &[InvoiceLineItem], writer: impl Write) -> Result<()> {
+    serde_json::to_writer(writer, items).map_err(|e| Error::Encode(e.to_string()))
+}
@@ -0,0 +1,354 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use meter_core::data::current_time_millis;
+use parking_lot::Condvar;
+use parking_lot::Mutex;
+#[cfg(feature = "tokio")]
+use tokio::sync::watch;
+use tracing::error;
+
+use crate::report::Report;
+use crate::report::ReportContext;
+
+/// When a [ThreadScheduler], or a `Scheduler` on tokio, runs its reporter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// The time between two runs.
+    pub interval: Duration,
+
+    /// Whether runs are aligned to wall-clock multiples of the interval,
+    /// e.g. exactly on the minute for a one-minute interval.
+    pub align: bool,
+
+    /// The upper bound of a random delay added to every run, so that many
+    /// nodes do not report at the same instant.
+    pub jitter: Duration,
+}
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "interval must be positive");
+
+        Self {
+            interval,
+            align: false,
+            jitter: Duration::ZERO,
+        }
+    }
+
+    pub fn aligned(mut self) -> Self {
+        self.align = true;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The time of the run following `previous_millis`, before jitter.
+    pub fn next_run(&self, previous_millis: u64) -> u64 {
+        let interval = self.interval.as_millis() as u64;
+        if self.align {
+            (previous_millis / interval + 1) * interval
+        } else {
+            previous_millis + interval
+        }
+    }
+
+    /// A random delay within the jitter.
+    pub fn sample_jitter(&self) -> Duration {
+        let jitter = self.jitter.as_millis() as u64;
+        if jitter == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(fastrand::u64(0..jitter))
+    }
+
+    /// The delay from now until `scheduled_millis`, plus jitter.
+    fn delay_until(&self, scheduled_millis: u64) -> Duration {
+        Duration::from_millis(scheduled_millis.saturating_sub(current_time_millis()))
+            + self.sample_jitter()
+    }
+}
+
+/// Runs a [Report] on a [Schedule] on a dedicated thread, with a final run
+/// on shutdown. Needs no async runtime.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::atomic::AtomicUsize;
+/// use std::sync::atomic::Ordering;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use meter_reporter::report::Report;
+/// use meter_reporter::report::ReportContext;
+/// use meter_reporter::schedule::Schedule;
+/// use meter_reporter::schedule::ThreadScheduler;
+///
+/// #[derive(Default)]
+/// struct CountingReporter(AtomicUsize);
+///
+/// impl Report for CountingReporter {
+///     fn report(&self, ctx: &ReportContext) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let reporter = Arc::new(CountingReporter::default());
+/// let handle = ThreadScheduler::new(Schedule::every(Duration::from_millis(10)), reporter.clone())
+///     .start()
+///     .unwrap();
+///
+/// std::thread::sleep(Duration::from_millis(50));
+/// handle.shutdown();
+///
+/// // Some periodic runs plus the final one.
+/// assert!(reporter.0.load(Ordering::Relaxed) >= 2);
+/// ```
+pub struct ThreadScheduler {
+    schedule: Schedule,
+    reporter: Arc<dyn Report>,
+}
+
+/// Stops a started [ThreadScheduler], on [ThreadSchedulerHandle::shutdown] or
+/// when dropped.
+pub struct ThreadSchedulerHandle {
+    shutdown: Arc<Shutdown>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Shutdown {
+    requested: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Shutdown {
+    /// Waits until `deadline`, returning whether shutdown was requested
+    /// meanwhile.
+    fn wait_until(&self, deadline: Instant) -> bool {
+        let mut requested = self.requested.lock();
+        while !*requested {
+            if self
+                .condvar
+                .wait_until(&mut requested, deadline)
+                .timed_out()
+            {
+                break;
+            }
+        }
+        *requested
+    }
+}
+
+impl ThreadSchedulerHandle {
+    /// Stops scheduling and waits for the final run of the reporter.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        *self.shutdown.requested.lock() = true;
+        self.shutdown.condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("[meter]reporter thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for ThreadSchedulerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl ThreadScheduler {
+    pub fn new(schedule: Schedule, reporter: Arc<dyn Report>) -> Self {
+        Self { schedule, reporter }
+    }
+
+    /// Spawns the schedule on a new thread.
+    pub fn start(self) -> io::Result<ThreadSchedulerHandle> {
+        let shutdown = Arc::new(Shutdown::default());
+        let thread = {
+            let shutdown = shutdown.clone();
+            std::thread::Builder::new()
+                .name("meter-reporter".to_string())
+                .spawn(move || self.run(&shutdown))?
+        };
+        Ok(ThreadSchedulerHandle {
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    fn run(self, shutdown: &Shutdown) {
+        let mut previous = current_time_millis();
+
+        loop {
+            let scheduled = self.schedule.next_run(previous);
+            let deadline = Instant::now() + self.schedule.delay_until(scheduled);
+            if shutdown.wait_until(deadline) {
+                break;
+            }
+
+            self.report(ReportContext {
+                scheduled_millis: scheduled,
+                elapsed_millis: scheduled - previous,
+                is_final: false,
+            });
+            previous = scheduled;
+        }
+
+        let now = current_time_millis();
+        self.report(ReportContext {
+            scheduled_millis: now,
+            elapsed_millis: now.saturating_sub(previous),
+            is_final: true,
+        });
+    }
+
+    fn report(&self, ctx: ReportContext) {
+        let reporter = AssertUnwindSafe(&self.reporter);
+        if std::panic::catch_unwind(|| reporter.report(&ctx)).is_err() {
+            error!("[meter]reporter panicked");
+        }
+    }
+}
+
+/// Runs a [Report] on a [Schedule] on the tokio runtime, with a final run on
+/// shutdown.
+///
+/// Requires the `tokio` feature.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::sync::atomic::AtomicUsize;
+/// use std::sync::atomic::Ordering;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// use meter_reporter::report::Report;
+/// use meter_reporter::report::ReportContext;
+/// use meter_reporter::schedule::Schedule;
+/// use meter_reporter::schedule::Scheduler;
+///
+/// #[derive(Default)]
+/// struct CountingReporter(AtomicUsize);
+///
+/// impl Report for CountingReporter {
+///     fn report(&self, ctx: &ReportContext) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let reporter = Arc::new(CountingReporter::default());
+/// let handle = Scheduler::new(Schedule::every(Duration::from_millis(10)), reporter.clone()).start();
+///
+/// tokio::time::sleep(Duration::from_millis(50)).await;
+/// handle.shutdown().await;
+///
+/// // Some periodic runs plus the final one.
+/// assert!(reporter.0.load(Ordering::Relaxed) >= 2);
+/// # });
+/// ```
+#[cfg(feature = "tokio")]
+pub struct Scheduler {
+    schedule: Schedule,
+    reporter: Arc<dyn Report>,
+}
+
+/// Stops a started [Scheduler].
+#[cfg(feature = "tokio")]
+pub struct SchedulerHandle {
+    shutdown: watch::Sender<bool>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "tokio")]
+impl SchedulerHandle {
+    /// Stops scheduling and waits for the final run of the reporter.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        if let Err(e) = self.task.await {
+            error!("[meter]reporter task failed: {}", e);
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Scheduler {
+    pub fn new(schedule: Schedule, reporter: Arc<dyn Report>) -> Self {
+        Self { schedule, reporter }
+    }
+
+    /// Spawns the schedule on the current tokio runtime.
+    pub fn start(self) -> SchedulerHandle {
+        let (shutdown, rx) = watch::channel(false);
+        let task = tokio::spawn(self.run(rx));
+        SchedulerHandle { shutdown, task }
+    }
+
+    async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        let mut previous = current_time_millis();
+
+        loop {
+            let scheduled = self.schedule.next_run(previous);
+            let delay = self.schedule.delay_until(scheduled);
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.changed() => break,
+            }
+
+            self.report(ReportContext {
+                scheduled_millis: scheduled,
+                elapsed_millis: scheduled - previous,
+                is_final: false,
+            })
+            .await;
+            previous = scheduled;
+        }
+
+        let now = current_time_millis();
+        self.report(ReportContext {
+            scheduled_millis: now,
+            elapsed_millis: now.saturating_sub(previous),
+            is_final: true,
+        })
+        .await;
+    }
+
+    async fn report(&self, ctx: ReportContext) {
+        let reporter = self.reporter.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || reporter.report(&ctx)).await {
+            error!("[meter]reporter panicked: {}", e);
+        }
+    }
+}
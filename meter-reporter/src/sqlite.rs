@@ -0,0 +1,267 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An archive of window aggregates in an embedded SQLite database, for
+//! deployments without a billing backend that still want usage history.
+
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use meter_core::window::Usage;
+use meter_core::window::WindowAggregate;
+use parking_lot::Mutex;
+use rusqlite::params;
+use rusqlite::Connection;
+use rusqlite::ErrorCode;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::export::Export;
+use crate::report::ReportContext;
+
+/// The schema migrations, applied in order. `PRAGMA user_version` holds how
+/// many of them a database has applied, so append new ones and never edit
+/// the released ones.
+const MIGRATIONS: [&str; 2] = [
+    "CREATE TABLE meter_usage (
+        catalog TEXT NOT NULL,
+        schema TEXT NOT NULL,
+        source INTEGER NOT NULL,
+        window_start INTEGER NOT NULL,
+        window_end INTEGER NOT NULL,
+        read_units INTEGER NOT NULL,
+        write_units INTEGER NOT NULL,
+        read_count INTEGER NOT NULL,
+        write_count INTEGER NOT NULL,
+        PRIMARY KEY (catalog, schema, source, window_start, window_end)
+    ) WITHOUT ROWID",
+    "CREATE INDEX meter_usage_window_start ON meter_usage (window_start)",
+];
+
+const UPSERT: &str = "INSERT INTO meter_usage (
+        catalog, schema, source, window_start, window_end,
+        read_units, write_units, read_count, write_count
+    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+    ON CONFLICT (catalog, schema, source, window_start, window_end) DO UPDATE SET
+        read_units = read_units + excluded.read_units,
+        write_units = write_units + excluded.write_units,
+        read_count = read_count + excluded.read_count,
+        write_count = write_count + excluded.write_count";
+
+/// The usage of one schema summed over a time range, by [SqliteArchive::query].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedUsage {
+    pub catalog: String,
+    pub schema: String,
+    pub usage: Usage,
+}
+
+/// An [Export] that stores aggregates in a SQLite database and answers usage
+/// queries over time ranges.
+///
+/// Aggregates of the same window and group key are added up, so exporting a
+/// window in several parts is fine. With a retention, every report deletes
+/// the windows that ended longer than the retention ago.
+///
+/// # Examples
+///
+/// ```rust
+/// use meter_core::window::GroupKey;
+/// use meter_core::window::Usage;
+/// use meter_core::window::Window;
+/// use meter_core::window::WindowAggregate;
+/// use meter_reporter::export::Export;
+/// use meter_reporter::sqlite::SqliteArchive;
+///
+/// let archive = SqliteArchive::open_in_memory().unwrap();
+///
+/// let mut usage = Usage::default();
+/// usage.add_write(10);
+/// let aggregate = |start| WindowAggregate {
+///     window: Window::new(start, start + 1_000),
+///     key: GroupKey::new("greptime", "public", 0),
+///     usage,
+/// };
+/// archive.export(&[aggregate(0), aggregate(1_000), aggregate(2_000)]).unwrap();
+///
+/// let usage = archive.query(0..2_000).unwrap();
+/// assert_eq!(usage.len(), 1);
+/// assert_eq!(usage[0].schema, "public");
+/// assert_eq!(usage[0].usage.write_units, 20);
+/// assert_eq!(usage[0].usage.write_count, 2);
+/// ```
+pub struct SqliteArchive {
+    conn: Mutex<Connection>,
+    retention: Option<Duration>,
+}
+
+impl SqliteArchive {
+    /// Opens or creates the database at `path` and brings its schema up to
+    /// date.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_connection(Connection::open(path).map_err(database)?)
+    }
+
+    /// Creates a database that lives as long as the archive.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory().map_err(database)?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self> {
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            retention: None,
+        })
+    }
+
+    /// Keeps windows for `retention` after they end. Everything is kept by
+    /// default.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// The number of migrations the database has applied.
+    pub fn schema_version(&self) -> Result<usize> {
+        schema_version(&self.conn.lock())
+    }
+
+    /// How long a statement waits for a lock held by another connection
+    /// before failing with [Error::DatabaseBusy], 5 seconds by default.
+    pub fn set_busy_timeout(&self, timeout: Duration) -> Result<()> {
+        self.conn.lock().busy_timeout(timeout).map_err(database)
+    }
+
+    /// Deletes the windows that ended at or before `before_millis`, returning
+    /// how many rows were deleted.
+    pub fn prune(&self, before_millis: u64) -> Result<usize> {
+        // The start bound is implied by the end one, it lets the index help.
+        self.conn
+            .lock()
+            .execute(
+                "DELETE FROM meter_usage WHERE window_start < ?1 AND window_end <= ?1",
+                params![bound(before_millis)],
+            )
+            .map_err(database)
+    }
+
+    /// Sums the usage of every catalog and schema over the windows starting
+    /// within `range`, in milliseconds since the Unix epoch. Rows are ordered
+    /// by catalog and schema.
+    pub fn query(&self, range: Range<u64>) -> Result<Vec<ArchivedUsage>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT catalog, schema,
+                    SUM(read_units), SUM(write_units), SUM(read_count), SUM(write_count)
+                FROM meter_usage
+                WHERE window_start >= ?1 AND window_start < ?2
+                GROUP BY catalog, schema
+                ORDER BY catalog, schema",
+            )
+            .map_err(database)?;
+        let rows = stmt
+            .query_map(params![bound(range.start), bound(range.end)], |row| {
+                Ok(ArchivedUsage {
+                    catalog: row.get(0)?,
+                    schema: row.get(1)?,
+                    usage: Usage {
+                        read_units: row.get(2)?,
+                        write_units: row.get(3)?,
+                        read_count: row.get(4)?,
+                        write_count: row.get(5)?,
+                    },
+                })
+            })
+            .map_err(database)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(database)
+    }
+}
+
+impl Export for SqliteArchive {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction().map_err(database)?;
+        {
+            let mut stmt = tx.prepare_cached(UPSERT).map_err(database)?;
+            for aggregate in aggregates {
+                let usage = &aggregate.usage;
+                stmt.execute(params![
+                    aggregate.key.catalog,
+                    aggregate.key.schema,
+                    aggregate.key.source,
+                    aggregate.window.start_millis,
+                    aggregate.window.end_millis,
+                    usage.read_units,
+                    usage.write_units,
+                    usage.read_count,
+                    usage.write_count,
+                ])
+                .map_err(database)?;
+            }
+        }
+        tx.commit().map_err(database)
+    }
+
+    fn on_report(&self, ctx: &ReportContext) -> Result<()> {
+        if let Some(retention) = self.retention {
+            let retention_millis = retention.as_millis() as u64;
+            self.prune(ctx.scheduled_millis.saturating_sub(retention_millis))?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies the migrations the database has not applied yet, each in its own
+/// transaction together with the version bump.
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version > MIGRATIONS.len() {
+        return Err(Error::Database(format!(
+            "schema version {} is newer than the supported {}",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(database)?;
+        tx.execute_batch(migration).map_err(database)?;
+        tx.pragma_update(None, "user_version", i + 1)
+            .map_err(database)?;
+        tx.commit().map_err(database)?;
+    }
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> Result<usize> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(database)
+}
+
+/// SQLite integers are signed, and no window starts after `i64::MAX`.
+fn bound(millis: u64) -> i64 {
+    millis.min(i64::MAX as u64) as i64
+}
+
+fn database(e: rusqlite::Error) -> Error {
+    match e.sqlite_error_code() {
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+            Error::DatabaseBusy(e.to_string())
+        }
+        _ => Error::Database(e.to_string()),
+    }
+}
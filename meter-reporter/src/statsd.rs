@@ -0,0 +1,252 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::window::WindowAggregate;
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::error::Result;
+use crate::export::Export;
+use crate::report::Report;
+use crate::report::ReportContext;
+
+/// The default payload size of a packet, which fits the usual 1500-byte
+/// Ethernet MTU with room for IP and UDP headers.
+pub const DEFAULT_MTU: usize = 1432;
+
+/// How tags are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsdFormat {
+    /// Plain StatsD has no tags, so they are appended to the metric name,
+    /// e.g. `greptime.meter.read_units.greptime.public.0:7|c`.
+    Statsd,
+
+    /// DogStatsD tags, e.g.
+    /// `greptime.meter.read_units:7|c|#catalog:greptime,schema:public,source:0`.
+    DogStatsd,
+}
+
+/// An exporter that sends aggregates and records as StatsD counters over
+/// UDP, packing as many lines as fit the MTU into each packet.
+///
+/// Aggregates are sent as `read_units`, `write_units`, `read_count` and
+/// `write_count` counters on [Export::export]. Records collected through
+/// [Collect] are sent as `read_units` and `write_units` counters once a
+/// packet is full, and on every report.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::net::UdpSocket;
+///
+/// use meter_core::collect::Collect;
+/// use meter_core::data::MeterRecord;
+/// use meter_reporter::report::Report;
+/// use meter_reporter::report::ReportContext;
+/// use meter_reporter::statsd::StatsdExporter;
+///
+/// let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let exporter = StatsdExporter::new(agent.local_addr().unwrap()).unwrap();
+///
+/// exporter.on_write(MeterRecord::new("greptime".to_string(), "public".to_string(), 7, 0));
+/// exporter.report(&ReportContext {
+///     scheduled_millis: 0,
+///     elapsed_millis: 0,
+///     is_final: false,
+/// });
+///
+/// let mut buf = [0; 1500];
+/// let n = agent.recv(&mut buf).unwrap();
+/// assert_eq!(
+///     std::str::from_utf8(&buf[..n]).unwrap(),
+///     "greptime.meter.write_units:7|c|#catalog:greptime,schema:public,source:0"
+/// );
+/// ```
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    prefix: String,
+    format: StatsdFormat,
+    mtu: usize,
+    pending: Mutex<String>,
+}
+
+impl StatsdExporter {
+    /// Creates an exporter sending to the agent at `addr`, e.g.
+    /// `127.0.0.1:8125`.
+    pub fn new(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(Self {
+            socket,
+            prefix: "greptime.meter".to_string(),
+            format: StatsdFormat::DogStatsd,
+            mtu: DEFAULT_MTU,
+            pending: Mutex::default(),
+        })
+    }
+
+    /// Sets the start of the metric names.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn with_format(mut self, format: StatsdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the maximum payload size of a packet.
+    pub fn with_mtu(mut self, mtu: usize) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Sends the lines buffered from records.
+    pub fn flush(&self) -> io::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock());
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.socket.send(pending.as_bytes()).map(|_| ())
+    }
+
+    /// Renders a counter, or nothing if it is zero.
+    fn line(
+        &self,
+        name: &str,
+        value: u64,
+        catalog: &str,
+        schema: &str,
+        source: u8,
+    ) -> Option<String> {
+        if value == 0 {
+            return None;
+        }
+
+        let catalog = sanitize(catalog);
+        let schema = sanitize(schema);
+        Some(match self.format {
+            StatsdFormat::Statsd => format!(
+                "{}.{}.{}.{}.{}:{}|c",
+                self.prefix, name, catalog, schema, source, value
+            ),
+            StatsdFormat::DogStatsd => format!(
+                "{}.{}:{}|c|#catalog:{},schema:{},source:{}",
+                self.prefix, name, value, catalog, schema, source
+            ),
+        })
+    }
+
+    /// Appends a line to `packet`, first taking out the packet, for the
+    /// caller to send, if the line would not fit.
+    fn pack(&self, packet: &mut String, line: &str) -> Option<String> {
+        let full = (!packet.is_empty() && packet.len() + 1 + line.len() > self.mtu)
+            .then(|| std::mem::take(packet));
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+        full
+    }
+
+    fn collect(&self, name: &str, record: &MeterRecord) {
+        let Some(line) = self.line(
+            name,
+            record.value,
+            &record.catalog,
+            &record.schema,
+            record.source,
+        ) else {
+            return;
+        };
+        // Send outside the lock, so other threads keep packing meanwhile.
+        let full = self.pack(&mut self.pending.lock(), &line);
+        if let Some(full) = full {
+            if let Err(e) = self.socket.send(full.as_bytes()) {
+                warn!("[meter]failed to send statsd packet: {}", e);
+            }
+        }
+    }
+}
+
+impl Export for StatsdExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let mut packet = String::new();
+        for aggregate in aggregates {
+            let usage = &aggregate.usage;
+            let counters = [
+                ("read_units", usage.read_units),
+                ("write_units", usage.write_units),
+                ("read_count", usage.read_count),
+                ("write_count", usage.write_count),
+            ];
+            for (name, value) in counters {
+                let key = &aggregate.key;
+                if let Some(line) = self.line(name, value, &key.catalog, &key.schema, key.source) {
+                    if let Some(full) = self.pack(&mut packet, &line) {
+                        self.socket.send(full.as_bytes())?;
+                    }
+                }
+            }
+        }
+        if !packet.is_empty() {
+            self.socket.send(packet.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn on_report(&self, _ctx: &ReportContext) -> Result<()> {
+        Ok(self.flush()?)
+    }
+}
+
+impl Collect for StatsdExporter {
+    fn on_write(&self, record: MeterRecord) {
+        self.collect("write_units", &record);
+    }
+
+    fn on_read(&self, record: MeterRecord) {
+        self.collect("read_units", &record);
+    }
+}
+
+impl Report for StatsdExporter {
+    fn report(&self, _ctx: &ReportContext) {
+        if let Err(e) = self.flush() {
+            warn!("[meter]failed to send statsd packet: {}", e);
+        }
+    }
+}
+
+/// Replaces the characters that delimit StatsD lines, names and tags.
+fn sanitize(value: &str) -> String {
+    value.replace([':', '|', '@', '#', ',', '\n'], "_")
+}
@@ -0,0 +1,425 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use meter_core::window::WindowAggregate;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use tracing::warn;
+use ureq::Agent;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::export::Export;
+use crate::http;
+use crate::retry::RetryPolicy;
+
+/// The default header carrying the idempotency key of a batch.
+pub const DEFAULT_IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// A JSON value with `{{name}}` placeholders.
+///
+/// A string that is exactly one placeholder is replaced by the value of the
+/// variable, keeping its JSON type. Placeholders within a longer string are
+/// replaced by the variable as text. Unknown variables render as `null`, or
+/// as an empty string within text.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+///
+/// use meter_reporter::webhook::Template;
+/// use serde_json::json;
+///
+/// let template = Template::parse(r#"{"tenant": "{{catalog}}.{{schema}}", "quantity": "{{read_units}}"}"#).unwrap();
+/// let vars = BTreeMap::from([
+///     ("catalog", json!("greptime")),
+///     ("schema", json!("public")),
+///     ("read_units", json!(42)),
+/// ]);
+///
+/// assert_eq!(
+///     template.render(&vars),
+///     json!({"tenant": "greptime.public", "quantity": 42})
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template(Value);
+
+impl Template {
+    pub fn new(value: Value) -> Self {
+        Self(value)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        serde_json::from_str(text)
+            .map(Self)
+            .map_err(|e| Error::Encode(e.to_string()))
+    }
+
+    pub fn render(&self, vars: &BTreeMap<&str, Value>) -> Value {
+        render(&self.0, vars)
+    }
+}
+
+fn render(value: &Value, vars: &BTreeMap<&str, Value>) -> Value {
+    match value {
+        Value::String(text) => {
+            if let Some(name) = placeholder(text) {
+                return vars.get(name).cloned().unwrap_or(Value::Null);
+            }
+            Value::String(substitute(text, vars))
+        }
+        Value::Array(values) => Value::Array(values.iter().map(|v| render(v, vars)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| (k.clone(), render(v, vars)))
+                .collect::<Map<_, _>>(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// The variable name if `text` is a single placeholder.
+fn placeholder(text: &str) -> Option<&str> {
+    let name = text.strip_prefix("{{")?.strip_suffix("}}")?;
+    (!name.contains("{{") && !name.contains("}}")).then(|| name.trim())
+}
+
+fn substitute(text: &str, vars: &BTreeMap<&str, Value>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        match vars.get(rest[start + 2..start + end].trim()) {
+            Some(Value::String(s)) => out.push_str(s),
+            Some(Value::Null) | None => {}
+            Some(v) => out.push_str(&v.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// An [Export] that posts aggregates to an HTTP endpoint as a JSON body built
+/// from templates.
+///
+/// Every aggregate is rendered by the item template with the variables
+/// `catalog`, `schema`, `source`, `window_start`, `window_end`, `read_units`,
+/// `write_units`, `read_count`, `write_count` and `idempotency_key`. The body
+/// template is rendered with `items`, the array of rendered aggregates, and
+/// the `idempotency_key` of the batch, which is also sent as a header.
+///
+/// Keys are derived from the windows, group keys and usage, so a batch keeps
+/// its key across retries and re-drives and the receiver can drop duplicates,
+/// while a different usage of the same window, e.g. of a partial flush
+/// before a restart, gets a new key.
+///
+/// Batches that still fail after the retries are written to the dead-letter
+/// directory, if any, and can be sent again with [WebhookExporter::redrive].
+pub struct WebhookExporter {
+    url: String,
+    body: Template,
+    item: Template,
+    idempotency_header: String,
+    headers: Vec<(String, String)>,
+    retry: RetryPolicy,
+    agent: Agent,
+    dead_letter_dir: Option<PathBuf>,
+}
+
+/// The result of [WebhookExporter::redrive].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Redrive {
+    /// The number of dead letters delivered and removed.
+    pub sent: usize,
+
+    /// The number of dead letters left because they failed again or could
+    /// not be read.
+    pub failed: usize,
+}
+
+impl WebhookExporter {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            body: Template::new(json!({
+                "idempotency_key": "{{idempotency_key}}",
+                "items": "{{items}}",
+            })),
+            item: Template::new(json!({
+                "idempotency_key": "{{idempotency_key}}",
+                "catalog": "{{catalog}}",
+                "schema": "{{schema}}",
+                "source": "{{source}}",
+                "window_start": "{{window_start}}",
+                "window_end": "{{window_end}}",
+                "read_units": "{{read_units}}",
+                "write_units": "{{write_units}}",
+                "read_count": "{{read_count}}",
+                "write_count": "{{write_count}}",
+            })),
+            idempotency_header: DEFAULT_IDEMPOTENCY_HEADER.to_string(),
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            retry: RetryPolicy::default().with_jitter(true),
+            agent: http::agent(http::DEFAULT_TIMEOUT),
+            dead_letter_dir: None,
+        }
+    }
+
+    pub fn with_body_template(mut self, body: Template) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn with_item_template(mut self, item: Template) -> Self {
+        self.item = item;
+        self
+    }
+
+    pub fn with_idempotency_header(mut self, name: impl Into<String>) -> Self {
+        self.idempotency_header = name.into();
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = http::agent(timeout);
+        self
+    }
+
+    /// Sets the directory of batches that failed all retries.
+    pub fn with_dead_letter_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dead_letter_dir = Some(dir.into());
+        self
+    }
+
+    /// Renders the aggregates as a body, returning it with its idempotency
+    /// key.
+    pub fn render(&self, aggregates: &[WindowAggregate]) -> (String, Value) {
+        let mut batch = Fnv::default();
+        let items = aggregates
+            .iter()
+            .map(|aggregate| {
+                let key = item_key(aggregate);
+                batch.write(key.as_bytes());
+                let vars = BTreeMap::from([
+                    ("idempotency_key", json!(key)),
+                    ("catalog", json!(aggregate.key.catalog)),
+                    ("schema", json!(aggregate.key.schema)),
+                    ("source", json!(aggregate.key.source)),
+                    ("window_start", json!(aggregate.window.start_millis)),
+                    ("window_end", json!(aggregate.window.end_millis)),
+                    ("read_units", json!(aggregate.usage.read_units)),
+                    ("write_units", json!(aggregate.usage.write_units)),
+                    ("read_count", json!(aggregate.usage.read_count)),
+                    ("write_count", json!(aggregate.usage.write_count)),
+                ]);
+                self.item.render(&vars)
+            })
+            .collect::<Vec<_>>();
+
+        let key = format!("{:016x}", batch.finish());
+        let vars = BTreeMap::from([
+            ("idempotency_key", json!(key)),
+            ("items", Value::Array(items)),
+        ]);
+        (key, self.body.render(&vars))
+    }
+
+    /// The dead letters waiting to be re-driven, oldest first.
+    pub fn dead_letters(&self) -> Result<Vec<PathBuf>> {
+        let Some(dir) = &self.dead_letter_dir else {
+            return Ok(vec![]);
+        };
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut letters = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                letters.push((entry.metadata()?.modified()?, path));
+            }
+        }
+        letters.sort();
+        Ok(letters.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Sends every dead letter again, with retries, removing those delivered.
+    ///
+    /// A letter that cannot be read or parsed counts as failed and is left in
+    /// place, without stopping the others.
+    pub fn redrive(&self) -> Result<Redrive> {
+        let mut redrive = Redrive::default();
+        for path in self.dead_letters()? {
+            match self.redrive_letter(&path) {
+                Ok(()) => redrive.sent += 1,
+                Err(e) => {
+                    warn!("[meter]failed to redrive {}: {}", path.display(), e);
+                    redrive.failed += 1;
+                }
+            }
+        }
+        Ok(redrive)
+    }
+
+    fn redrive_letter(&self, path: &Path) -> Result<()> {
+        let letter: Value =
+            serde_json::from_slice(&fs::read(path)?).map_err(|e| Error::Encode(e.to_string()))?;
+        let key = letter["idempotency_key"].as_str().unwrap_or_default();
+        self.send(key, &letter["body"])?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn send(&self, key: &str, body: &Value) -> Result<()> {
+        let body = serde_json::to_vec(body).map_err(|e| Error::Encode(e.to_string()))?;
+        let mut headers = self.headers.clone();
+        headers.push((self.idempotency_header.clone(), key.to_string()));
+        self.retry
+            .run(|| http::post(&self.agent, &self.url, &headers, &body))
+            .map(|_| ())
+    }
+
+    /// Writes a batch to the dead-letter directory, under a temporary name
+    /// first so that a re-drive never reads a partial letter.
+    ///
+    /// An existing letter is never replaced: a batch with the same key gets a
+    /// numbered name, e.g. `{key}.1.json`.
+    fn dead_letter(&self, dir: &Path, key: &str, body: Value) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let letter = json!({
+            "idempotency_key": key,
+            "body": body,
+        });
+        let temp = dir.join(format!(".{}.json.tmp", key));
+        fs::write(
+            &temp,
+            serde_json::to_vec(&letter).map_err(|e| Error::Encode(e.to_string()))?,
+        )?;
+
+        // Unlike a rename, linking fails rather than replace the target.
+        let mut n = 0;
+        let linked = loop {
+            let path = match n {
+                0 => dir.join(format!("{}.json", key)),
+                n => dir.join(format!("{}.{}.json", key, n)),
+            };
+            match fs::hard_link(&temp, &path) {
+                Ok(()) => break Ok(path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => break Err(e),
+            }
+        };
+        fs::remove_file(&temp)?;
+        Ok(linked?)
+    }
+}
+
+impl Export for WebhookExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        let (key, body) = self.render(aggregates);
+        let Err(e) = self.send(&key, &body) else {
+            return Ok(());
+        };
+
+        match &self.dead_letter_dir {
+            Some(dir) => {
+                let path = self.dead_letter(dir, &key, body)?;
+                warn!(
+                    "[meter]failed to post {} aggregates: {}, dead-lettered to {}",
+                    aggregates.len(),
+                    e,
+                    path.display()
+                );
+                Ok(())
+            }
+            None => Err(e),
+        }
+    }
+}
+
+/// The idempotency key of an aggregate. The catalog and schema are escaped,
+/// so a `/` within them cannot make two aggregates share a key.
+fn item_key(aggregate: &WindowAggregate) -> String {
+    let usage = &aggregate.usage;
+    format!(
+        "{}/{}/{}/{}-{}/{}-{}-{}-{}",
+        escape_key_part(&aggregate.key.catalog),
+        escape_key_part(&aggregate.key.schema),
+        aggregate.key.source,
+        aggregate.window.start_millis,
+        aggregate.window.end_millis,
+        usage.read_units,
+        usage.write_units,
+        usage.read_count,
+        usage.write_count
+    )
+}
+
+/// Percent-encodes `%` and `/`.
+fn escape_key_part(part: &str) -> String {
+    part.replace('%', "%25").replace('/', "%2F")
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the std hasher, so keys of
+/// dead letters still match after an upgrade.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        // Separate consecutive keys.
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers shared by the integration tests.
+
+// Every test crate uses its own subset of the helpers.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use meter_core::window::GroupKey;
+use meter_core::window::Usage;
+use meter_core::window::Window;
+use meter_core::window::WindowAggregate;
+use meter_reporter::report::ReportContext;
+use parking_lot::Mutex;
+use tiny_http::Response;
+use tiny_http::Server;
+
+/// A local HTTP server answering with the given statuses in turn, then with
+/// the default status, and recording every request.
+pub struct MockServer {
+    pub endpoint: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    server: Arc<Server>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// A request received by a [MockServer].
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8(self.body.clone()).unwrap()
+    }
+}
+
+impl MockServer {
+    pub fn start(statuses: Vec<u16>, default_status: u16) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let endpoint = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let handle = {
+            let server = server.clone();
+            let requests = requests.clone();
+            std::thread::spawn(move || {
+                let mut statuses = statuses.into_iter();
+                for mut request in server.incoming_requests() {
+                    let mut body = vec![];
+                    request.as_reader().read_to_end(&mut body).unwrap();
+                    let headers = request
+                        .headers()
+                        .iter()
+                        .map(|h| (h.field.to_string(), h.value.to_string()))
+                        .collect();
+                    requests.lock().push(Request {
+                        url: request.url().to_string(),
+                        headers,
+                        body,
+                    });
+
+                    let status = statuses.next().unwrap_or(default_status);
+                    let _ = request.respond(Response::empty(status));
+                }
+            })
+        };
+
+        Self {
+            endpoint,
+            requests,
+            server,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// The usage of `greptime.<schema>` from source 0 in the window
+/// `[60_000, 120_000)`. Zero units are not counted as a request.
+pub fn aggregate(schema: &str, read: u64, write: u64) -> WindowAggregate {
+    let mut usage = Usage::default();
+    if read > 0 {
+        usage.add_read(read);
+    }
+    if write > 0 {
+        usage.add_write(write);
+    }
+    WindowAggregate {
+        window: Window::new(60_000, 120_000),
+        key: GroupKey::new("greptime", schema, 0),
+        usage,
+    }
+}
+
+/// Adjusts the defaults of [aggregate].
+pub trait AggregateExt {
+    fn with_catalog(self, catalog: &str) -> Self;
+
+    fn with_source(self, source: u8) -> Self;
+
+    fn with_window(self, start_millis: u64, end_millis: u64) -> Self;
+}
+
+impl AggregateExt for WindowAggregate {
+    fn with_catalog(mut self, catalog: &str) -> Self {
+        self.key.catalog = catalog.to_string();
+        self
+    }
+
+    fn with_source(mut self, source: u8) -> Self {
+        self.key.source = source;
+        self
+    }
+
+    fn with_window(mut self, start_millis: u64, end_millis: u64) -> Self {
+        self.window = Window::new(start_millis, end_millis);
+        self
+    }
+}
+
+/// A periodic, non-final report scheduled at 0.
+pub fn ctx() -> ReportContext {
+    ctx_at(0, false)
+}
+
+pub fn ctx_at(scheduled_millis: u64, is_final: bool) -> ReportContext {
+    ReportContext {
+        scheduled_millis,
+        elapsed_millis: 0,
+        is_final,
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use common::aggregate;
+use common::AggregateExt;
+use meter_reporter::event::TracingExporter;
+use meter_reporter::event::TARGET;
+use meter_reporter::export::Export;
+use parking_lot::Mutex;
+use tracing::field::Field;
+use tracing::field::Visit;
+use tracing::span;
+use tracing::Event;
+use tracing::Level;
+use tracing::Metadata;
+use tracing::Subscriber;
+
+/// An event captured by [CapturingSubscriber], with its fields rendered.
+#[derive(Debug)]
+struct CapturedEvent {
+    target: String,
+    level: Level,
+    fields: BTreeMap<String, String>,
+}
+
+/// A subscriber that keeps every event it receives.
+#[derive(Default, Clone)]
+struct CapturingSubscriber {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl Visit for CapturedEvent {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields
+            .insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.fields
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for CapturingSubscriber {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut captured = CapturedEvent {
+            target: event.metadata().target().to_string(),
+            level: *event.metadata().level(),
+            fields: BTreeMap::new(),
+        };
+        event.record(&mut captured);
+        self.events.lock().push(captured);
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+#[test]
+fn emits_one_event_per_aggregate() {
+    let subscriber = CapturingSubscriber::default();
+    let aggregates = [
+        aggregate("public", 7, 11).with_source(2),
+        aggregate("metrics", 3, 0).with_catalog("tenant"),
+    ];
+    tracing::subscriber::with_default(subscriber.clone(), || {
+        TracingExporter.export(&aggregates).unwrap();
+    });
+
+    let events = subscriber.events.lock();
+    assert_eq!(events.len(), 2);
+    assert!(events
+        .iter()
+        .all(|e| e.target == TARGET && e.level == Level::INFO));
+    assert_eq!(TARGET, "greptime_meter");
+
+    let field = |i: usize, name: &str| events[i].fields[name].as_str();
+    for (name, value) in [
+        ("catalog", "greptime"),
+        ("schema", "public"),
+        ("source", "2"),
+        ("window_start", "60000"),
+        ("window_end", "120000"),
+        ("read_units", "7"),
+        ("write_units", "11"),
+        ("read_count", "1"),
+        ("write_count", "1"),
+    ] {
+        assert_eq!(field(0, name), value, "{}", name);
+    }
+    assert_eq!(field(1, "catalog"), "tenant");
+    assert_eq!(field(1, "schema"), "metrics");
+    assert_eq!(field(1, "read_units"), "3");
+    assert_eq!(field(1, "write_units"), "0");
+}
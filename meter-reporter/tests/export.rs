@@ -0,0 +1,119 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::ctx;
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_core::wal::DurableCollector;
+use meter_core::wal::WalConfig;
+use meter_core::window::WindowAggregate;
+use meter_core::window::WindowConfig;
+use meter_core::window::WindowedCollector;
+use meter_reporter::error::Error;
+use meter_reporter::error::Result;
+use meter_reporter::export::Export;
+use meter_reporter::export::ExportReporter;
+use meter_reporter::report::Report;
+use parking_lot::Mutex;
+
+/// An [Export] that fails while `failing` is set.
+#[derive(Default)]
+struct FlakyExporter {
+    failing: AtomicBool,
+    exported: Mutex<Vec<WindowAggregate>>,
+}
+
+impl Export for FlakyExporter {
+    fn export(&self, aggregates: &[WindowAggregate]) -> Result<()> {
+        if self.failing.load(Ordering::Relaxed) {
+            return Err(Error::Transport("unavailable".to_string()));
+        }
+        self.exported.lock().extend_from_slice(aggregates);
+        Ok(())
+    }
+}
+
+fn windowed() -> Arc<WindowedCollector> {
+    Arc::new(WindowedCollector::new(WindowConfig::tumbling(
+        Duration::from_secs(1),
+    )))
+}
+
+fn open(dir: &Path, inner: Arc<WindowedCollector>) -> Arc<DurableCollector> {
+    let config = WalConfig::new(dir).with_sync_batch(1);
+    Arc::new(DurableCollector::open(config, inner).unwrap())
+}
+
+/// The write units replayed from the log in `dir` after a restart.
+fn replayed(dir: &Path) -> u64 {
+    let inner = windowed();
+    let _wal = open(dir, inner.clone());
+    inner.flush().iter().map(|a| a.usage.write_units).sum()
+}
+
+#[test]
+fn acknowledges_the_wal_only_after_a_successful_export() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter = Arc::new(FlakyExporter::default());
+    exporter.failing.store(true, Ordering::Relaxed);
+
+    let inner = windowed();
+    let wal = open(dir.path(), inner.clone());
+    let reporter = ExportReporter::new(inner, exporter.clone()).with_wal(wal.clone());
+    let record = MeterRecord::new("greptime".into(), "public".into(), 10, 0);
+    wal.on_write(record.with_timestamp_millis(0));
+
+    reporter.report(&ctx());
+    assert!(exporter.exported.lock().is_empty());
+    drop((reporter, wal));
+    // The failed export kept the log.
+    assert_eq!(replayed(dir.path()), 10);
+
+    let inner = windowed();
+    let wal = open(dir.path(), inner.clone());
+    let reporter = ExportReporter::new(inner, exporter.clone()).with_wal(wal.clone());
+    reporter.report(&ctx());
+    assert!(exporter.exported.lock().is_empty());
+
+    // The failed aggregates are exported again on the next run.
+    exporter.failing.store(false, Ordering::Relaxed);
+    reporter.report(&ctx());
+    assert_eq!(exporter.exported.lock()[0].usage.write_units, 10);
+    drop((reporter, wal));
+    // The successful export truncated the log.
+    assert_eq!(replayed(dir.path()), 0);
+}
+
+#[test]
+fn drops_failed_aggregates_without_a_wal() {
+    let exporter = Arc::new(FlakyExporter::default());
+    exporter.failing.store(true, Ordering::Relaxed);
+    let collector = windowed();
+    let reporter = ExportReporter::new(collector.clone(), exporter.clone());
+    let record = MeterRecord::new("greptime".into(), "public".into(), 10, 0);
+    collector.on_write(record.with_timestamp_millis(0));
+
+    reporter.report(&ctx());
+    exporter.failing.store(false, Ordering::Relaxed);
+    reporter.report(&ctx());
+    assert!(exporter.exported.lock().is_empty());
+}
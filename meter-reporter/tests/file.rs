@@ -0,0 +1,316 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use common::aggregate;
+use common::ctx_at;
+use common::AggregateExt;
+use flate2::read::GzDecoder;
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_reporter::export::Export;
+use meter_reporter::file::AggregateRow;
+use meter_reporter::file::Compression;
+use meter_reporter::file::FileConfig;
+use meter_reporter::file::FileExporter;
+use meter_reporter::file::FileFormat;
+use meter_reporter::file::FileRecordCollector;
+use meter_reporter::file::RecordRow;
+use meter_reporter::report::Report;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+const DAY_MILLIS: u64 = 24 * 3600 * 1000;
+
+/// The files under `dir`, recursively, sorted.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(self::files(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .unwrap()
+        .to_string_lossy()
+        .ends_with(".tmp")
+}
+
+fn read_jsonl<T: serde::de::DeserializeOwned>(reader: impl Read) -> Vec<T> {
+    BufReader::new(reader)
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect()
+}
+
+#[test]
+fn finishes_files_on_final_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter = FileExporter::new(FileConfig::new(dir.path()).with_prefix("usage"));
+
+    exporter
+        .export(&[
+            aggregate("public", 1, 0).with_window(0, 60_000),
+            aggregate("public", 2, 0)
+                .with_catalog("other")
+                .with_window(0, 60_000),
+        ])
+        .unwrap();
+    let written = files(dir.path());
+    assert_eq!(written.len(), 1);
+    assert!(is_temp(&written[0]));
+
+    exporter.on_report(&ctx_at(1000, true)).unwrap();
+    let written = files(dir.path());
+    assert_eq!(written.len(), 1);
+    assert!(!is_temp(&written[0]));
+    let name = written[0]
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+    assert!(name.starts_with("usage-") && name.ends_with(".jsonl"));
+
+    let rows: Vec<AggregateRow> = read_jsonl(File::open(&written[0]).unwrap());
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].catalog, "greptime");
+    assert_eq!(rows[1].read_units, 2);
+    assert_eq!(rows[1].window_end, 60_000);
+}
+
+#[test]
+fn rotates_by_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter = FileExporter::new(FileConfig::new(dir.path()).with_max_bytes(1));
+
+    for i in 0..3 {
+        exporter
+            .export(&[aggregate("public", 1, 0).with_window(i, i + 60_000)])
+            .unwrap();
+    }
+
+    let written = files(dir.path());
+    assert_eq!(written.len(), 3);
+    assert!(written.iter().all(|path| !is_temp(path)));
+}
+
+#[test]
+fn rotates_by_age_on_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter =
+        FileExporter::new(FileConfig::new(dir.path()).with_max_age(Duration::from_secs(3600)));
+
+    exporter
+        .export(&[aggregate("public", 1, 0).with_window(0, 60_000)])
+        .unwrap();
+    let now = meter_core::data::current_time_millis();
+
+    exporter.on_report(&ctx_at(now + 1000, false)).unwrap();
+    assert!(is_temp(&files(dir.path())[0]));
+
+    exporter.on_report(&ctx_at(now + 3_600_000, false)).unwrap();
+    assert!(!is_temp(&files(dir.path())[0]));
+
+    // Nothing is open, so later reports create no file.
+    exporter.on_report(&ctx_at(now + 7_200_000, true)).unwrap();
+    assert_eq!(files(dir.path()).len(), 1);
+}
+
+#[test]
+fn writes_gzip_csv() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter = FileExporter::new(
+        FileConfig::new(dir.path())
+            .with_format(FileFormat::Csv)
+            .with_compression(Compression::Gzip),
+    );
+
+    exporter
+        .export(&[
+            aggregate("public", 5, 0).with_window(0, 60_000),
+            aggregate("public", 6, 0).with_window(60_000, 120_000),
+        ])
+        .unwrap();
+    exporter.on_report(&ctx_at(0, true)).unwrap();
+
+    let written = files(dir.path());
+    assert!(written[0].to_string_lossy().ends_with(".csv.gz"));
+    let mut reader = csv::Reader::from_reader(GzDecoder::new(File::open(&written[0]).unwrap()));
+    let rows = reader
+        .deserialize()
+        .collect::<Result<Vec<AggregateRow>, _>>()
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].window_start, 60_000);
+    assert_eq!(rows[1].read_units, 6);
+}
+
+#[test]
+fn writes_zstd_jsonl() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter =
+        FileExporter::new(FileConfig::new(dir.path()).with_compression(Compression::Zstd));
+
+    exporter
+        .export(&[aggregate("public", 5, 0).with_window(0, 60_000)])
+        .unwrap();
+    exporter.on_report(&ctx_at(0, true)).unwrap();
+
+    let written = files(dir.path());
+    assert!(written[0].to_string_lossy().ends_with(".jsonl.zst"));
+    let rows: Vec<AggregateRow> =
+        read_jsonl(zstd::Decoder::new(File::open(&written[0]).unwrap()).unwrap());
+    assert_eq!(rows[0].read_units, 5);
+}
+
+#[test]
+fn partitions_parquet_by_date_and_catalog() {
+    let dir = tempfile::tempdir().unwrap();
+    let exporter = FileExporter::new(
+        FileConfig::new(dir.path())
+            .with_format(FileFormat::Parquet)
+            .with_compression(Compression::Zstd),
+    );
+
+    exporter
+        .export(&[
+            aggregate("public", 1, 0).with_window(0, 60_000),
+            aggregate("public", 2, 0).with_window(60_000, 120_000),
+            aggregate("public", 3, 0)
+                .with_catalog("other")
+                .with_window(0, 60_000),
+            aggregate("public", 4, 0).with_window(DAY_MILLIS, DAY_MILLIS + 60_000),
+        ])
+        .unwrap();
+    exporter.on_report(&ctx_at(0, true)).unwrap();
+
+    let written = files(dir.path());
+    let partitions = written
+        .iter()
+        .map(|path| {
+            path.parent()
+                .unwrap()
+                .strip_prefix(dir.path())
+                .unwrap()
+                .to_path_buf()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        partitions,
+        vec![
+            Path::new("date=1970-01-01").join("catalog=greptime"),
+            Path::new("date=1970-01-01").join("catalog=other"),
+            Path::new("date=1970-01-02").join("catalog=greptime"),
+        ]
+    );
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&written[0]).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let rows = reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>();
+    assert_eq!(rows, 2);
+}
+
+#[test]
+fn writes_raw_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let collector = FileRecordCollector::new(FileConfig::new(dir.path()));
+
+    collector.on_write(
+        MeterRecord::new("greptime".to_string(), "public".to_string(), 5, 1)
+            .with_label("region", "us-east")
+            .with_timestamp_millis(1000),
+    );
+    collector.on_read(
+        MeterRecord::new("greptime".to_string(), "public".to_string(), 2, 0)
+            .with_timestamp_millis(2000),
+    );
+    assert_eq!(collector.buffered(), 2);
+
+    collector.report(&ctx_at(0, true));
+    assert_eq!(collector.buffered(), 0);
+
+    let written = files(dir.path());
+    let rows: Vec<RecordRow> = read_jsonl(File::open(&written[0]).unwrap());
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].kind, "write");
+    assert_eq!(rows[0].labels, r#"{"region":"us-east"}"#);
+    assert_eq!(rows[1].kind, "read");
+    assert_eq!(rows[1].timestamp, 2000);
+}
+
+#[test]
+fn keeps_records_of_failed_writes() {
+    let root = tempfile::tempdir().unwrap();
+    let dir = root.path().join("records");
+    // A file in place of the directory fails every write.
+    std::fs::write(&dir, "").unwrap();
+    let collector = FileRecordCollector::new(FileConfig::new(&dir));
+
+    let record = |value| {
+        MeterRecord::new("greptime".to_string(), "public".to_string(), value, 0)
+            .with_timestamp_millis(value)
+    };
+    collector.on_write(record(1));
+    collector.report(&ctx_at(0, false));
+    assert_eq!(collector.buffered(), 1);
+
+    collector.on_write(record(2));
+    std::fs::remove_file(&dir).unwrap();
+    collector.report(&ctx_at(0, true));
+    assert_eq!(collector.buffered(), 0);
+
+    let rows: Vec<RecordRow> = read_jsonl(File::open(&files(&dir)[0]).unwrap());
+    let values = rows.iter().map(|row| row.value).collect::<Vec<_>>();
+    assert_eq!(values, vec![1, 2]);
+}
+
+#[test]
+fn bounds_the_record_buffer() {
+    let dir = tempfile::tempdir().unwrap();
+    let collector = FileRecordCollector::new(FileConfig::new(dir.path())).with_max_buffered(2);
+
+    for value in 0..5 {
+        collector.on_write(MeterRecord::new(
+            "greptime".to_string(),
+            "public".to_string(),
+            value,
+            0,
+        ));
+    }
+    assert_eq!(collector.buffered(), 2);
+    assert_eq!(collector.dropped(), 3);
+
+    collector.report(&ctx_at(0, true));
+    let rows: Vec<RecordRow> = read_jsonl(File::open(&files(dir.path())[0]).unwrap());
+    let values = rows.iter().map(|row| row.value).collect::<Vec<_>>();
+    assert_eq!(values, vec![3, 4]);
+}
@@ -0,0 +1,227 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::aggregate;
+use common::ctx;
+use common::MockServer;
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_reporter::export::Export;
+use meter_reporter::greptimedb::GreptimeExporter;
+use meter_reporter::greptimedb::GreptimeRecordCollector;
+use meter_reporter::report::Report;
+use meter_reporter::retry::RetryPolicy;
+
+fn exporter(server: &MockServer) -> GreptimeExporter {
+    GreptimeExporter::new(&server.endpoint, "meter").with_retry(
+        RetryPolicy::default()
+            .with_max_retries(2)
+            .with_initial_backoff(Duration::from_millis(10)),
+    )
+}
+
+#[test]
+fn writes_aggregates_as_line_protocol() {
+    let server = MockServer::start(vec![], 204);
+    let exporter = exporter(&server)
+        .with_table("usage")
+        .with_basic_auth("greptime", "secret");
+
+    exporter
+        .export(&[aggregate("public", 7, 11), aggregate("my schema", 1, 0)])
+        .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url, "/v1/influxdb/write?db=meter&precision=ms");
+    assert_eq!(
+        requests[0].header("Authorization"),
+        Some("Basic Z3JlcHRpbWU6c2VjcmV0")
+    );
+    assert_eq!(
+        requests[0].text().lines().collect::<Vec<_>>(),
+        vec![
+            "usage,catalog=greptime,schema=public,source=0 read_units=7u,write_units=11u,read_count=1u,write_count=1u,window_end=120000u 60000",
+            "usage,catalog=greptime,schema=my\\ schema,source=0 read_units=1u,write_units=0u,read_count=1u,write_count=0u,window_end=120000u 60000",
+        ]
+    );
+}
+
+#[test]
+fn splits_batches() {
+    let server = MockServer::start(vec![], 204);
+    let exporter = exporter(&server).with_batch_size(2);
+
+    let aggregates = (0..5)
+        .map(|i| aggregate(&format!("s{}", i), 1, 1))
+        .collect::<Vec<_>>();
+    exporter.export(&aggregates).unwrap();
+
+    let requests = server.requests();
+    let sizes = requests
+        .iter()
+        .map(|r| r.text().lines().count())
+        .collect::<Vec<_>>();
+    assert_eq!(sizes, vec![2, 2, 1]);
+}
+
+#[test]
+fn retries_failed_batches() {
+    let server = MockServer::start(vec![503, 500], 204);
+    let exporter = exporter(&server);
+
+    exporter.export(&[aggregate("public", 1, 1)]).unwrap();
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn writes_raw_records() {
+    let server = MockServer::start(vec![], 204);
+    let collector = GreptimeRecordCollector::new(Arc::new(exporter(&server)));
+
+    collector.on_write(
+        MeterRecord::new("greptime".to_string(), "public".to_string(), 5, 1)
+            .with_label("region", "us-east")
+            .with_timestamp_millis(1000),
+    );
+    collector.on_read(
+        MeterRecord::new("greptime".to_string(), "public".to_string(), 2, 0)
+            .with_timestamp_millis(2000),
+    );
+    assert_eq!(collector.buffered(), 2);
+
+    collector.report(&ctx());
+    assert_eq!(collector.buffered(), 0);
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].text().lines().collect::<Vec<_>>(),
+        vec![
+            "greptime_meter_records,catalog=greptime,schema=public,source=1,kind=write,region=us-east value=5u 1000",
+            "greptime_meter_records,catalog=greptime,schema=public,source=0,kind=read value=2u 2000",
+        ]
+    );
+}
+
+#[test]
+fn keeps_records_of_failed_writes() {
+    // Every attempt of the first report fails.
+    let server = MockServer::start(vec![500, 500, 500], 204);
+    let collector = GreptimeRecordCollector::new(Arc::new(exporter(&server)));
+
+    collector.on_write(
+        MeterRecord::new("greptime".to_string(), "public".to_string(), 5, 0)
+            .with_timestamp_millis(1000),
+    );
+    collector.report(&ctx());
+    assert_eq!(collector.buffered(), 1);
+
+    collector.report(&ctx());
+    assert_eq!(collector.buffered(), 0);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn bounds_the_record_buffer() {
+    let server = MockServer::start(vec![], 204);
+    let collector = GreptimeRecordCollector::new(Arc::new(exporter(&server))).with_max_buffered(2);
+
+    for i in 0..5 {
+        collector.on_write(
+            MeterRecord::new("greptime".to_string(), "public".to_string(), i, 0)
+                .with_timestamp_millis(i),
+        );
+    }
+    assert_eq!(collector.buffered(), 2);
+    assert_eq!(collector.dropped(), 3);
+
+    collector.report(&ctx());
+    let requests = server.requests();
+    assert!(requests[0].text().starts_with(
+        "greptime_meter_records,catalog=greptime,schema=public,source=0,kind=write value=3u 3"
+    ));
+}
+
+#[test]
+fn drops_rejected_batches_and_keeps_going() {
+    // The first batch is malformed, the second one goes through.
+    let server = MockServer::start(vec![400], 204);
+    let exporter = exporter(&server).with_batch_size(1);
+    let collector = GreptimeRecordCollector::new(Arc::new(exporter));
+
+    for value in [1, 2] {
+        collector.on_write(
+            MeterRecord::new("greptime".to_string(), "public".to_string(), value, 0)
+                .with_timestamp_millis(value),
+        );
+    }
+    collector.report(&ctx());
+
+    assert_eq!(collector.buffered(), 0);
+    assert_eq!(collector.rejected(), 1);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].text().contains("value=2u"));
+
+    // Nothing is sent again on the next run.
+    collector.report(&ctx());
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn escapes_line_breaks_and_backslashes() {
+    let exporter = GreptimeExporter::new("http://localhost:4000", "public");
+    let record = MeterRecord::new("greptime".to_string(), "a\\b".to_string(), 1, 0)
+        .with_label("user", "alice\nevil,catalog=other value=1u 0")
+        .with_timestamp_millis(0);
+
+    let line = exporter.record_line(MeterKind::Write, &record);
+    assert_eq!(line.lines().count(), 1);
+    assert_eq!(
+        line,
+        "greptime_meter_records,catalog=greptime,schema=a\\\\b,source=0,kind=write,\
+         user=alice\\nevil\\,catalog\\=other\\ value\\=1u\\ 0 value=1u 0"
+    );
+}
+
+#[test]
+fn prefixes_labels_named_like_reserved_tags() {
+    let exporter = GreptimeExporter::new("http://localhost:4000", "public");
+    let mut record = MeterRecord::new("greptime".to_string(), "public".to_string(), 1, 0)
+        .with_timestamp_millis(0);
+    for tag in ["catalog", "schema", "source", "kind"] {
+        record = record.with_label(tag, "label");
+    }
+
+    assert_eq!(
+        exporter.record_line(MeterKind::Read, &record),
+        "greptime_meter_records,catalog=greptime,schema=public,source=0,kind=read,\
+         label_catalog=label,label_kind=label,label_schema=label,label_source=label value=1u 0"
+    );
+}
+
+#[test]
+fn encodes_the_database_name() {
+    let exporter = GreptimeExporter::new("http://localhost:4000/", "my db&precision=s");
+    assert_eq!(
+        exporter.url(),
+        "http://localhost:4000/v1/influxdb/write?db=my%20db%26precision%3Ds&precision=ms"
+    );
+}
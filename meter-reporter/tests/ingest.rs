@@ -0,0 +1,286 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::io::Read;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::ctx;
+use common::MockServer;
+use meter_core::collect::Collect;
+use meter_core::data::MeterKind;
+use meter_core::data::MeterRecord;
+use meter_core::dedup::DedupCollector;
+use meter_core::dedup::Deduplicator;
+use meter_core::id::RecordId;
+use meter_core::window::GroupKey;
+use meter_core::window::WindowConfig;
+use meter_core::window::WindowedCollector;
+use meter_reporter::ingest::IngestBatch;
+use meter_reporter::ingest::IngestExporter;
+use meter_reporter::ingest::IngestRecord;
+use meter_reporter::ingest::IngestServer;
+use meter_reporter::ingest::CONTENT_TYPE;
+use meter_reporter::report::Report;
+use meter_reporter::retry::RetryPolicy;
+use prost::Message;
+
+fn record(schema: &str, value: u64, timestamp_millis: u64) -> MeterRecord {
+    MeterRecord::new("greptime".to_string(), schema.to_string(), value, 0)
+        .with_timestamp_millis(timestamp_millis)
+}
+
+fn aggregator() -> (Arc<WindowedCollector>, IngestServer) {
+    // Nodes ship at their own pace, so records of other nodes arrive late.
+    let config = WindowConfig::tumbling(Duration::from_secs(1))
+        .with_allowed_lateness(Duration::from_secs(10));
+    let collector = Arc::new(WindowedCollector::new(config));
+    let server = IngestServer::start("127.0.0.1:0", collector.clone()).unwrap();
+    (collector, server)
+}
+
+fn post(server: &IngestServer, path: &str, body: &[u8]) -> (u16, String) {
+    let mut response = ureq::post(format!("http://{}{}", server.local_addr(), path))
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .header("Content-Type", CONTENT_TYPE)
+        .send(body)
+        .unwrap();
+    let status = response.status().as_u16();
+    let mut body = String::new();
+    response
+        .body_mut()
+        .as_reader()
+        .read_to_string(&mut body)
+        .unwrap();
+    (status, body)
+}
+
+#[test]
+fn aggregates_records_of_several_nodes() {
+    let (collector, server) = aggregator();
+    let endpoint = format!("http://{}", server.local_addr());
+    let nodes = [
+        IngestExporter::new(&endpoint, 1),
+        IngestExporter::new(&endpoint, 2).with_batch_size(1),
+    ];
+
+    for node in &nodes {
+        node.on_write(record("public", 10, 100));
+        node.on_read(record("public", 3, 200));
+        node.on_write(record("metrics", 1, 1_100));
+        node.report(&ctx());
+        assert_eq!(node.buffered(), 0);
+    }
+    assert_eq!(server.received(), 6);
+
+    let aggregates = collector.flush();
+    let find = |schema: &str, start_millis: u64| {
+        aggregates
+            .iter()
+            .find(|a| {
+                a.key == GroupKey::new("greptime", schema, 0)
+                    && a.window.start_millis == start_millis
+            })
+            .unwrap()
+            .usage
+    };
+    let public = find("public", 0);
+    assert_eq!(public.write_units, 20);
+    assert_eq!(public.read_units, 6);
+    assert_eq!(public.write_count, 2);
+    assert_eq!(find("metrics", 1_000).write_units, 2);
+}
+
+#[test]
+fn keeps_labels_and_id() {
+    let id = RecordId::new(1_000, 7, 1, 42);
+    let original = record("public", 10, 100)
+        .with_label("table", "cpu")
+        .with_id(id);
+
+    let encoded = IngestBatch {
+        records: vec![IngestRecord::new(MeterKind::Read, &original)],
+    }
+    .encode_to_vec();
+    let mut decoded = IngestBatch::decode(encoded.as_slice()).unwrap();
+    let (kind, record) = decoded.records.remove(0).into_record().unwrap();
+
+    assert_eq!(kind, MeterKind::Read);
+    assert_eq!(record.catalog, "greptime");
+    assert_eq!(record.schema, "public");
+    assert_eq!(record.value, 10);
+    assert_eq!(record.timestamp_millis, 100);
+    assert_eq!(record.labels, original.labels);
+    assert_eq!(record.id, Some(id));
+}
+
+#[test]
+fn rejects_invalid_batches_as_a_whole() {
+    let (collector, server) = aggregator();
+
+    let valid = IngestRecord::new(MeterKind::Write, &record("public", 10, 100));
+    let mut invalid = valid.clone();
+    invalid.source = 300;
+    let body = IngestBatch {
+        records: vec![valid, invalid],
+    }
+    .encode_to_vec();
+
+    let (status, message) = post(&server, "/v1/meter/records", &body);
+    assert_eq!(status, 400);
+    assert_eq!(message, "source 300 out of range");
+    assert_eq!(post(&server, "/v1/meter/records", b"\xff\xff").0, 400);
+    assert_eq!(post(&server, "/metrics", &[]).0, 404);
+
+    assert_eq!(server.received(), 0);
+    assert!(collector.flush().is_empty());
+}
+
+#[test]
+fn keeps_records_until_the_aggregator_is_up() {
+    // Reserve a port, then free it for the aggregator to start on later.
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let exporter = IngestExporter::new(format!("http://{}", addr), 1)
+        .with_retry(RetryPolicy::none())
+        .with_max_buffered(2);
+
+    exporter.on_write(record("public", 1, 100));
+    exporter.on_write(record("public", 10, 100));
+    exporter.report(&ctx());
+    assert_eq!(exporter.buffered(), 2);
+
+    exporter.on_write(record("public", 100, 100));
+    assert_eq!(exporter.buffered(), 2);
+    assert_eq!(exporter.dropped(), 1);
+
+    let collector = Arc::new(WindowedCollector::new(WindowConfig::tumbling(
+        Duration::from_secs(1),
+    )));
+    let server = IngestServer::start(addr, collector.clone()).unwrap();
+    exporter.report(&ctx());
+    assert_eq!(exporter.buffered(), 0);
+    assert_eq!(server.received(), 2);
+    assert_eq!(collector.flush()[0].usage.write_units, 110);
+}
+
+#[test]
+fn drops_rejected_batches() {
+    let server = MockServer::start(vec![413], 204);
+    let exporter = IngestExporter::new(&server.endpoint, 1).with_batch_size(1);
+
+    exporter.on_write(record("public", 1, 100));
+    exporter.on_write(record("public", 10, 100));
+    exporter.report(&ctx());
+
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(exporter.buffered(), 0);
+    assert_eq!(exporter.rejected(), 1);
+}
+
+#[test]
+fn splits_batches_too_large_for_the_server() {
+    let server = MockServer::start(vec![413, 413], 204);
+    let exporter = IngestExporter::new(&server.endpoint, 1);
+
+    for value in 0..4 {
+        exporter.on_write(record("public", value, 100));
+    }
+    exporter.report(&ctx());
+
+    // The whole batch and then its first half are too large, so both are split.
+    let sizes = server
+        .requests()
+        .iter()
+        .map(|r| {
+            IngestBatch::decode(r.body.as_slice())
+                .unwrap()
+                .records
+                .len()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(sizes, vec![4, 2, 1, 1, 2]);
+    assert_eq!(exporter.buffered(), 0);
+    assert_eq!(exporter.rejected(), 0);
+}
+
+#[test]
+fn records_of_several_nodes_are_not_duplicates() {
+    let collector = Arc::new(WindowedCollector::new(WindowConfig::tumbling(
+        Duration::from_secs(1),
+    )));
+    let dedup = Arc::new(DedupCollector::new(
+        Deduplicator::new(Duration::from_secs(600), 1_000),
+        collector.clone(),
+    ));
+    let server = IngestServer::start("127.0.0.1:0", dedup.clone()).unwrap();
+    let endpoint = format!("http://{}", server.local_addr());
+
+    // Both nodes assign their first IDs at the same timestamp.
+    for node_id in [1, 2] {
+        let exporter = IngestExporter::new(&endpoint, node_id);
+        exporter.on_write(record("public", 10, 100));
+        exporter.report(&ctx());
+    }
+
+    assert_eq!(server.received(), 2);
+    assert_eq!(dedup.deduplicator().duplicates(), 0);
+    assert_eq!(collector.flush()[0].usage.write_units, 20);
+}
+
+#[test]
+fn retried_batches_are_counted_once_behind_dedup() {
+    // The first attempt fails after the server may have processed it.
+    let server = MockServer::start(vec![503], 204);
+    let exporter = IngestExporter::new(&server.endpoint, 1).with_retry(
+        RetryPolicy::default()
+            .with_max_retries(1)
+            .with_initial_backoff(Duration::from_millis(10)),
+    );
+    exporter.on_write(record("public", 10, 100));
+    exporter.report(&ctx());
+
+    let collector = Arc::new(WindowedCollector::new(WindowConfig::tumbling(
+        Duration::from_secs(1),
+    )));
+    let dedup = DedupCollector::new(
+        Deduplicator::new(Duration::from_secs(600), 1_000),
+        collector.clone(),
+    );
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    for request in requests {
+        for record in IngestBatch::decode(request.body.as_slice())
+            .unwrap()
+            .records
+        {
+            let (kind, record) = record.into_record().unwrap();
+            assert!(record.id.is_some());
+            match kind {
+                MeterKind::Write => dedup.on_write(record),
+                MeterKind::Read => dedup.on_read(record),
+            }
+        }
+    }
+
+    assert_eq!(collector.flush()[0].usage.write_units, 10);
+    assert_eq!(dedup.deduplicator().duplicates(), 1);
+}
@@ -0,0 +1,179 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::time::Duration;
+
+use common::aggregate;
+use common::AggregateExt;
+use common::MockServer;
+use meter_core::window::WindowAggregate;
+use meter_reporter::error::Error;
+use meter_reporter::export::Export;
+use meter_reporter::otlp::OtlpExporter;
+use meter_reporter::retry::RetryPolicy;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::common::v1::KeyValue;
+use opentelemetry_proto::tonic::metrics::v1::metric::Data;
+use opentelemetry_proto::tonic::metrics::v1::number_data_point;
+use opentelemetry_proto::tonic::metrics::v1::AggregationTemporality;
+use prost::Message;
+
+fn aggregates() -> Vec<WindowAggregate> {
+    vec![aggregate("public", 7, 11).with_source(2)]
+}
+
+/// The decoded requests received by `server`.
+fn requests(server: &MockServer) -> Vec<ExportMetricsServiceRequest> {
+    server
+        .requests()
+        .iter()
+        .map(|r| ExportMetricsServiceRequest::decode(r.body.as_slice()).unwrap())
+        .collect()
+}
+
+fn retry() -> RetryPolicy {
+    RetryPolicy::default()
+        .with_max_retries(3)
+        .with_initial_backoff(Duration::from_millis(10))
+}
+
+fn attribute<'a>(attributes: &'a [KeyValue], key: &str) -> &'a Value {
+    attributes
+        .iter()
+        .find(|kv| kv.key == key)
+        .and_then(|kv| kv.value.as_ref())
+        .and_then(|v| v.value.as_ref())
+        .unwrap_or_else(|| panic!("missing attribute {}", key))
+}
+
+#[test]
+fn exports_sums_with_attributes() {
+    let receiver = MockServer::start(vec![], 200);
+    let exporter = OtlpExporter::new(&receiver.endpoint)
+        .with_node("datanode-0")
+        .with_cluster("test")
+        .with_retry(retry());
+
+    exporter.export(&aggregates()).unwrap();
+
+    assert_eq!(receiver.requests().len(), 1);
+    assert_eq!(receiver.requests()[0].url, "/v1/metrics");
+    let request = &requests(&receiver)[0];
+
+    let resource_metrics = &request.resource_metrics[0];
+    let resource = resource_metrics.resource.as_ref().unwrap();
+    assert_eq!(
+        attribute(&resource.attributes, "node"),
+        &Value::StringValue("datanode-0".to_string())
+    );
+    assert_eq!(
+        attribute(&resource.attributes, "cluster"),
+        &Value::StringValue("test".to_string())
+    );
+
+    let metrics = &resource_metrics.scope_metrics[0].metrics;
+    let read_units = metrics
+        .iter()
+        .find(|m| m.name == "greptime.meter.read_units")
+        .unwrap();
+    let Some(Data::Sum(sum)) = &read_units.data else {
+        panic!("read units is not a sum");
+    };
+    assert!(sum.is_monotonic);
+    assert_eq!(
+        sum.aggregation_temporality,
+        AggregationTemporality::Delta as i32
+    );
+
+    let point = &sum.data_points[0];
+    assert_eq!(point.start_time_unix_nano, 60_000_000_000);
+    assert_eq!(point.time_unix_nano, 120_000_000_000);
+    assert_eq!(point.value, Some(number_data_point::Value::AsInt(7)));
+    assert_eq!(
+        attribute(&point.attributes, "catalog"),
+        &Value::StringValue("greptime".to_string())
+    );
+    assert_eq!(
+        attribute(&point.attributes, "schema"),
+        &Value::StringValue("public".to_string())
+    );
+    assert_eq!(attribute(&point.attributes, "source"), &Value::IntValue(2));
+
+    let write_units = metrics
+        .iter()
+        .find(|m| m.name == "greptime.meter.write_units")
+        .unwrap();
+    let Some(Data::Sum(sum)) = &write_units.data else {
+        panic!("write units is not a sum");
+    };
+    assert_eq!(
+        sum.data_points[0].value,
+        Some(number_data_point::Value::AsInt(11))
+    );
+}
+
+#[test]
+fn clamps_sums_beyond_i64() {
+    let receiver = MockServer::start(vec![], 200);
+    let exporter = OtlpExporter::new(&receiver.endpoint);
+
+    exporter
+        .export(&[aggregate("public", u64::MAX, i64::MAX as u64)])
+        .unwrap();
+
+    let request = &requests(&receiver)[0];
+    let metrics = &request.resource_metrics[0].scope_metrics[0].metrics;
+    for name in ["greptime.meter.read_units", "greptime.meter.write_units"] {
+        let metric = metrics.iter().find(|m| m.name == name).unwrap();
+        let Some(Data::Sum(sum)) = &metric.data else {
+            panic!("{} is not a sum", name);
+        };
+        assert_eq!(
+            sum.data_points[0].value,
+            Some(number_data_point::Value::AsInt(i64::MAX))
+        );
+    }
+}
+
+#[test]
+fn retries_server_errors() {
+    let receiver = MockServer::start(vec![503, 429], 200);
+    let exporter = OtlpExporter::new(&receiver.endpoint).with_retry(retry());
+
+    exporter.export(&aggregates()).unwrap();
+    assert_eq!(receiver.requests().len(), 3);
+}
+
+#[test]
+fn gives_up_after_max_retries() {
+    let receiver = MockServer::start(vec![500; 10], 200);
+    let exporter = OtlpExporter::new(&receiver.endpoint).with_retry(retry());
+
+    let err = exporter.export(&aggregates()).unwrap_err();
+    assert!(matches!(err, Error::Http { status: 500, .. }));
+    assert_eq!(receiver.requests().len(), 4);
+}
+
+#[test]
+fn does_not_retry_client_errors() {
+    let receiver = MockServer::start(vec![400], 200);
+    let exporter = OtlpExporter::new(&receiver.endpoint).with_retry(retry());
+
+    let err = exporter.export(&aggregates()).unwrap_err();
+    assert!(matches!(err, Error::Http { status: 400, .. }));
+    assert_eq!(receiver.requests().len(), 1);
+}
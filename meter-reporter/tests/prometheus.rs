@@ -0,0 +1,146 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use common::aggregate;
+use common::AggregateExt;
+use meter_reporter::export::Export;
+use meter_reporter::prometheus::PrometheusExporter;
+use prometheus_parse::Scrape;
+use prometheus_parse::Value;
+
+fn parse(text: &str) -> Scrape {
+    Scrape::parse(text.lines().map(|l| Ok(l.to_string()))).unwrap()
+}
+
+fn counter(scrape: &Scrape, name: &str, catalog: &str, schema: &str) -> f64 {
+    let sample = scrape
+        .samples
+        .iter()
+        .find(|s| {
+            s.metric == name
+                && s.labels.get("catalog") == Some(catalog)
+                && s.labels.get("schema") == Some(schema)
+        })
+        .unwrap_or_else(|| panic!("missing {} for {}.{}", name, catalog, schema));
+    match sample.value {
+        Value::Counter(v) => v,
+        ref v => panic!("{} is not a counter: {:?}", name, v),
+    }
+}
+
+#[test]
+fn renders_cumulative_counters() {
+    let exporter = PrometheusExporter::default();
+    exporter
+        .export(&[
+            aggregate("public", 10, 20),
+            aggregate("other", 1, 0).with_source(1),
+        ])
+        .unwrap();
+    exporter.export(&[aggregate("public", 5, 0)]).unwrap();
+
+    let scrape = parse(&exporter.render());
+    assert_eq!(
+        counter(
+            &scrape,
+            "greptime_meter_read_units_total",
+            "greptime",
+            "public"
+        ),
+        15.0
+    );
+    assert_eq!(
+        counter(
+            &scrape,
+            "greptime_meter_write_units_total",
+            "greptime",
+            "public"
+        ),
+        20.0
+    );
+    assert_eq!(
+        counter(
+            &scrape,
+            "greptime_meter_read_requests_total",
+            "greptime",
+            "public"
+        ),
+        2.0
+    );
+    assert_eq!(
+        counter(
+            &scrape,
+            "greptime_meter_read_units_total",
+            "greptime",
+            "other"
+        ),
+        1.0
+    );
+
+    let other = scrape
+        .samples
+        .iter()
+        .find(|s| s.labels.get("schema") == Some("other"))
+        .unwrap();
+    assert_eq!(other.labels.get("source"), Some("1"));
+}
+
+#[test]
+fn escapes_label_values() {
+    let exporter = PrometheusExporter::new("test");
+    exporter
+        .export(&[aggregate("c\\d", 1, 1).with_catalog("a\"b")])
+        .unwrap();
+
+    let text = exporter.render();
+    assert!(text.contains(r#"catalog="a\"b",schema="c\\d""#));
+    assert_eq!(parse(&text).samples.len(), 4);
+}
+
+#[test]
+fn serves_metrics_endpoint() {
+    let exporter = Arc::new(PrometheusExporter::default());
+    exporter.export(&[aggregate("public", 3, 4)]).unwrap();
+    let server = exporter.serve("127.0.0.1:0").unwrap();
+
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"));
+    assert!(head.contains("text/plain; version=0.0.4"));
+    let scrape = parse(body);
+    assert_eq!(
+        counter(
+            &scrape,
+            "greptime_meter_write_units_total",
+            "greptime",
+            "public"
+        ),
+        4.0
+    );
+
+    drop(server);
+}
@@ -0,0 +1,240 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use chrono_tz::Tz;
+use common::aggregate;
+use common::AggregateExt;
+use meter_reporter::export::Export;
+use meter_reporter::rollup::write_csv;
+use meter_reporter::rollup::write_json;
+use meter_reporter::rollup::BillingPeriod;
+use meter_reporter::rollup::BillingRollup;
+use meter_reporter::rollup::InvoiceLineItem;
+
+const HOUR_MILLIS: u64 = 3600 * 1000;
+
+/// 2024-01-31T16:00:00Z, midnight of February 1st in Shanghai.
+const FEB_1_SHANGHAI: u64 = 1_706_716_800_000;
+
+fn write_units(items: &[InvoiceLineItem]) -> Vec<(&str, &str, u64)> {
+    items
+        .iter()
+        .filter(|item| item.meter == "write_units")
+        .map(|item| (item.tenant.as_str(), item.period.as_str(), item.quantity))
+        .collect()
+}
+
+#[test]
+fn periods_follow_time_zone() {
+    let rollup = BillingRollup::new(Tz::Asia__Shanghai);
+    rollup
+        .export(&[
+            aggregate("public", 0, 1).with_window(FEB_1_SHANGHAI - 5_000, FEB_1_SHANGHAI),
+            aggregate("public", 0, 10).with_window(FEB_1_SHANGHAI, FEB_1_SHANGHAI + 5_000),
+            aggregate("metrics", 0, 100).with_window(
+                FEB_1_SHANGHAI + HOUR_MILLIS,
+                FEB_1_SHANGHAI + HOUR_MILLIS + 5_000,
+            ),
+            aggregate("public", 0, 1000)
+                .with_catalog("tenant")
+                .with_window(FEB_1_SHANGHAI, FEB_1_SHANGHAI + 5_000),
+        ])
+        .unwrap();
+
+    let daily = rollup.line_items(BillingPeriod::Daily);
+    assert_eq!(
+        write_units(&daily),
+        vec![
+            ("greptime", "2024-01-31", 1),
+            ("greptime", "2024-02-01", 110),
+            ("tenant", "2024-02-01", 1000),
+        ]
+    );
+    let february = daily
+        .iter()
+        .find(|item| item.period == "2024-02-01")
+        .unwrap();
+    assert_eq!(february.period_start, FEB_1_SHANGHAI);
+    assert_eq!(february.period_end, FEB_1_SHANGHAI + 24 * HOUR_MILLIS);
+    assert_eq!(february.unit, "unit");
+
+    let monthly = rollup.line_items(BillingPeriod::Monthly);
+    assert_eq!(
+        write_units(&monthly),
+        vec![
+            ("greptime", "2024-01", 1),
+            ("greptime", "2024-02", 110),
+            ("tenant", "2024-02", 1000),
+        ]
+    );
+    let february = monthly
+        .iter()
+        .find(|item| item.period == "2024-02")
+        .unwrap();
+    assert_eq!(february.period_start, FEB_1_SHANGHAI);
+    assert_eq!(february.period_end, FEB_1_SHANGHAI + 29 * 24 * HOUR_MILLIS);
+
+    let requests = monthly
+        .iter()
+        .find(|item| item.period == "2024-02" && item.meter == "write_requests")
+        .unwrap();
+    assert_eq!(requests.quantity, 2);
+    assert_eq!(requests.unit, "request");
+    // Nothing was read.
+    assert!(monthly.iter().all(|item| !item.meter.starts_with("read")));
+}
+
+#[test]
+fn daylight_saving_day_is_shorter() {
+    // 2024-03-10T05:00:00Z, midnight in New York on the day clocks go forward.
+    let midnight = 1_710_046_800_000;
+    let rollup = BillingRollup::new(Tz::America__New_York);
+    rollup
+        .export(&[aggregate("public", 0, 1)
+            .with_window(midnight + HOUR_MILLIS, midnight + HOUR_MILLIS + 5_000)])
+        .unwrap();
+
+    let daily = rollup.line_items(BillingPeriod::Daily);
+    assert_eq!(daily[0].period, "2024-03-10");
+    assert_eq!(daily[0].period_start, midnight);
+    assert_eq!(daily[0].period_end, midnight + 23 * HOUR_MILLIS);
+}
+
+#[test]
+fn drains_closed_periods() {
+    let rollup = BillingRollup::new(Tz::Asia__Shanghai);
+    rollup
+        .export(&[
+            aggregate("public", 0, 1).with_window(FEB_1_SHANGHAI - 5_000, FEB_1_SHANGHAI),
+            aggregate("public", 0, 10).with_window(FEB_1_SHANGHAI, FEB_1_SHANGHAI + 5_000),
+        ])
+        .unwrap();
+
+    let closed = rollup.drain_closed(BillingPeriod::Daily, FEB_1_SHANGHAI + HOUR_MILLIS);
+    assert_eq!(write_units(&closed), vec![("greptime", "2024-01-31", 1)]);
+    assert!(rollup
+        .drain_closed(BillingPeriod::Daily, FEB_1_SHANGHAI + HOUR_MILLIS)
+        .is_empty());
+    assert_eq!(
+        write_units(&rollup.line_items(BillingPeriod::Daily)),
+        vec![("greptime", "2024-02-01", 10)]
+    );
+
+    // Monthly totals are kept apart from the daily ones.
+    let closed = rollup.drain_closed(BillingPeriod::Monthly, FEB_1_SHANGHAI);
+    assert_eq!(write_units(&closed), vec![("greptime", "2024-01", 1)]);
+    assert_eq!(
+        write_units(&rollup.line_items(BillingPeriod::Monthly)),
+        vec![("greptime", "2024-02", 10)]
+    );
+}
+
+#[test]
+fn writes_csv_and_json() {
+    let rollup = BillingRollup::new(Tz::UTC);
+    rollup
+        .export(&[aggregate("public", 0, 7).with_window(0, 5_000)])
+        .unwrap();
+    let items = rollup.line_items(BillingPeriod::Daily);
+
+    let mut csv = vec![];
+    write_csv(&items, &mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "tenant,period,period_start,period_end,meter,quantity,unit,adjustment\n\
+         greptime,1970-01-01,0,86400000,write_units,7,unit,false\n\
+         greptime,1970-01-01,0,86400000,write_requests,1,request,false\n"
+    );
+
+    let mut json = vec![];
+    write_json(&items, &mut json).unwrap();
+    let decoded: Vec<InvoiceLineItem> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(decoded, items);
+}
+
+#[test]
+fn late_aggregates_of_drained_periods_become_adjustments() {
+    let rollup = BillingRollup::new(Tz::Asia__Shanghai);
+    let late = || {
+        rollup
+            .export(
+                &[aggregate("public", 0, 5).with_window(FEB_1_SHANGHAI - 5_000, FEB_1_SHANGHAI)],
+            )
+            .unwrap()
+    };
+
+    late();
+    let closed = rollup.drain_closed(BillingPeriod::Daily, FEB_1_SHANGHAI + HOUR_MILLIS);
+    assert_eq!(write_units(&closed), vec![("greptime", "2024-01-31", 5)]);
+    assert!(closed.iter().all(|item| !item.adjustment));
+
+    // The drained day is not invoiced again as a regular line item.
+    late();
+    assert_eq!(
+        write_units(&rollup.line_items(BillingPeriod::Daily)),
+        vec![("greptime", "2024-01-31", 5)]
+    );
+    let closed = rollup.drain_closed(BillingPeriod::Daily, FEB_1_SHANGHAI + HOUR_MILLIS);
+    assert_eq!(write_units(&closed), vec![("greptime", "2024-01-31", 5)]);
+    assert!(closed.iter().all(|item| item.adjustment));
+    assert!(rollup
+        .drain_closed(BillingPeriod::Daily, FEB_1_SHANGHAI + HOUR_MILLIS)
+        .is_empty());
+
+    // The month is still running, so it keeps both.
+    assert_eq!(
+        write_units(&rollup.line_items(BillingPeriod::Monthly)),
+        vec![("greptime", "2024-01", 10)]
+    );
+}
+
+#[test]
+fn restores_drained_periods_after_restart() {
+    let rollup = BillingRollup::new(Tz::Asia__Shanghai);
+    assert!(rollup
+        .drain_closed(BillingPeriod::Daily, FEB_1_SHANGHAI + HOUR_MILLIS)
+        .is_empty());
+
+    rollup
+        .export(&[aggregate("public", 0, 5).with_window(FEB_1_SHANGHAI - 5_000, FEB_1_SHANGHAI)])
+        .unwrap();
+    let items = rollup.line_items(BillingPeriod::Daily);
+    assert_eq!(write_units(&items), vec![("greptime", "2024-01-31", 5)]);
+    assert!(items.iter().all(|item| item.adjustment));
+}
+
+#[test]
+fn windows_straddling_midnight_are_billed_to_their_start() {
+    // 2024-01-31T18:00:00Z, 23:30 in Kolkata.
+    let start = FEB_1_SHANGHAI + 2 * HOUR_MILLIS;
+    let rollup = BillingRollup::new(Tz::Asia__Kolkata);
+    rollup
+        .export(&[
+            // An hourly window ends at 00:30 on February 1st.
+            aggregate("public", 0, 1).with_window(start, start + HOUR_MILLIS),
+            // Half-hour windows align with the local midnight.
+            aggregate("public", 0, 10).with_window(start + HOUR_MILLIS / 2, start + HOUR_MILLIS),
+        ])
+        .unwrap();
+
+    assert_eq!(
+        write_units(&rollup.line_items(BillingPeriod::Daily)),
+        vec![
+            ("greptime", "2024-01-31", 1),
+            ("greptime", "2024-02-01", 10)
+        ]
+    );
+}
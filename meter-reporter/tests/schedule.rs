@@ -0,0 +1,131 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use meter_reporter::report::Report;
+use meter_reporter::report::ReportContext;
+use meter_reporter::schedule::Schedule;
+use meter_reporter::schedule::ThreadScheduler;
+use parking_lot::Mutex;
+
+/// A [Report] keeping the context of every run, panicking on the runs in
+/// `panic_on`.
+#[derive(Default)]
+struct Recording {
+    runs: Mutex<Vec<ReportContext>>,
+    panic_on: Vec<usize>,
+}
+
+impl Report for Recording {
+    fn report(&self, ctx: &ReportContext) {
+        let mut runs = self.runs.lock();
+        runs.push(*ctx);
+        if self.panic_on.contains(&(runs.len() - 1)) {
+            drop(runs);
+            panic!("reporter failed");
+        }
+    }
+}
+
+/// Runs `reporter` on `schedule` until it ran `runs` times, then shuts down.
+fn run_until(schedule: Schedule, reporter: Arc<Recording>, runs: usize) -> Vec<ReportContext> {
+    let handle = ThreadScheduler::new(schedule, reporter.clone())
+        .start()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while reporter.runs.lock().len() < runs && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    handle.shutdown();
+    let runs = reporter.runs.lock().clone();
+    runs
+}
+
+#[test]
+fn aligned_runs_are_on_multiples_of_the_interval() {
+    let schedule = Schedule::every(Duration::from_secs(60)).aligned();
+    assert_eq!(schedule.next_run(0), 60_000);
+    assert_eq!(schedule.next_run(59_999), 60_000);
+    assert_eq!(schedule.next_run(60_000), 120_000);
+    assert_eq!(schedule.next_run(61_234), 120_000);
+}
+
+#[test]
+fn unaligned_runs_follow_the_previous_one() {
+    let schedule = Schedule::every(Duration::from_secs(60));
+    assert_eq!(schedule.next_run(0), 60_000);
+    assert_eq!(schedule.next_run(61_234), 121_234);
+}
+
+#[test]
+fn jitter_stays_below_its_bound() {
+    let schedule = Schedule::every(Duration::from_secs(1)).with_jitter(Duration::from_millis(50));
+    let samples = (0..1_000)
+        .map(|_| schedule.sample_jitter())
+        .collect::<Vec<_>>();
+    assert!(samples
+        .iter()
+        .all(|jitter| *jitter < Duration::from_millis(50)));
+    // Not every node waits the same.
+    assert!(samples.iter().any(|jitter| *jitter != samples[0]));
+
+    let schedule = Schedule::every(Duration::from_secs(1));
+    assert_eq!(schedule.sample_jitter(), Duration::ZERO);
+}
+
+#[test]
+fn runs_periodically_and_once_more_on_shutdown() {
+    let schedule = Schedule::every(Duration::from_millis(20)).aligned();
+    let runs = run_until(schedule, Arc::default(), 3);
+
+    let (last, periodic) = runs.split_last().unwrap();
+    assert!(last.is_final);
+    assert!(periodic.len() >= 2, "only {} runs", periodic.len());
+    for ctx in periodic {
+        assert!(!ctx.is_final);
+        assert_eq!(ctx.scheduled_millis % 20, 0);
+    }
+    for pair in periodic.windows(2) {
+        assert_eq!(pair[1].scheduled_millis - pair[0].scheduled_millis, 20);
+        assert_eq!(pair[1].elapsed_millis, 20);
+    }
+}
+
+#[test]
+fn keeps_running_after_a_reporter_panics() {
+    let reporter = Arc::new(Recording {
+        panic_on: vec![0],
+        ..Default::default()
+    });
+    let runs = run_until(Schedule::every(Duration::from_millis(10)), reporter, 3);
+
+    assert!(runs.len() >= 4, "only {} runs", runs.len());
+    assert!(runs.last().unwrap().is_final);
+}
+
+#[test]
+fn dropping_the_handle_runs_the_final_report() {
+    let reporter = Arc::new(Recording::default());
+    let handle = ThreadScheduler::new(Schedule::every(Duration::from_secs(3600)), reporter.clone())
+        .start()
+        .unwrap();
+    drop(handle);
+
+    let runs = reporter.runs.lock();
+    assert_eq!(runs.len(), 1);
+    assert!(runs[0].is_final);
+}
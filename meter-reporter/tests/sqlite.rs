@@ -0,0 +1,168 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::time::Duration;
+
+use common::aggregate;
+use common::AggregateExt;
+use meter_core::window::Usage;
+use meter_reporter::error::Error;
+use meter_reporter::export::Export;
+use meter_reporter::report::ReportContext;
+use meter_reporter::sqlite::ArchivedUsage;
+use meter_reporter::sqlite::SqliteArchive;
+
+const MINUTE_MILLIS: u64 = 60_000;
+
+fn usage(schema: &str, read_units: u64, write_units: u64, count: u64) -> ArchivedUsage {
+    ArchivedUsage {
+        catalog: "greptime".to_string(),
+        schema: schema.to_string(),
+        usage: Usage {
+            read_units,
+            write_units,
+            read_count: count,
+            write_count: count,
+        },
+    }
+}
+
+#[test]
+fn sums_schemas_over_range() {
+    let archive = SqliteArchive::open_in_memory().unwrap();
+    archive
+        .export(&[
+            aggregate("public", 1, 10).with_window(0, MINUTE_MILLIS),
+            aggregate("public", 2, 20)
+                .with_source(1)
+                .with_window(0, MINUTE_MILLIS),
+            aggregate("public", 3, 30).with_window(MINUTE_MILLIS, 2 * MINUTE_MILLIS),
+            aggregate("metrics", 4, 40).with_window(MINUTE_MILLIS, 2 * MINUTE_MILLIS),
+            aggregate("public", 5, 50).with_window(2 * MINUTE_MILLIS, 3 * MINUTE_MILLIS),
+        ])
+        .unwrap();
+
+    assert_eq!(
+        archive.query(0..2 * MINUTE_MILLIS).unwrap(),
+        vec![usage("metrics", 4, 40, 1), usage("public", 6, 60, 3)]
+    );
+    assert_eq!(
+        archive.query(2 * MINUTE_MILLIS..3 * MINUTE_MILLIS).unwrap(),
+        vec![usage("public", 5, 50, 1)]
+    );
+    assert!(archive
+        .query(10 * MINUTE_MILLIS..20 * MINUTE_MILLIS)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn adds_up_same_window() {
+    let archive = SqliteArchive::open_in_memory().unwrap();
+    archive
+        .export(&[aggregate("public", 1, 10).with_window(0, MINUTE_MILLIS)])
+        .unwrap();
+    archive
+        .export(&[aggregate("public", 2, 20).with_window(0, MINUTE_MILLIS)])
+        .unwrap();
+
+    assert_eq!(
+        archive.query(0..MINUTE_MILLIS).unwrap(),
+        vec![usage("public", 3, 30, 2)]
+    );
+}
+
+#[test]
+fn keeps_data_and_schema_on_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("meter.db");
+
+    let archive = SqliteArchive::open(&path).unwrap();
+    assert_eq!(archive.schema_version().unwrap(), 2);
+    archive
+        .export(&[aggregate("public", 1, 10).with_window(0, MINUTE_MILLIS)])
+        .unwrap();
+    drop(archive);
+
+    let archive = SqliteArchive::open(&path).unwrap();
+    assert_eq!(archive.schema_version().unwrap(), 2);
+    assert_eq!(
+        archive.query(0..MINUTE_MILLIS).unwrap(),
+        vec![usage("public", 1, 10, 1)]
+    );
+}
+
+#[test]
+fn rejects_newer_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("meter.db");
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.pragma_update(None, "user_version", 99).unwrap();
+    drop(conn);
+
+    assert!(matches!(
+        SqliteArchive::open(&path),
+        Err(Error::Database(_))
+    ));
+}
+
+#[test]
+fn prunes_by_retention_on_report() {
+    let archive = SqliteArchive::open_in_memory()
+        .unwrap()
+        .with_retention(Duration::from_millis(2 * MINUTE_MILLIS));
+    archive
+        .export(&[
+            aggregate("public", 1, 10).with_window(0, MINUTE_MILLIS),
+            aggregate("public", 2, 20).with_window(MINUTE_MILLIS, 2 * MINUTE_MILLIS),
+            aggregate("public", 3, 30).with_window(2 * MINUTE_MILLIS, 3 * MINUTE_MILLIS),
+        ])
+        .unwrap();
+
+    // Only the first window ended two minutes before the report.
+    archive
+        .on_report(&ReportContext {
+            scheduled_millis: 3 * MINUTE_MILLIS,
+            elapsed_millis: MINUTE_MILLIS,
+            is_final: false,
+        })
+        .unwrap();
+    assert_eq!(
+        archive.query(0..u64::MAX).unwrap(),
+        vec![usage("public", 5, 50, 2)]
+    );
+
+    assert_eq!(archive.prune(10 * MINUTE_MILLIS).unwrap(), 2);
+    assert!(archive.query(0..u64::MAX).unwrap().is_empty());
+}
+
+#[test]
+fn busy_database_is_retryable() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.db");
+    let archive = SqliteArchive::open(&path).unwrap();
+    archive.set_busy_timeout(Duration::ZERO).unwrap();
+
+    let other = rusqlite::Connection::open(&path).unwrap();
+    other.execute_batch("BEGIN EXCLUSIVE").unwrap();
+
+    let err = archive.export(&[aggregate("public", 1, 10)]).unwrap_err();
+    assert!(matches!(err, Error::DatabaseBusy(_)), "{:?}", err);
+    assert!(err.is_retryable());
+
+    other.execute_batch("COMMIT").unwrap();
+    archive.export(&[aggregate("public", 1, 10)]).unwrap();
+}
@@ -0,0 +1,160 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use common::aggregate;
+use common::ctx;
+use common::AggregateExt;
+use meter_core::collect::Collect;
+use meter_core::data::MeterRecord;
+use meter_reporter::export::Export;
+use meter_reporter::statsd::StatsdExporter;
+use meter_reporter::statsd::StatsdFormat;
+
+fn agent() -> UdpSocket {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    socket
+}
+
+/// Receives packets until none arrives within the timeout.
+fn packets(agent: &UdpSocket) -> Vec<String> {
+    let mut packets = vec![];
+    let mut buf = [0; 65536];
+    while let Ok(n) = agent.recv(&mut buf) {
+        packets.push(String::from_utf8(buf[..n].to_vec()).unwrap());
+    }
+    packets
+}
+
+#[test]
+fn sends_aggregates_with_dogstatsd_tags() {
+    let agent = agent();
+    let exporter = StatsdExporter::new(agent.local_addr().unwrap()).unwrap();
+
+    exporter
+        .export(&[aggregate("public", 7, 0).with_source(1)])
+        .unwrap();
+
+    assert_eq!(
+        packets(&agent),
+        vec![[
+            "greptime.meter.read_units:7|c|#catalog:greptime,schema:public,source:1",
+            "greptime.meter.read_count:1|c|#catalog:greptime,schema:public,source:1",
+        ]
+        .join("\n")]
+    );
+}
+
+#[test]
+fn sends_plain_statsd() {
+    let agent = agent();
+    let exporter = StatsdExporter::new(agent.local_addr().unwrap())
+        .unwrap()
+        .with_prefix("meter")
+        .with_format(StatsdFormat::Statsd);
+
+    exporter
+        .export(&[aggregate("a:b", 0, 3).with_source(1)])
+        .unwrap();
+
+    assert_eq!(
+        packets(&agent),
+        vec!["meter.write_units.greptime.a_b.1:3|c\nmeter.write_count.greptime.a_b.1:1|c"]
+    );
+}
+
+#[test]
+fn packs_lines_up_to_the_mtu() {
+    let agent = agent();
+    let exporter = StatsdExporter::new(agent.local_addr().unwrap())
+        .unwrap()
+        .with_mtu(200);
+
+    let aggregates = (0..20)
+        .map(|i| aggregate(&format!("schema{}", i), 5, 5).with_source(1))
+        .collect::<Vec<_>>();
+    exporter.export(&aggregates).unwrap();
+
+    let packets = packets(&agent);
+    assert!(packets.len() > 1);
+    assert!(packets.iter().all(|p| p.len() <= 200));
+    let lines = packets.iter().flat_map(|p| p.lines()).count();
+    assert_eq!(lines, 20 * 4);
+}
+
+#[test]
+fn buffers_records_until_report() {
+    let agent = agent();
+    let exporter = StatsdExporter::new(agent.local_addr().unwrap())
+        .unwrap()
+        .with_mtu(150);
+
+    let record = |value| MeterRecord::new("greptime".to_string(), "public".to_string(), value, 0);
+    exporter.on_read(record(1));
+    exporter.on_write(record(2));
+    // Zero values are not sent.
+    exporter.on_write(record(0));
+    // The first two lines fill a packet, which is sent before the third.
+    exporter.on_write(record(3));
+    assert_eq!(
+        packets(&agent),
+        vec![[
+            "greptime.meter.read_units:1|c|#catalog:greptime,schema:public,source:0",
+            "greptime.meter.write_units:2|c|#catalog:greptime,schema:public,source:0",
+        ]
+        .join("\n")]
+    );
+
+    exporter.on_report(&ctx()).unwrap();
+    assert_eq!(
+        packets(&agent),
+        vec!["greptime.meter.write_units:3|c|#catalog:greptime,schema:public,source:0"]
+    );
+}
+
+#[test]
+fn packs_records_of_several_threads() {
+    let agent = agent();
+    let exporter = StatsdExporter::new(agent.local_addr().unwrap())
+        .unwrap()
+        .with_mtu(150);
+
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for value in 1..=50 {
+                    exporter.on_write(MeterRecord::new(
+                        "greptime".to_string(),
+                        "public".to_string(),
+                        value,
+                        0,
+                    ));
+                }
+            });
+        }
+    });
+    exporter.on_report(&ctx()).unwrap();
+
+    let packets = packets(&agent);
+    assert!(packets.iter().all(|p| p.len() <= 150));
+    let lines = packets.iter().flat_map(|p| p.lines()).count();
+    assert_eq!(lines, 4 * 50);
+}
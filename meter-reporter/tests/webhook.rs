@@ -0,0 +1,207 @@
+// Copyright 2024 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod common;
+
+use std::time::Duration;
+
+use common::aggregate;
+use common::AggregateExt;
+use common::MockServer;
+use meter_core::window::WindowAggregate;
+use meter_reporter::error::Error;
+use meter_reporter::export::Export;
+use meter_reporter::retry::RetryPolicy;
+use meter_reporter::webhook::Template;
+use meter_reporter::webhook::WebhookExporter;
+use serde_json::json;
+use serde_json::Value;
+
+fn aggregates() -> Vec<WindowAggregate> {
+    vec![aggregate("public", 7, 11)]
+}
+
+/// The idempotency key and JSON body of every request received by `server`.
+fn requests(server: &MockServer) -> Vec<(String, Value)> {
+    server
+        .requests()
+        .iter()
+        .map(|r| {
+            let key = r.header("Idempotency-Key").unwrap_or_default().to_string();
+            (key, serde_json::from_slice(&r.body).unwrap())
+        })
+        .collect()
+}
+
+fn url(server: &MockServer) -> String {
+    format!("{}/usage", server.endpoint)
+}
+
+fn retry() -> RetryPolicy {
+    RetryPolicy::default()
+        .with_max_retries(2)
+        .with_initial_backoff(Duration::from_millis(10))
+        .with_jitter(true)
+}
+
+#[test]
+fn posts_templated_body() {
+    let server = MockServer::start(vec![], 200);
+    let exporter = WebhookExporter::new(url(&server))
+        .with_body_template(Template::new(json!({
+            "batch": "{{idempotency_key}}",
+            "events": "{{items}}",
+        })))
+        .with_item_template(
+            Template::parse(
+                r#"{"customer": "{{catalog}}/{{schema}}", "units": "{{read_units}}", "start": "{{window_start}}"}"#,
+            )
+            .unwrap(),
+        );
+
+    exporter.export(&aggregates()).unwrap();
+
+    let requests = requests(&server);
+    assert_eq!(requests.len(), 1);
+    let (key, body) = &requests[0];
+    assert_eq!(body["batch"], json!(key));
+    assert_eq!(
+        body["events"],
+        json!([{"customer": "greptime/public", "units": 7, "start": 60000}])
+    );
+}
+
+#[test]
+fn keeps_idempotency_key_across_retries() {
+    let server = MockServer::start(vec![503, 502], 200);
+    let exporter = WebhookExporter::new(url(&server)).with_retry(retry());
+
+    exporter.export(&aggregates()).unwrap();
+
+    let requests = requests(&server);
+    assert_eq!(requests.len(), 3);
+    assert!(!requests[0].0.is_empty());
+    assert!(requests.iter().all(|(key, _)| *key == requests[0].0));
+
+    // The same aggregates always get the same key.
+    let (key, _) = exporter.render(&aggregates());
+    assert_eq!(key, requests[0].0);
+}
+
+#[test]
+fn fails_without_dead_letter_dir() {
+    let server = MockServer::start(vec![500; 3], 200);
+    let exporter = WebhookExporter::new(url(&server)).with_retry(retry());
+
+    let err = exporter.export(&aggregates()).unwrap_err();
+    assert!(matches!(err, Error::Http { status: 500, .. }));
+}
+
+#[test]
+fn dead_letters_and_redrives() {
+    let dir = tempfile::tempdir().unwrap();
+    // Every attempt of the export and of the first re-drive fails.
+    let server = MockServer::start(vec![500; 6], 200);
+    let exporter = WebhookExporter::new(url(&server))
+        .with_retry(retry())
+        .with_dead_letter_dir(dir.path());
+
+    exporter.export(&aggregates()).unwrap();
+    assert_eq!(requests(&server).len(), 3);
+    let letters = exporter.dead_letters().unwrap();
+    assert_eq!(letters.len(), 1);
+
+    let redrive = exporter.redrive().unwrap();
+    assert_eq!((redrive.sent, redrive.failed), (0, 1));
+    assert_eq!(exporter.dead_letters().unwrap(), letters);
+
+    let redrive = exporter.redrive().unwrap();
+    assert_eq!((redrive.sent, redrive.failed), (1, 0));
+    assert!(exporter.dead_letters().unwrap().is_empty());
+
+    let requests = requests(&server);
+    let (key, body) = requests.last().unwrap();
+    assert_eq!(key, &requests[0].0);
+    assert_eq!(body, &requests[0].1);
+}
+
+#[test]
+fn redrive_skips_unreadable_letters() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockServer::start(vec![500; 3], 200);
+    let exporter = WebhookExporter::new(url(&server))
+        .with_retry(retry())
+        .with_dead_letter_dir(dir.path());
+
+    exporter.export(&aggregates()).unwrap();
+    std::fs::write(dir.path().join("corrupt.json"), "{").unwrap();
+    assert_eq!(exporter.dead_letters().unwrap().len(), 2);
+
+    let redrive = exporter.redrive().unwrap();
+    assert_eq!((redrive.sent, redrive.failed), (1, 1));
+    assert_eq!(
+        exporter.dead_letters().unwrap(),
+        vec![dir.path().join("corrupt.json")]
+    );
+}
+
+#[test]
+fn item_keys_tell_apart_slashes_in_names() {
+    let exporter = WebhookExporter::new("http://localhost/usage");
+    let item_key = |catalog: &str, schema: &str| {
+        let (_, body) = exporter.render(&[aggregate(schema, 1, 0).with_catalog(catalog)]);
+        body["items"][0]["idempotency_key"].clone()
+    };
+
+    assert_eq!(
+        item_key("a/b", "c"),
+        json!("a%2Fb/c/0/60000-120000/1-0-1-0")
+    );
+    assert_ne!(item_key("a/b", "c"), item_key("a", "b/c"));
+}
+
+#[test]
+fn keys_depend_on_the_usage() {
+    let exporter = WebhookExporter::new("http://localhost/usage");
+
+    // A partial flush and the full window after a restart.
+    let (partial, _) = exporter.render(&[aggregate("public", 1, 0)]);
+    let (full, _) = exporter.render(&[aggregate("public", 3, 0)]);
+    assert_ne!(partial, full);
+}
+
+#[test]
+fn never_overwrites_dead_letters() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = MockServer::start(vec![], 500);
+    let exporter = WebhookExporter::new(url(&server))
+        .with_retry(RetryPolicy::none())
+        .with_dead_letter_dir(dir.path());
+
+    exporter.export(&aggregates()).unwrap();
+    exporter.export(&aggregates()).unwrap();
+
+    let (key, _) = exporter.render(&aggregates());
+    let mut letters = exporter.dead_letters().unwrap();
+    letters.sort();
+    assert_eq!(
+        letters,
+        vec![
+            dir.path().join(format!("{}.1.json", key)),
+            dir.path().join(format!("{}.json", key)),
+        ]
+    );
+    // No temporary file is left behind.
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}